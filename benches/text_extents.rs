@@ -0,0 +1,55 @@
+//! Compare measuring text with a fresh surface and context for every string, like wmfocus used
+//! to, with reusing a single `TextMeasurer`.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+
+use wmfocus::TextMeasurer;
+
+/// Number of strings to measure, about what a busy desktop needs for its hints and titles.
+const STRINGS: usize = 100;
+
+const FAMILY: &str = "monospace";
+const SIZE: f64 = 72.0;
+
+/// Measure `text` on a surface of its own like before `TextMeasurer` existed.
+fn extents_on_fresh_surface(text: &str) -> cairo::TextExtents {
+    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1024, 1024).unwrap();
+    let cr = cairo::Context::new(&surface).unwrap();
+    cr.select_font_face(FAMILY, cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+    cr.set_font_size(SIZE);
+    cr.text_extents(text).unwrap()
+}
+
+fn bench_text_extents(c: &mut Criterion) {
+    let strings: Vec<String> = (0..STRINGS).map(|i| format!("window {i}")).collect();
+
+    let mut group = c.benchmark_group("text_extents");
+    group.bench_function("fresh_surface", |b| {
+        b.iter(|| {
+            for text in &strings {
+                extents_on_fresh_surface(text);
+            }
+        })
+    });
+    group.bench_function("measurer", |b| {
+        b.iter(|| {
+            let mut measurer = TextMeasurer::new().unwrap();
+            for text in &strings {
+                measurer.extents_for_text(text, FAMILY, SIZE).unwrap();
+            }
+        })
+    });
+    group.bench_function("measurer_fast_metrics", |b| {
+        b.iter(|| {
+            let mut measurer = TextMeasurer::new().unwrap();
+            measurer.set_fast_metrics(true);
+            for text in &strings {
+                measurer.extents_for_text(text, FAMILY, SIZE).unwrap();
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_text_extents);
+criterion_main!(benches);
@@ -0,0 +1,130 @@
+//! Compare asking for the properties of many windows one round trip at a time with asking for
+//! all of them before waiting for any reply.
+//!
+//! This needs an X server and is skipped without `DISPLAY`. The difference grows with the latency
+//! of the connection, eg with X forwarded over SSH.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use x11rb::connection::Connection;
+use x11rb::protocol::xproto::{AtomEnum, ConnectionExt, CreateWindowAux, PropMode, WindowClass};
+use x11rb::wrapper::ConnectionExt as _;
+use x11rb::xcb_ffi::XCBConnection;
+
+use wmfocus::properties::{self, Atoms};
+
+/// Number of windows to look at, a busy desktop.
+const WINDOWS: usize = 100;
+
+/// Intern `name`, waiting for the reply.
+fn atom(conn: &XCBConnection, name: &str) -> u32 {
+    conn.intern_atom(false, name.as_bytes())
+        .unwrap()
+        .reply()
+        .unwrap()
+        .atom
+}
+
+/// Get the names of the atoms in the ATOM list `property` of `window`, waiting for every reply.
+fn atom_names(conn: &XCBConnection, window: u32, property: &str) -> Vec<String> {
+    let property = atom(conn, property);
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::ATOM, 0, u32::MAX)
+        .unwrap()
+        .reply()
+        .unwrap();
+    reply
+        .value32()
+        .into_iter()
+        .flatten()
+        .map(|atom| {
+            let name = conn.get_atom_name(atom).unwrap().reply().unwrap().name;
+            String::from_utf8_lossy(&name).into_owned()
+        })
+        .collect()
+}
+
+/// Read the same properties as `properties::get_window_properties` one window and one request at
+/// a time, interning the atoms for every window again.
+fn sequential(conn: &XCBConnection, windows: &[u32]) -> Vec<(Vec<String>, bool, bool, bool)> {
+    windows
+        .iter()
+        .map(|&window| {
+            let types = atom_names(conn, window, "_NET_WM_WINDOW_TYPE");
+            let override_redirect = conn
+                .get_window_attributes(window)
+                .unwrap()
+                .reply()
+                .unwrap()
+                .override_redirect;
+            let opacity = atom(conn, "_NET_WM_WINDOW_OPACITY");
+            let opacity = conn
+                .get_property(false, window, opacity, AtomEnum::CARDINAL, 0, 1)
+                .unwrap()
+                .reply()
+                .unwrap()
+                .value32()
+                .is_some_and(|mut values| values.next().is_some());
+            let minimized = atom_names(conn, window, "_NET_WM_STATE")
+                .iter()
+                .any(|state| state == "_NET_WM_STATE_HIDDEN");
+            (types, override_redirect, opacity, minimized)
+        })
+        .collect()
+}
+
+fn bench_window_properties(c: &mut Criterion) {
+    if std::env::var_os("DISPLAY").is_none() {
+        eprintln!("Skipping the window properties benchmark without an X server");
+        return;
+    }
+    let (conn, screen_num) = XCBConnection::connect(None).unwrap();
+    let screen = &conn.setup().roots[screen_num];
+    let atoms = Atoms::intern(&conn).unwrap();
+    let normal = atom(&conn, "_NET_WM_WINDOW_TYPE_NORMAL");
+
+    // The windows are never mapped so nothing shows up on the screen.
+    let windows: Vec<u32> = (0..WINDOWS)
+        .map(|_| {
+            let window = conn.generate_id().unwrap();
+            conn.create_window(
+                x11rb::COPY_DEPTH_FROM_PARENT,
+                window,
+                screen.root,
+                0,
+                0,
+                100,
+                100,
+                0,
+                WindowClass::INPUT_OUTPUT,
+                x11rb::COPY_FROM_PARENT,
+                &CreateWindowAux::new(),
+            )
+            .unwrap();
+            conn.change_property32(
+                PropMode::REPLACE,
+                window,
+                atoms._NET_WM_WINDOW_TYPE,
+                AtomEnum::ATOM,
+                &[normal],
+            )
+            .unwrap();
+            window
+        })
+        .collect();
+    conn.sync().unwrap();
+
+    let mut group = c.benchmark_group("window_properties");
+    group.bench_function("sequential", |b| b.iter(|| sequential(&conn, &windows)));
+    group.bench_function("batched", |b| {
+        b.iter(|| properties::get_window_properties(&conn, &atoms, &windows).unwrap())
+    });
+    group.finish();
+
+    for window in windows {
+        conn.destroy_window(window).unwrap();
+    }
+    conn.flush().unwrap();
+}
+
+criterion_group!(benches, bench_window_properties);
+criterion_main!(benches);
@@ -1,9 +1,17 @@
-use anyhow::{Context, Result};
-use clap::{Parser, ValueEnum};
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, ValueEnum};
 use css_color_parser::Color as CssColor;
 use font_loader::system_fonts;
 use log::{info, warn};
 
+use crate::config::{self, ConfigFile};
+use crate::filter::{ClassPalette, WindowFilter};
+use crate::geometry::Rect;
+use crate::template::Template;
+use crate::themes::Theme;
 use crate::utils;
 
 #[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -20,43 +28,336 @@ pub enum VerticalAlign {
     Bottom,
 }
 
-/// Load a system font.
-fn load_font(font_family: &str) -> Result<Vec<u8>> {
-    let mut font_family_property = system_fonts::FontPropertyBuilder::new()
-        .family(font_family)
-        .build();
-    let info = system_fonts::query_specific(&mut font_family_property);
-    info!("Returned effective font is: {:?}", info);
-    let (loaded_font, _) =
-        if let Some((loaded_font, index)) = system_fonts::get(&font_family_property) {
-            (loaded_font, index)
-        } else {
-            warn!("Family not found, falling back to first Monospace font");
-            let mut font_monospace_property =
-                system_fonts::FontPropertyBuilder::new().monospace().build();
-            let sysfonts = system_fonts::query_specific(&mut font_monospace_property);
-            warn!("Falling back to font '{font}'", font = sysfonts[0]);
-            let (loaded_font, index) = system_fonts::get(&font_monospace_property)
-                .context("Couldn't find suitable font")?;
-            (loaded_font, index)
-        };
-    Ok(loaded_font)
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintAnchor {
+    /// Put every hint into its window
+    Window,
+    /// Put all hints in a grid around the mouse pointer
+    Pointer,
 }
 
-/// Generate a valid `FontConfig` from `f`.
-/// `f` is expected to be in format `Mono:72`.
-fn parse_truetype_font(f: &str) -> Result<FontConfig> {
-    let mut v = f.split(':');
-    let (family, size) = (
-        v.next().context("Wrong font format")?,
-        v.next().context("Wrong font format")?,
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GeometrySource {
+    /// Place boxes relative to the window as reported by the window manager
+    Client,
+    /// Place boxes relative to the frame that the window manager drew around the window,
+    /// including its title bar
+    Frame,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LegendPosition {
+    /// Don't show a legend
+    Off,
+    /// Show the legend on the left edge of the screen
+    Left,
+    /// Show the legend on the right edge of the screen
+    Right,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontWeight {
+    Normal,
+    Bold,
+}
+
+impl From<FontWeight> for cairo::FontWeight {
+    fn from(weight: FontWeight) -> Self {
+        match weight {
+            FontWeight::Normal => cairo::FontWeight::Normal,
+            FontWeight::Bold => cairo::FontWeight::Bold,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontUnit {
+    /// Pixels
+    Px,
+    /// Points at the DPI of the screen
+    Pt,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FontSlant {
+    Normal,
+    Italic,
+    Oblique,
+}
+
+impl From<FontSlant> for cairo::FontSlant {
+    fn from(slant: FontSlant) -> Self {
+        match slant {
+            FontSlant::Normal => cairo::FontSlant::Normal,
+            FontSlant::Italic => cairo::FontSlant::Italic,
+            FontSlant::Oblique => cairo::FontSlant::Oblique,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Antialias {
+    Default,
+    None,
+    Gray,
+    Subpixel,
+}
+
+impl From<Antialias> for cairo::Antialias {
+    fn from(antialias: Antialias) -> Self {
+        match antialias {
+            Antialias::Default => cairo::Antialias::Default,
+            Antialias::None => cairo::Antialias::None,
+            Antialias::Gray => cairo::Antialias::Gray,
+            Antialias::Subpixel => cairo::Antialias::Subpixel,
+        }
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkspaceSelection {
+    /// Only the currently visible workspaces
+    Current,
+    /// All workspaces
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MonitorSelection {
+    /// All monitors
+    All,
+    /// The monitor the mouse pointer is on
+    Current,
+    /// The primary monitor as configured in RandR
+    Primary,
+    /// The monitor with this RandR output name, eg DP-1
+    Name(String),
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextDirection {
+    /// Left to right
+    Ltr,
+    /// Top to bottom with one character per row
+    Vertical,
+}
+
+/// `_NET_WM_WINDOW_TYPE`s whose windows don't get hints unless explicitly included.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WindowType {
+    Dock,
+    Toolbar,
+    Menu,
+    Splash,
+    Notification,
+    Desktop,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintLength {
+    /// All hints have the same length
+    Uniform,
+    /// Give as many windows as possible short hints
+    Shortest,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HintOrder {
+    /// Top-left to bottom-right
+    Position,
+    /// Grouped by window class
+    Class,
+    /// The order the window manager reports the windows in
+    Stacking,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Focus the window
+    Focus,
+    /// Ask the window to close itself
+    Close,
+    /// Kill the client owning the window
+    Kill,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FocusMethod {
+    /// Use ewmh if the window manager supports it and xinput otherwise
+    Auto,
+    /// Ask the window manager to activate the window via _NET_ACTIVE_WINDOW
+    Ewmh,
+    /// Set the X input focus directly
+    Xinput,
+    /// Ask the window manager over its IPC
+    Wm,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Renderer {
+    /// Give every hint its own window
+    PerWindow,
+    /// Draw all hints onto a single window spanning the screen
+    Single,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendSelection {
+    /// Wayland if there is no X11 display but a Wayland one, X11 otherwise
+    Auto,
+    /// Draw the hints with X11, which also works on Wayland compositors through XWayland
+    X11,
+    /// Draw the hints with the wlr layer-shell protocol and find windows through the wlr
+    /// foreign-toplevel-management protocol, needs the wayland feature
+    Wayland,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InputMethod {
+    /// Grab the keyboard
+    Core,
+    /// Listen for raw XInput2 key events instead of grabbing the keyboard, keys also reach the
+    /// focused window
+    Xi2,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MatchBy {
+    /// Match the characters the keys produce in the current keyboard layout
+    Keysym,
+    /// Match the physical keys that produced the hint characters when wmfocus started, even if
+    /// the layout changes later on
+    Keycode,
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrintFormat {
+    /// Only the X window id
+    Id,
+    /// The X window id, class, instance and title separated by tabs
+    Full,
+}
+
+/// Find the first available system font out of `font_families`.
+///
+/// Families that are actually installed are preferred in the given order. If none of them are
+/// installed, we let fontconfig pick a substitute and only if that fails as well, we fall back to
+/// the first monospace font. Returns the family that was found.
+///
+/// Cairo loads the font by its family itself so we never need the font data. Only the fallback
+/// paths have to read the font file as that's the only way to ask font-loader for a substitute.
+fn load_font(font_families: &[&str]) -> Result<String> {
+    for font_family in font_families {
+        let mut font_family_property = system_fonts::FontPropertyBuilder::new()
+            .family(font_family)
+            .build();
+        let info = system_fonts::query_specific(&mut font_family_property);
+        info!("Returned effective font for '{font_family}' is: {:?}", info);
+        if !info.is_empty() {
+            return Ok(font_family.to_string());
+        }
+    }
+
+    // None of the families are installed under exactly that name but they may still be aliases
+    // such as "Mono" that fontconfig knows how to substitute.
+    for font_family in font_families {
+        let font_family_property = system_fonts::FontPropertyBuilder::new()
+            .family(font_family)
+            .build();
+        if system_fonts::get(&font_family_property).is_some() {
+            info!("Using fontconfig substitute for '{font_family}'");
+            return Ok(font_family.to_string());
+        }
+    }
+
+    warn!(
+        "None of the font families {:?} were found, falling back to first Monospace font",
+        font_families
     );
+    let mut font_monospace_property = system_fonts::FontPropertyBuilder::new().monospace().build();
+    let sysfonts = system_fonts::query_specific(&mut font_monospace_property);
+    let font = sysfonts.first().context("No monospace fonts installed")?;
+    warn!("Falling back to font '{font}'");
+    Ok(font.to_string())
+}
 
-    let loaded_font = load_font(family).context("Couldn't load font")?;
+/// The file fontconfig matches for `family`, the same one font-loader reads for a substitute.
+pub(crate) fn font_file(family: &str) -> Option<PathBuf> {
+    use fontconfig::fontconfig as fc;
+    use std::ffi::{c_char, CStr, CString, OsStr};
+    use std::os::unix::ffi::OsStrExt;
+    use std::ptr;
+
+    let name = CString::new(family).ok()?;
+    // A null config is fontconfig's current one, which it sets up on first use.
+    unsafe {
+        let pattern = fc::FcNameParse(name.as_ptr() as *const fc::FcChar8);
+        if pattern.is_null() {
+            return None;
+        }
+        fc::FcConfigSubstitute(ptr::null_mut(), pattern, fc::FcMatchPattern);
+        fc::FcDefaultSubstitute(pattern);
+        let mut result = fc::FcResultNoMatch;
+        let font = fc::FcFontMatch(ptr::null_mut(), pattern, &mut result);
+        fc::FcPatternDestroy(pattern);
+        if font.is_null() {
+            return None;
+        }
+        let mut file: *mut fc::FcChar8 = ptr::null_mut();
+        let found = fc::FcPatternGetString(font, c"file".as_ptr(), 0, &mut file);
+        let path = (found == fc::FcResultMatch && !file.is_null()).then(|| {
+            let file = CStr::from_ptr(file as *const c_char);
+            PathBuf::from(OsStr::from_bytes(file.to_bytes()))
+        });
+        // The file name belongs to the pattern so it has to be copied first.
+        fc::FcPatternDestroy(font);
+        path
+    }
+}
+
+/// Split a comma-separated chain of font families like `Fira Code,DejaVu Sans Mono`.
+fn split_font_families(families: &str) -> Vec<&str> {
+    families
+        .split(',')
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .collect()
+}
+
+/// Split `f` into its family and size.
+/// `f` is expected to be in format `Mono:72`. The size is always taken from the last
+/// colon-delimited token so that the family itself may contain colons.
+fn split_font_spec(f: &str) -> Result<(&str, f64)> {
+    let (family, size) = f
+        .rsplit_once(':')
+        .context("Wrong font format, expected family:size")?;
+    if family.is_empty() {
+        bail!("Wrong font format, font family is empty");
+    }
+    let size = size
+        .parse::<f64>()
+        .with_context(|| format!("Couldn't parse font size '{size}'"))?;
+    Ok((family, size))
+}
+
+/// Load the first installed family of the comma separated `families`.
+pub fn parse_fallback_font(families: &str) -> Result<String> {
+    let family = load_font(&split_font_families(families)).context("Couldn't load font")?;
+    info!("Loaded fallback font family '{family}'");
+    Ok(family)
+}
+
+/// Generate a valid `FontConfig` from `f`.
+/// `f` is expected to be in format `Mono:72` or `Fira Code,DejaVu Sans Mono,Mono:72` for a chain
+/// of fallback families.
+pub fn parse_truetype_font(f: &str) -> Result<FontConfig> {
+    let (families, size) = split_font_spec(f)?;
+
+    let family = load_font(&split_font_families(families)).context("Couldn't load font")?;
+    info!("Loaded font family '{family}'");
     let font_config = FontConfig {
-        font_family: family.to_string(),
-        font_size: size.parse::<f64>().context("Couldn't parse font size")?,
-        loaded_font,
+        font_family: family,
+        font_size: size,
     };
     Ok(font_config)
 }
@@ -79,9 +380,251 @@ fn parse_offset(c: &str) -> Result<Offset, String> {
     Ok(offset)
 }
 
+/// Expand comma-separated tokens of hint characters.
+///
+/// A token is either a preset (`homerow`, `qwerty` or `dvorak`), a range like `a-z` or a literal
+/// list of characters. The characters of all tokens are joined in the order of the tokens, so
+/// earlier tokens make up the shorter hints.
+fn expand_hint_chars(s: &str) -> Result<String, String> {
+    let mut chars = String::new();
+    for token in s.split(',') {
+        match token {
+            "homerow" => chars.push_str("asdfghjkl"),
+            "qwerty" => chars.push_str("qwertyuiopasdfghjklzxcvbnm"),
+            "dvorak" => chars.push_str("pyfgcrlaoeuidhtnsqjkxbmwvz"),
+            _ => match token.chars().collect::<Vec<char>>()[..] {
+                [from, '-', to] => {
+                    if from > to {
+                        return Err(format!("Range '{token}' is backwards"));
+                    }
+                    chars.extend(from..=to);
+                }
+                _ => chars.push_str(token),
+            },
+        }
+    }
+    Ok(chars)
+}
+
+/// Expand hint characters with `expand_hint_chars` and validate them.
+pub fn parse_hint_chars(s: &str) -> Result<String, String> {
+    validate_hint_chars(expand_hint_chars(s)?)
+}
+
+/// Validate hint characters.
+///
+/// We need at least two characters to be able to build hints of arbitrary length and every
+/// character may only appear once so that hints are unambiguous.
+fn validate_hint_chars(s: String) -> Result<String, String> {
+    let mut seen = vec![];
+    for c in s.chars() {
+        if seen.contains(&c) {
+            return Err(format!("Duplicate hint character '{c}'"));
+        }
+        seen.push(c);
+    }
+    if seen.len() < 2 {
+        return Err("Need at least two unique hint characters".to_string());
+    }
+    Ok(s)
+}
+
+/// Parse a monitor which is either `all`, `current`, `primary` or the name of a RandR output.
+pub fn parse_monitor(s: &str) -> Result<MonitorSelection, String> {
+    match s.to_lowercase().as_str() {
+        "" => Err("Monitor name can't be empty".to_string()),
+        "all" => Ok(MonitorSelection::All),
+        "current" => Ok(MonitorSelection::Current),
+        "primary" => Ok(MonitorSelection::Primary),
+        _ => Ok(MonitorSelection::Name(s.to_string())),
+    }
+}
+
+fn parse_template(s: &str) -> Result<Template> {
+    Template::parse(s)
+}
+
+/// Parse the opacity of the dimming layer which has to be between 0 and 1.
+pub fn parse_dim(s: &str) -> Result<f64, String> {
+    let dim = s
+        .parse::<f64>()
+        .map_err(|_| format!("Expected a number between 0.0 and 1.0, got '{}'", s))?;
+    if !(0.0..=1.0).contains(&dim) {
+        return Err(format!(
+            "Expected a number between 0.0 and 1.0, got '{}'",
+            s
+        ));
+    }
+    Ok(dim)
+}
+
+/// Parse the maximum length of hints which has to be at least 1.
+pub fn parse_max_hint_length(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(length) if length > 0 => Ok(length),
+        _ => Err(format!("Expected a positive number, got '{}'", s)),
+    }
+}
+
+/// Parse a region of the screen given as `x,y,w,h` with a positive width and height.
+pub fn parse_region(s: &str) -> Result<Rect, String> {
+    let values = s
+        .split(',')
+        .map(|v| v.trim().parse::<i32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Expected x,y,w,h, got '{}'", s))?;
+    let [x, y, w, h] = values[..] else {
+        return Err(format!("Expected x,y,w,h, got '{}'", s));
+    };
+    if w <= 0 || h <= 0 {
+        return Err(format!(
+            "The width and height of '{}' have to be positive",
+            s
+        ));
+    }
+    Ok(Rect::new(x, y, w, h))
+}
+
+/// Parse the maximum number of windows to hint which has to be at least 1.
+pub fn parse_max_windows(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("Expected a positive number, got '{}'", s)),
+    }
+}
+
+/// The log level asked for by --quiet and --verbose, `None` leaves it to RUST_LOG.
+pub fn log_level(quiet: bool, verbose: u8) -> Option<log::LevelFilter> {
+    if quiet {
+        return Some(log::LevelFilter::Off);
+    }
+    match verbose {
+        0 => None,
+        1 => Some(log::LevelFilter::Warn),
+        2 => Some(log::LevelFilter::Info),
+        3 => Some(log::LevelFilter::Debug),
+        _ => Some(log::LevelFilter::Trace),
+    }
+}
+
+/// The log level asked for by --quiet and --verbose in the raw `args`, the program name first.
+///
+/// This doesn't need the other options to be valid so that the logger can be set up before
+/// parsing them, which logs too. Values of other options are skipped, `-c -v` doesn't log more.
+pub fn raw_log_level<I, T>(args: I) -> Option<log::LevelFilter>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString>,
+{
+    let command = AppConfig::command();
+    let mut quiet = false;
+    let mut verbose = 0u8;
+    // Returns whether `arg` takes a value, which clap never reads from a following flag.
+    let mut visit = |arg: Option<&clap::Arg>| match arg.map(|a| a.get_id().as_str()) {
+        Some("quiet") => {
+            quiet = true;
+            false
+        }
+        Some("verbose") => {
+            verbose = verbose.saturating_add(1);
+            false
+        }
+        _ => arg.is_some_and(|a| a.get_action().takes_values()),
+    };
+
+    let mut args = args.into_iter().skip(1).map(Into::into).peekable();
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        let mut takes_value = false;
+        if arg == "--" {
+            break;
+        } else if let Some(long) = arg.strip_prefix("--") {
+            if !long.contains('=') {
+                takes_value = visit(command.get_arguments().find(|a| a.get_long() == Some(long)));
+            }
+        } else if let Some(shorts) = arg.strip_prefix('-') {
+            for (i, c) in shorts.char_indices() {
+                if visit(command.get_arguments().find(|a| a.get_short() == Some(c))) {
+                    // The rest of the group is the value if there is any.
+                    takes_value = i + c.len_utf8() == shorts.len();
+                    break;
+                }
+            }
+        }
+        let is_flag = |next: &std::ffi::OsString| next.to_str().is_some_and(|n| n.starts_with('-'));
+        if takes_value && args.peek().is_some_and(|next| !is_flag(next)) {
+            args.next();
+        }
+    }
+    log_level(quiet, verbose)
+}
+
+/// Parse a margin which is either a factor of the text size (`0.2`) or absolute pixels (`12px`).
+pub fn parse_margin(s: &str) -> Result<Margin, String> {
+    if let Some(pixels) = s.strip_suffix("px") {
+        if pixels.trim_start().starts_with('-') {
+            return Err("Margin can't be negative".to_string());
+        }
+        let pixels = pixels
+            .parse::<u32>()
+            .map_err(|_| format!("Couldn't parse margin pixels '{pixels}'"))?;
+        return Ok(Margin::Pixels(pixels));
+    }
+    let factor = s.parse::<f32>().map_err(|_| {
+        format!("Couldn't parse margin '{s}', expected a factor or pixels like 12px")
+    })?;
+    if !factor.is_finite() || factor < 0.0 {
+        return Err("Margin can't be negative".to_string());
+    }
+    Ok(Margin::Factor(factor))
+}
+
+/// Rewrite hex colors with an alpha channel (`#rgba` and `#rrggbbaa`) to `rgba()` notation.
+///
+/// `css_color_parser` only understands hex colors without alpha. Returns `None` for anything that
+/// isn't such a color.
+fn normalize_hex_alpha(color_str: &str) -> Option<String> {
+    let hex = color_str.trim().strip_prefix('#')?;
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channels: Vec<u8> = match hex.len() {
+        4 => hex
+            .chars()
+            .map(|c| u8::from_str_radix(&c.to_string().repeat(2), 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        8 => (0..8)
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        _ => return None,
+    };
+    Some(format!(
+        "rgba({}, {}, {}, {})",
+        channels[0],
+        channels[1],
+        channels[2],
+        f64::from(channels[3]) / 255.0
+    ))
+}
+
 /// Parse a color into a tuple of floats.
-fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
-    let color = color_str
+///
+/// Accepts everything `css_color_parser` does (color names, `#rgb`, `#rrggbb`, `rgb()`,
+/// `rgba()`, `hsl()` and `hsla()`) as well as `#rgba`, `#rrggbbaa` and `rebeccapurple`.
+pub fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
+    // The only named color that came after CSS3, which `css_color_parser` implements.
+    if color_str.trim().eq_ignore_ascii_case("rebeccapurple") {
+        return parse_color("#663399");
+    }
+    let normalized = normalize_hex_alpha(color_str);
+    let color = normalized
+        .as_deref()
+        .unwrap_or(color_str)
         .parse::<CssColor>()
         .map_err(|_| "Invalid color format")?;
     Ok((
@@ -92,27 +635,205 @@ fn parse_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
     ))
 }
 
+/// Value of `--textcolor` that picks black or white depending on the background.
+pub(crate) const AUTO_TEXT_COLOR: &str = "auto";
+
+/// Returns true for the `auto` text color.
+pub(crate) fn is_auto_text_color(color_str: &str) -> bool {
+    color_str.eq_ignore_ascii_case(AUTO_TEXT_COLOR)
+}
+
+/// Like `parse_color` but also accepts `auto`, which is resolved later on to a text color that is
+/// readable on `--bgcolor`.
+///
+/// `auto` parses to black until then.
+pub(crate) fn parse_text_color(color_str: &str) -> Result<(f64, f64, f64, f64), String> {
+    if is_auto_text_color(color_str) {
+        return Ok((0.0, 0.0, 0.0, 1.0));
+    }
+    parse_color(color_str)
+}
+
+/// Swap the text and background colors of both the other windows and the current one.
+///
+/// Only the RGB is swapped so that a translucent background stays translucent. The colors of the
+/// typed part of a hint keep their place between the text and the background, a dimmed text
+/// color becomes a text color dimmed towards the new background.
+fn reverse_video(config: &mut AppConfig) {
+    type Color = (f64, f64, f64, f64);
+    fn mirror(alt: Color, text: Color, bg: Color) -> Color {
+        let channel = |alt: f64, text: f64, bg: f64| (text + bg - alt).clamp(0.0, 1.0);
+        (
+            channel(alt.0, text.0, bg.0),
+            channel(alt.1, text.1, bg.1),
+            channel(alt.2, text.2, bg.2),
+            alt.3,
+        )
+    }
+    fn swap_rgb(a: &mut Color, b: &mut Color) {
+        std::mem::swap(&mut a.0, &mut b.0);
+        std::mem::swap(&mut a.1, &mut b.1);
+        std::mem::swap(&mut a.2, &mut b.2);
+    }
+
+    config.text_color_alt = mirror(config.text_color_alt, config.text_color, config.bg_color);
+    config.text_color_current_alt = mirror(
+        config.text_color_current_alt,
+        config.text_color_current,
+        config.bg_color_current,
+    );
+    swap_rgb(&mut config.text_color, &mut config.bg_color);
+    swap_rgb(&mut config.text_color_current, &mut config.bg_color_current);
+}
+
+/// Black or white, whichever is easier to read on `bg_color`.
+///
+/// Uses the relative luminance from WCAG, the threshold being where both have the same contrast.
+pub fn contrasting_text_color(bg_color: (f64, f64, f64, f64)) -> (f64, f64, f64, f64) {
+    let linear = |c: f64| {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let luminance =
+        0.2126 * linear(bg_color.0) + 0.7152 * linear(bg_color.1) + 0.0722 * linear(bg_color.2);
+    if luminance > 0.179 {
+        (0.0, 0.0, 0.0, 1.0)
+    } else {
+        (1.0, 1.0, 1.0, 1.0)
+    }
+}
+
+/// Format `color` in CSS notation, `#rrggbb` if it is opaque and `rgba()` otherwise.
+pub fn format_color(color: (f64, f64, f64, f64)) -> String {
+    let channel = |c: f64| (c * 255.0).round() as u8;
+    let (r, g, b) = (channel(color.0), channel(color.1), channel(color.2));
+    if color.3 >= 1.0 {
+        format!("#{r:02x}{g:02x}{b:02x}")
+    } else {
+        // Alpha went through an f32 so we'd get lots of noise digits otherwise.
+        let alpha = (color.3 * 1000.0).round() / 1000.0;
+        format!("rgba({r}, {g}, {b}, {alpha})")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Offset {
     pub x: i32,
     pub y: i32,
 }
 
+/// Margin between the text and the edges of its box.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Margin {
+    /// Factor of the text size.
+    Factor(f32),
+    /// Absolute pixels on each side.
+    Pixels(u32),
+}
+
+impl Margin {
+    /// Size of the box around text that is `text_size` large in one dimension.
+    pub fn box_size(&self, text_size: f64) -> f64 {
+        match *self {
+            Margin::Factor(factor) => text_size * (1.0 + f64::from(factor)),
+            Margin::Pixels(pixels) => text_size + 2.0 * f64::from(pixels),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct FontConfig {
     pub font_family: String,
     pub font_size: f64,
-    pub loaded_font: Vec<u8>,
+}
+
+/// Parse the name of a keysym like `Escape` into the keysym.
+pub fn parse_exit_key(s: &str) -> Result<u32, String> {
+    utils::keysym_from_name(s).ok_or_else(|| format!("Unknown key name '{s}', see xev for names"))
 }
 
 fn parse_exit_keys(s: &str) -> Result<utils::Sequence> {
     Ok(utils::Sequence::new(Some(s)))
 }
 
-#[derive(Parser, Debug)]
-#[command(name = "wmfocus", author, about, version)]
+/// A key that is treated as if another key had been pressed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyAlias {
+    pub from: String,
+    pub to: String,
+}
+
+/// Parse a key alias in the format `from=to`, eg `h=j`.
+pub fn parse_key_alias(s: &str) -> Result<KeyAlias, String> {
+    let (from, to) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected an alias like h=j, got '{}'", s))?;
+    if from.is_empty() || to.is_empty() {
+        return Err(format!("Both sides of the alias '{}' need a key", s));
+    }
+    Ok(KeyAlias {
+        from: from.to_string(),
+        to: to.to_string(),
+    })
+}
+
+/// A hint that is always given to windows of a class.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pin {
+    pub class: String,
+    pub hint: String,
+}
+
+/// Parse a pinned hint in the format `class=hint`, eg `firefox=f`.
+pub fn parse_pin(s: &str) -> Result<Pin, String> {
+    let (class, hint) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected a pin like firefox=f, got '{}'", s))?;
+    if class.is_empty() || hint.is_empty() {
+        return Err(format!("The pin '{}' needs both a class and a hint", s));
+    }
+    Ok(Pin {
+        class: class.to_string(),
+        hint: hint.to_string(),
+    })
+}
+
+/// A background color for the hints of windows of a class.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassColor {
+    pub class: String,
+    pub color: (f64, f64, f64, f64),
+}
+
+/// Parse a class color in the format `class=color`, eg `firefox=#336699`.
+pub fn parse_class_color(s: &str) -> Result<ClassColor, String> {
+    let (class, color) = s
+        .split_once('=')
+        .ok_or_else(|| format!("Expected a class color like firefox=blue, got '{}'", s))?;
+    if class.is_empty() {
+        return Err(format!("The class color '{}' needs a class", s));
+    }
+    Ok(ClassColor {
+        class: class.to_string(),
+        color: parse_color(color)?,
+    })
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(
+    name = "wmfocus",
+    author,
+    about,
+    version,
+    group(clap::ArgGroup::new("font_sizing").multiple(true)),
+    after_help = "Colors can be given as a name (red), #rgb, #rgba, #rrggbb, #rrggbbaa, rgb(r, g, b), \
+                  rgba(r, g, b, a), hsl(h, s%, l%) or hsla(h, s%, l%, a)."
+)]
 pub struct AppConfig {
-    /// Use a specific TrueType font with this format: family:size
+    /// Use a specific TrueType font with this format: family:size or family,fallback,...:size
     #[arg(
         short,
         long,
@@ -121,20 +842,91 @@ pub struct AppConfig {
     )]
     pub font: FontConfig,
 
-    /// Define a set of possbile values to use as hint characters
-    #[arg(short = 'c', long = "chars", default_value = "sadfjklewcmpgh")]
+    /// Weight of the font
+    #[arg(long, default_value = "normal", ignore_case = true)]
+    pub font_weight: FontWeight,
+
+    /// Weight of the font for the current window, the same as --font-weight if not given
+    #[arg(long, value_name = "FONT_WEIGHT", ignore_case = true)]
+    pub font_weight_current: Option<FontWeight>,
+
+    /// Slant of the font
+    #[arg(long, default_value = "normal", ignore_case = true)]
+    pub font_slant: FontSlant,
+
+    /// How to antialias the text and shapes of hints, none can make small hints sharper on
+    /// low-res displays
+    #[arg(long, default_value = "default", ignore_case = true)]
+    pub antialias: Antialias,
+
+    /// Unit of the font size, pt is converted using the DPI of the screen before --dpi-scale
+    #[arg(long, default_value = "px", ignore_case = true)]
+    pub font_unit: FontUnit,
+
+    /// Measure ASCII hints by adding up the measured characters instead of measuring every hint
+    #[arg(long)]
+    pub fast_metrics: bool,
+
+    /// Scale the font size by each monitor's DPI relative to 96 DPI
+    #[arg(long)]
+    pub dpi_scale: bool,
+
+    /// Shrink the font of hints that don't fit into their window
+    #[arg(long, group = "font_sizing")]
+    pub auto_fit: bool,
+
+    /// Smallest font size --auto-fit shrinks to and --font-size-from-window picks
+    #[arg(long, default_value = "8", requires = "font_sizing")]
+    pub min_font_size: f64,
+
+    /// Size the font of every hint by its window, FACTOR times the square root of the window's
+    /// area, before --dpi-scale
+    #[arg(long, value_name = "FACTOR", group = "font_sizing")]
+    pub font_size_from_window: Option<f64>,
+
+    /// Largest font size --font-size-from-window picks
+    #[arg(long, default_value = "72", requires = "font_size_from_window")]
+    pub max_font_size: f64,
+
+    /// Define a set of possbile values to use as hint characters, comma-separated ranges like a-z
+    /// and the presets homerow, qwerty and dvorak are expanded
+    #[arg(
+        short = 'c',
+        long = "chars",
+        default_value = "sadfjklewcmpgh",
+        value_parser(parse_hint_chars)
+    )]
     pub hint_chars: String,
 
-    /// Add an additional margin around the text box (value is a factor of the box size)
-    #[arg(short, long, default_value = "0.2")]
-    pub margin: f32,
+    /// Match typed keys against hint characters case-insensitively
+    #[arg(long)]
+    pub ignore_case: bool,
+
+    /// Draw hints in uppercase while still typing them in lowercase
+    #[arg(long)]
+    pub uppercase_labels: bool,
+
+    /// Add an additional margin around the text box (value is a factor of the text size or
+    /// absolute pixels, eg 12px)
+    #[arg(short, long, default_value = "0.2", value_parser(parse_margin))]
+    pub margin: Margin,
+
+    /// Keep the text this many pixels away from the edges of its box
+    #[arg(long, value_name = "PX", default_value = "0")]
+    pub text_padding: u32,
 
-    /// Text color (CSS notation)
+    /// Set all colors from a bundled theme (nord, solarized-dark or gruvbox), the color options
+    /// below and the colors of the config file still override single colors of it
+    #[arg(long, value_name = "NAME", display_order = 48)]
+    pub theme: Option<String>,
+
+    /// Text color (CSS notation), auto for black or white depending on --bgcolor which is also
+    /// the default if only --bgcolor is given
     #[arg(
         long = "textcolor",
         display_order = 49,
         default_value = "#dddddd",
-        value_parser(parse_color)
+        value_parser(parse_text_color)
     )]
     pub text_color: (f64, f64, f64, f64),
 
@@ -147,6 +939,16 @@ pub struct AppConfig {
     )]
     pub text_color_alt: (f64, f64, f64, f64),
 
+    /// Color (CSS notation) of the already typed part of all hints instead of --textcoloralt and
+    /// --textcolorcurrentalt
+    #[arg(
+        long,
+        value_name = "COLOR",
+        display_order = 50,
+        value_parser(parse_color)
+    )]
+    pub match_color: Option<(f64, f64, f64, f64)>,
+
     /// Background color (CSS notation)
     #[arg(
         long = "bgcolor",
@@ -183,6 +985,96 @@ pub struct AppConfig {
     )]
     pub bg_color_current: (f64, f64, f64, f64),
 
+    /// Background color for windows of a class other than the current one, eg firefox=blue (can
+    /// be repeated, the class is a glob)
+    #[arg(
+        long = "class-color",
+        value_name = "CLASS=COLOR",
+        display_order = 54,
+        value_parser(parse_class_color)
+    )]
+    pub class_colors: Vec<ClassColor>,
+
+    /// Compiled class colors built from the option above.
+    #[arg(skip)]
+    pub class_palette: ClassPalette,
+
+    /// Swap the text and background colors for a quick light theme, also for the current window
+    #[arg(long, display_order = 54)]
+    pub reverse_video: bool,
+
+    /// Round the corners of the box with this radius in pixels
+    #[arg(long, display_order = 55, default_value = "0")]
+    pub corner_radius: u32,
+
+    /// Shadow color (CSS notation)
+    #[arg(
+        long,
+        display_order = 58,
+        default_value = "rgba(0, 0, 0, 0.5)",
+        value_parser(parse_color)
+    )]
+    pub shadow_color: (f64, f64, f64, f64),
+
+    /// Move the shadow of the box by this offset (x,y), 0,0 and no blur disables the shadow
+    #[arg(
+        long,
+        display_order = 59,
+        allow_hyphen_values = true,
+        default_value = "0,0",
+        value_parser(parse_offset)
+    )]
+    pub shadow_offset: Offset,
+
+    /// Blur the shadow by this many pixels
+    #[arg(long, display_order = 60, default_value = "0")]
+    pub shadow_blur: u32,
+
+    /// Dim the whole screen behind the hints, 0.0 disables and 1.0 blacks out the screen
+    #[arg(
+        long,
+        value_name = "0.0-1.0",
+        display_order = 61,
+        default_value = "0",
+        value_parser(parse_dim)
+    )]
+    pub dim: f64,
+
+    /// Fade the hints in over this many milliseconds, 0 shows them right away
+    #[arg(long, value_name = "MS", display_order = 62, default_value = "0")]
+    pub fade_in: u64,
+
+    /// Make the boxes catch mouse clicks instead of letting them through to the windows below
+    #[arg(long, display_order = 63)]
+    pub opaque_input: bool,
+
+    /// Display server to show the hints on, Wayland compositors don't tell us where windows are so
+    /// the hints are shown in a grid along with the windows' titles there
+    #[arg(long, display_order = 64, default_value = "auto", ignore_case = true)]
+    pub backend: BackendSelection,
+
+    /// How to put the hints on the screen, single needs fewer round trips with many windows
+    #[arg(
+        long,
+        display_order = 64,
+        default_value = "per-window",
+        ignore_case = true
+    )]
+    pub renderer: Renderer,
+
+    /// Border color (CSS notation)
+    #[arg(
+        long,
+        display_order = 56,
+        default_value = "#000000",
+        value_parser(parse_color)
+    )]
+    pub border_color: (f64, f64, f64, f64),
+
+    /// Width of the border drawn inside the box in pixels, 0 to disable
+    #[arg(long, display_order = 57, default_value = "0")]
+    pub border_width: u32,
+
     /// Horizontal alignment of the box inside the window
     #[arg(
         long = "halign",
@@ -201,14 +1093,150 @@ pub struct AppConfig {
     )]
     pub vertical_align: VerticalAlign,
 
-    /// Completely fill out windows
-    #[arg(long, display_order = 102, conflicts_with_all(&["horizontal_align", "vertical_align", "margin", "offset"]))]
+    /// Which geometry of windows --halign, --valign and --fill are relative to
+    #[arg(
+        long,
+        display_order = 102,
+        default_value = "client",
+        ignore_case = true
+    )]
+    pub geometry_source: GeometrySource,
+
+    /// Completely fill out windows, the text is still placed by --halign and --valign
+    #[arg(long, display_order = 102, conflicts_with_all(&["margin", "offset"]))]
     pub fill: bool,
 
+    /// Where to show the hints, pointer clusters them near the mouse pointer
+    #[arg(
+        long,
+        value_name = "ANCHOR",
+        display_order = 102,
+        default_value = "window",
+        ignore_case = true
+    )]
+    pub hint_position_relative_to: HintAnchor,
+
+    /// Also list all hints along with the class and title of their windows on this edge of the
+    /// screen
+    #[arg(
+        long,
+        value_name = "EDGE",
+        display_order = 103,
+        default_value = "off",
+        ignore_case = true
+    )]
+    pub legend: LegendPosition,
+
+    /// Draw lines of this color (CSS notation) from hints near the pointer to their windows,
+    /// requires --hint-position-relative-to pointer and --renderer single
+    #[arg(
+        long,
+        value_name = "COLOR",
+        display_order = 103,
+        value_parser(parse_color)
+    )]
+    pub connector_color: Option<(f64, f64, f64, f64)>,
+
+    /// Draw the window title after the hint, which then gets a background of its own
+    #[arg(long)]
+    pub show_title: bool,
+
+    /// Direction to lay out hints in
+    #[arg(
+        long,
+        default_value = "ltr",
+        ignore_case = true,
+        conflicts_with = "show_title"
+    )]
+    pub text_direction: TextDirection,
+
+    /// Font family to draw titles in if --font lacks some of their characters, eg emoji or CJK
+    #[arg(
+        long,
+        value_name = "FAMILY",
+        value_parser(parse_fallback_font),
+        requires = "show_title"
+    )]
+    pub fallback_font: Option<String>,
+
+    /// Shorten titles with an ellipsis if they are wider than this many pixels
+    #[arg(
+        long,
+        value_name = "PX",
+        default_value = "400",
+        requires = "show_title"
+    )]
+    pub title_max_width: u32,
+
+    /// What to do with the selected window
+    #[arg(long, default_value = "focus", ignore_case = true)]
+    pub action: Action,
+
+    /// How to focus the selected window
+    #[arg(long, default_value = "auto", ignore_case = true)]
+    pub focus_method: FocusMethod,
+
+    /// Also raise the selected window above all others after focusing it
+    #[arg(long)]
+    pub raise: bool,
+
+    /// Show hints again after every action until the selection is cancelled
+    #[arg(long, conflicts_with_all = ["print_only", "list"])]
+    pub repeat: bool,
+
     /// Print the window id only but don't change focus
     #[arg(short, long)]
     pub print_only: bool,
 
+    /// Print the windows that would get hints along with their hints as JSON and exit
+    #[arg(long)]
+    pub list: bool,
+
+    /// Don't log anything and don't print a message if there are no windows to hint
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Log more, repeat for more detail (-v warnings, -vv info, -vvv debug, -vvvv everything),
+    /// overrides RUST_LOG
+    #[arg(short, long, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    pub verbose: u8,
+
+    /// Read windows as lines of "id x y width height [class] [title]" from stdin instead of
+    /// asking the window manager, requires --print-only or --list. An id like "0x1200003/left"
+    /// gives an area inside the window a hint of its own
+    #[arg(long)]
+    pub windows_from_stdin: bool,
+
+    /// What to print for the selected window in print-only mode
+    #[arg(
+        long,
+        default_value = "id",
+        requires = "print_only",
+        ignore_case = true
+    )]
+    pub print_format: PrintFormat,
+
+    /// Template to print the selected window with in print-only mode, eg '{id}\t{class}'. Knows
+    /// {id}, {target}, {class}, {instance}, {title}, {x}, {y}, {width}, {height} and {hint}
+    #[arg(
+        long,
+        requires = "print_only",
+        conflicts_with = "print_format",
+        value_parser(parse_template)
+    )]
+    pub format: Option<Template>,
+
+    /// Print SENTINEL, 'cancelled' if not given, to stdout if the selection is cancelled in
+    /// print-only mode
+    #[arg(
+        long,
+        value_name = "SENTINEL",
+        num_args = 0..=1,
+        default_missing_value = "cancelled",
+        requires = "print_only"
+    )]
+    pub stdout_on_cancel: Option<String>,
+
     /// Offset box from edge of window relative to alignment (x,y)
     #[arg(
         short,
@@ -222,13 +1250,915 @@ pub struct AppConfig {
     /// List of keys to exit application, sequences separator is space, key separator is '+', eg Control_L+g Shift_L+f
     #[arg(short, long, value_parser(parse_exit_keys))]
     pub exit_keys: Vec<utils::Sequence>,
+
+    /// Key that cancels instead of Escape, named like in xev without the XK_ prefix, eg Caps_Lock
+    /// (can be repeated)
+    #[arg(
+        long = "exit-key",
+        value_name = "KEYNAME",
+        default_value = "Escape",
+        value_parser(parse_exit_key)
+    )]
+    pub cancel_keys: Vec<u32>,
+
+    /// Treat a key as if another one was pressed, eg h=j (can be repeated)
+    #[arg(
+        long = "key-alias",
+        value_name = "FROM=TO",
+        value_parser(parse_key_alias)
+    )]
+    pub key_aliases: Vec<KeyAlias>,
+
+    /// How long the hints are
+    #[arg(
+        long,
+        display_order = 107,
+        default_value = "uniform",
+        ignore_case = true
+    )]
+    pub hint_length: HintLength,
+
+    /// Give windows that would need longer hints than this numeric hints, typed with the digit
+    /// keys
+    #[arg(
+        long,
+        value_name = "CHARS",
+        display_order = 107,
+        value_parser(parse_max_hint_length)
+    )]
+    pub max_hint_length: Option<u32>,
+
+    /// Start hints with a character selecting the monitor from --monitor-chars
+    #[arg(long, display_order = 107)]
+    pub group_by_monitor: bool,
+
+    /// Characters selecting the monitor with --group-by-monitor, in RandR order
+    #[arg(
+        long,
+        display_order = 107,
+        default_value = "123456789",
+        requires = "group_by_monitor",
+        value_parser(parse_hint_chars)
+    )]
+    pub monitor_chars: String,
+
+    /// Always give windows of a class this hint, eg firefox=f (can be repeated)
+    #[arg(
+        long = "pin",
+        value_name = "CLASS=HINT",
+        display_order = 107,
+        value_parser(parse_pin)
+    )]
+    pub pins: Vec<Pin>,
+
+    /// In which order windows get assigned hints
+    #[arg(
+        long,
+        display_order = 108,
+        default_value = "position",
+        ignore_case = true
+    )]
+    pub order: HintOrder,
+
+    /// Only hint the first N windows in the --order, a safety net against sessions with a huge
+    /// number of windows
+    #[arg(
+        long,
+        value_name = "N",
+        display_order = 108,
+        value_parser(parse_max_windows)
+    )]
+    pub max_windows: Option<usize>,
+
+    /// Which workspaces to hint windows on
+    #[arg(
+        long,
+        value_name = "WORKSPACES",
+        display_order = 109,
+        default_value = "current",
+        ignore_case = true
+    )]
+    pub workspace: WorkspaceSelection,
+
+    /// Hint the windows on all EWMH desktops from _NET_CLIENT_LIST and switch to the desktop of
+    /// the selected one, hidden windows are always included
+    #[arg(long, display_order = 109, conflicts_with = "windows_from_stdin")]
+    pub all_desktops: bool,
+
+    /// Reuse the windows found by the last run if it was at most SECS ago and the same window is
+    /// still active, windows that moved meanwhile get misplaced hints
+    #[arg(
+        long,
+        value_name = "SECS",
+        display_order = 109,
+        num_args = 0..=1,
+        default_missing_value = "2",
+        conflicts_with = "windows_from_stdin"
+    )]
+    pub cache: Option<u64>,
+
+    /// Only hint windows on this monitor: current, primary, all or an output name like DP-1
+    #[arg(
+        long,
+        display_order = 109,
+        default_value = "all",
+        value_parser(parse_monitor)
+    )]
+    pub monitor: MonitorSelection,
+
+    /// Only hint windows that mostly lie within this region of the screen
+    #[arg(
+        long,
+        value_name = "X,Y,W,H",
+        display_order = 109,
+        value_parser(parse_region)
+    )]
+    pub region: Option<Rect>,
+
+    /// Only hint windows whose class matches this glob (can be repeated)
+    #[arg(long = "class", value_name = "GLOB", display_order = 110)]
+    pub class_globs: Vec<String>,
+
+    /// Only hint windows whose instance matches this glob (can be repeated)
+    #[arg(long = "instance", value_name = "GLOB", display_order = 111)]
+    pub instance_globs: Vec<String>,
+
+    /// Don't hint windows whose class matches this glob (can be repeated)
+    #[arg(long = "skip-class", value_name = "GLOB", display_order = 112)]
+    pub skip_class_globs: Vec<String>,
+
+    /// Only hint the other windows with the same class as the active window
+    #[arg(long, display_order = 112)]
+    pub same_class: bool,
+
+    /// Select the only other window of the same class right away instead of hinting it
+    #[arg(long, display_order = 112, requires = "same_class")]
+    pub same_class_auto: bool,
+
+    /// Only hint the topmost window of every class so that each application gets a single hint
+    #[arg(long, display_order = 112, conflicts_with = "same_class")]
+    pub once_per_class: bool,
+
+    /// Also hint windows of this type which are skipped by default (can be repeated)
+    #[arg(
+        long = "include-type",
+        value_name = "TYPE",
+        display_order = 113,
+        ignore_case = true
+    )]
+    pub include_types: Vec<WindowType>,
+
+    /// Don't hint minimized windows, selecting one restores it otherwise
+    #[arg(long, display_order = 113)]
+    pub skip_minimized: bool,
+
+    /// Also hint windows whose _NET_WM_WINDOW_OPACITY makes them invisible
+    #[arg(long, display_order = 113)]
+    pub include_transparent: bool,
+
+    /// Also hint windows that are completely covered by other windows
+    #[arg(long, display_order = 113)]
+    pub include_hidden: bool,
+
+    /// Also hint override-redirect windows that the window manager doesn't manage, eg the windows
+    /// of some launchers. Tooltips and menus are override-redirect as well
+    #[arg(long, display_order = 113)]
+    pub include_override_redirect: bool,
+
+    /// Match --class, --instance and --skip-class case-sensitively
+    #[arg(long, display_order = 114)]
+    pub case_sensitive: bool,
+
+    /// Compiled window filters built from the options above.
+    #[arg(skip)]
+    pub filter: WindowFilter,
+
+    /// How to receive key presses, xi2 works while another client grabs the keyboard
+    #[arg(long, default_value = "core", ignore_case = true)]
+    pub input: InputMethod,
+
+    /// How to match key presses against the hints, keycode keeps matching the same physical keys
+    /// after switching the keyboard layout
+    #[arg(long, default_value = "keysym", ignore_case = true)]
+    pub match_by: MatchBy,
+
+    /// Don't ring the bell when a key doesn't match any hint
+    #[arg(long)]
+    pub no_bell: bool,
+
+    /// Exit if no key is pressed within this many seconds (0 disables the timeout)
+    #[arg(long, value_name = "SECS", default_value = "0")]
+    pub timeout: u64,
+
+    /// Wait this many milliseconds before grabbing the keyboard and drop the key events that came
+    /// in meanwhile
+    #[arg(long, value_name = "MS", default_value = "0")]
+    pub delay: u64,
+
+    /// Exit if the keyboard grab gets lost or a hint gets covered by another window
+    #[arg(long)]
+    pub exit_on_focus_loss: bool,
+
+    /// Print all options in effect and exit, those the config file can't set in comments
+    #[arg(long)]
+    pub dump_config: bool,
+
+    /// Read options from this config file instead of $XDG_CONFIG_HOME/wmfocus/config.toml
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+}
+
+impl AppConfig {
+    /// Check for options that can't be used together.
+    ///
+    /// `parse_args` does this already, call it before `run` for configs that were built by hand.
+    pub fn validate(&self) -> Result<()> {
+        validate_hint_chars(self.hint_chars.clone())
+            .map_err(|e| anyhow!("Invalid hint characters: {e}"))?;
+        validate_pins(&self.pins, &self.hint_chars)?;
+
+        // Numeric hints have to be told apart from the others by their first key.
+        if self.max_hint_length.is_some() {
+            if let Some(c) = self.hint_chars.chars().find(char::is_ascii_digit) {
+                bail!(
+                    "--max-hint-length gives out numeric hints so '{c}' can't be a hint character"
+                );
+            }
+        }
+
+        if self.group_by_monitor {
+            if let Some(c) = self
+                .monitor_chars
+                .chars()
+                .find(|c| self.hint_chars.contains(*c))
+            {
+                bail!("Monitor character '{c}' is also a hint character");
+            }
+        }
+
+        // Filled boxes are as large as their windows so they can't be put near the pointer.
+        if self.fill && self.hint_position_relative_to == HintAnchor::Pointer {
+            bail!("--fill can't be used with --hint-position-relative-to pointer");
+        }
+
+        // Only the single renderer has a surface that the lines can be drawn across.
+        if self.connector_color.is_some()
+            && (self.hint_position_relative_to != HintAnchor::Pointer
+                || self.renderer != Renderer::Single)
+        {
+            bail!(
+                "--connector-color requires --hint-position-relative-to pointer and --renderer single"
+            );
+        }
+
+        if let Some(factor) = self.font_size_from_window {
+            if factor <= 0.0 {
+                bail!("--font-size-from-window needs a positive factor");
+            }
+            if self.min_font_size > self.max_font_size {
+                bail!("--min-font-size can't be larger than --max-font-size");
+            }
+        }
+
+        // The window manager's IPC doesn't know about X window ids.
+        if self.all_desktops && self.focus_method == FocusMethod::Wm {
+            bail!("--all-desktops can't be used with --focus-method wm");
+        }
+
+        // Windows from stdin are unknown to the window manager so it can't focus them.
+        if self.windows_from_stdin && !self.print_only && !self.list {
+            bail!("--windows-from-stdin requires --print-only or --list");
+        }
+        Ok(())
+    }
+}
+
+impl Default for AppConfig {
+    /// The configuration wmfocus uses if no flags are given, ignoring any config file and the
+    /// environment.
+    ///
+    /// Unlike `parse_args` this doesn't look up the font, cairo resolves the family when drawing.
+    fn default() -> Self {
+        AppConfig::from_arg_matches(&command_without_fonts().get_matches_from(["wmfocus"]))
+            .expect("The defaults are valid")
+    }
+}
+
+/// Name of the environment variable for the flag `long`, eg `WMFOCUS_BGCOLOR` for `--bgcolor`.
+fn env_var_name(long: &str) -> String {
+    format!("WMFOCUS_{}", long.to_uppercase().replace('-', "_"))
+}
+
+/// Options that control a single run rather than how wmfocus looks and behaves, an environment
+/// variable left over from another run mustn't change them.
+const NO_ENV: [&str; 5] = ["verbose", "quiet", "config", "dump_config", "list"];
+
+/// The command line interface where every appearance and behaviour option can also be set by its
+/// environment variable.
+fn command() -> clap::Command {
+    AppConfig::command().mut_args(|arg| {
+        if NO_ENV.contains(&arg.get_id().as_str()) {
+            return arg;
+        }
+        match arg.get_long().map(env_var_name) {
+            Some(name) => arg.env(name).hide_env(true),
+            None => arg,
+        }
+    })
+}
+
+/// Returns true if the option `id` was given on the command line or in its environment variable.
+pub(crate) fn is_explicit(matches: &ArgMatches, id: &str) -> bool {
+    matches!(
+        matches.value_source(id),
+        Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+    )
+}
+
+/// The command of `AppConfig` without loading the font, the family is used as given.
+pub(crate) fn command_without_fonts() -> clap::Command {
+    AppConfig::command().mut_arg("font", |arg| {
+        arg.value_parser(|f: &str| -> Result<FontConfig> {
+            let (family, size) = split_font_spec(f)?;
+            Ok(FontConfig {
+                font_family: family.to_string(),
+                font_size: size,
+            })
+        })
+    })
+}
+
+/// Parse `args` into a config without loading the font, the family is used as given.
+///
+/// None of the checks and adjustments of `parse_args` are done.
+#[cfg(test)]
+pub(crate) fn test_config(args: &[&str]) -> AppConfig {
+    AppConfig::from_arg_matches(&command_without_fonts().get_matches_from(args)).unwrap()
+}
+
+/// Drop the hint characters that only differ in case from an earlier one and spell pinned hints
+/// with the remaining ones, so that `F` is pinned to the hint `f` of `--chars asdf`.
+fn fold_case(config: &mut AppConfig) -> Result<()> {
+    config.hint_chars = validate_hint_chars(utils::fold_hint_chars(&config.hint_chars))
+        .map_err(|e| anyhow!("Invalid hint characters after folding their case: {e}"))?;
+    for pin in &mut config.pins {
+        pin.hint = utils::fold_hint(&pin.hint, &config.hint_chars);
+    }
+    Ok(())
 }
 
-pub fn parse_args() -> AppConfig {
-    let mut config = AppConfig::parse();
-    if config.fill {
+/// Check that all `pins` can be typed with `hint_chars` and that none of them gets in the way of
+/// another.
+fn validate_pins(pins: &[Pin], hint_chars: &str) -> Result<()> {
+    for pin in pins {
+        if let Some(c) = pin.hint.chars().find(|c| !hint_chars.contains(*c)) {
+            bail!(
+                "Pinned hint '{}' uses '{c}' which isn't a hint character",
+                pin.hint
+            );
+        }
+        if let Some(other) = pins
+            .iter()
+            .find(|p| p.hint == pin.hint && p.class != pin.class)
+        {
+            bail!(
+                "Hint '{}' is pinned to both {} and {}",
+                pin.hint,
+                pin.class,
+                other.class
+            );
+        }
+        if let Some(other) = pins
+            .iter()
+            .find(|p| p.hint != pin.hint && p.hint.starts_with(&pin.hint))
+        {
+            bail!(
+                "Pinned hint '{}' is a prefix of pinned hint '{}'",
+                pin.hint,
+                other.hint
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn parse_args() -> Result<AppConfig> {
+    let matches = command().get_matches();
+    let config = AppConfig::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let config_file = match &config.config {
+        Some(path) => Some(ConfigFile::load(path)?),
+        None => config::default_path()
+            .filter(|p| p.exists())
+            .map(|path| ConfigFile::load(&path))
+            .transpose()?,
+    };
+    resolve(config, &matches, config_file)
+}
+
+/// Merge `config_file` into `config` as parsed from `matches`, resolve the options that depend on
+/// each other and check the result.
+fn resolve(
+    mut config: AppConfig,
+    matches: &ArgMatches,
+    config_file: Option<ConfigFile>,
+) -> Result<AppConfig> {
+    let default_align = (config.horizontal_align, config.vertical_align);
+    let mut default_colors = (config.text_color, config.bg_color);
+
+    // A theme replaces the default colors, so the config file and explicit options override it.
+    if let Some(theme) = config.theme.as_deref().map(Theme::find).transpose()? {
+        theme.apply(&mut config, matches);
+        default_colors = (theme.text_color, theme.bg_color);
+    }
+
+    // Text in the default color can be unreadable on a custom background.
+    let mut auto_text_color = matches
+        .get_raw("text_color")
+        .and_then(|mut values| values.next())
+        .and_then(|value| value.to_str())
+        .is_some_and(is_auto_text_color);
+
+    // Options from the config file are only used if they weren't given on the command line or in
+    // the environment.
+    if let Some(config_file) = config_file {
+        auto_text_color |= config_file.auto_text_color() && !is_explicit(matches, "text_color");
+        config_file.apply(&mut config, matches);
+    }
+
+    let custom_bg_color = is_explicit(matches, "bg_color") || config.bg_color != default_colors.1;
+    if auto_text_color
+        || (!is_explicit(matches, "text_color")
+            && config.text_color == default_colors.0
+            && custom_bg_color)
+    {
+        config.text_color = contrasting_text_color(config.bg_color);
+    }
+
+    // Swapping after the config file was applied also swaps the colors that came from it. --dim
+    // draws its own window so it isn't affected.
+    if config.reverse_video {
+        reverse_video(&mut config);
+    }
+
+    // Hint characters that only differ in case would look the same in uppercase.
+    if config.ignore_case || config.uppercase_labels {
+        fold_case(&mut config)?;
+    }
+
+    config.filter = WindowFilter::new(
+        &config.class_globs,
+        &config.instance_globs,
+        &config.skip_class_globs,
+        config.case_sensitive,
+    )?;
+    config.class_palette = ClassPalette::new(&config.class_colors, config.case_sensitive)?;
+
+    config.validate()?;
+
+    // Filled windows get their text centered unless asked otherwise.
+    if config.fill
+        && !is_explicit(matches, "horizontal_align")
+        && config.horizontal_align == default_align.0
+    {
         config.horizontal_align = HorizontalAlign::Center;
+    }
+    if config.fill
+        && !is_explicit(matches, "vertical_align")
+        && config.vertical_align == default_align.1
+    {
         config.vertical_align = VerticalAlign::Center;
     }
-    config
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_font_spec() {
+        assert_eq!(split_font_spec("Mono:72").unwrap(), ("Mono", 72.0));
+        assert_eq!(
+            split_font_spec("Noto Color Emoji:24").unwrap(),
+            ("Noto Color Emoji", 24.0)
+        );
+        assert_eq!(
+            split_font_spec("Noto Sans:bold:14").unwrap(),
+            ("Noto Sans:bold", 14.0)
+        );
+        assert!(split_font_spec("JustAFamily").is_err());
+        assert!(split_font_spec("Mono:big").is_err());
+        assert!(split_font_spec(":72").is_err());
+    }
+
+    #[test]
+    fn test_split_font_families() {
+        assert_eq!(split_font_families("Mono"), vec!["Mono"]);
+        assert_eq!(
+            split_font_families("Fira Code, DejaVu Sans Mono,Mono"),
+            vec!["Fira Code", "DejaVu Sans Mono", "Mono"]
+        );
+        assert_eq!(split_font_families("Mono,,"), vec!["Mono"]);
+    }
+
+    #[test]
+    fn test_default() {
+        let config = AppConfig::default();
+        assert_eq!(config.font.font_family, "Mono");
+        assert_eq!(config.font.font_size, 72.0);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = AppConfig {
+            fill: true,
+            hint_position_relative_to: HintAnchor::Pointer,
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_err());
+        let config = AppConfig {
+            hint_chars: "aa".to_string(),
+            ..AppConfig::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_env_vars() {
+        assert_eq!(env_var_name("bgcolor"), "WMFOCUS_BGCOLOR");
+        assert_eq!(env_var_name("print-only"), "WMFOCUS_PRINT_ONLY");
+        let command = command();
+        let env = |id: &str| {
+            command
+                .get_arguments()
+                .find(|a| a.get_id() == id)
+                .and_then(|a| a.get_env())
+                .map(|e| e.to_string_lossy().into_owned())
+        };
+        assert_eq!(env("font").as_deref(), Some("WMFOCUS_FONT"));
+        assert_eq!(env("hint_chars").as_deref(), Some("WMFOCUS_CHARS"));
+        assert_eq!(env("horizontal_align").as_deref(), Some("WMFOCUS_HALIGN"));
+        for id in NO_ENV {
+            assert!(command.get_arguments().any(|a| a.get_id() == id));
+            assert_eq!(env(id), None);
+        }
+    }
+
+    #[test]
+    fn test_parse_margin() {
+        assert_eq!(parse_margin("0.2").unwrap(), Margin::Factor(0.2));
+        assert_eq!(parse_margin("12px").unwrap(), Margin::Pixels(12));
+        assert!(parse_margin("-0.2").is_err());
+        assert!(parse_margin("-12px").is_err());
+        assert!(parse_margin("12em").is_err());
+    }
+
+    #[test]
+    fn test_margin_box_size() {
+        assert_eq!(Margin::Factor(0.5).box_size(10.0), 15.0);
+        assert_eq!(Margin::Pixels(3).box_size(10.0), 16.0);
+    }
+
+    #[test]
+    fn test_parse_hint_chars() {
+        assert_eq!(parse_hint_chars("asdf").unwrap(), "asdf");
+        assert!(parse_hint_chars("").is_err());
+        assert!(parse_hint_chars("a").is_err());
+        assert!(parse_hint_chars("asda").unwrap_err().contains("'a'"));
+    }
+
+    #[test]
+    fn test_expand_hint_chars() {
+        assert_eq!(parse_hint_chars("a-e").unwrap(), "abcde");
+        assert_eq!(parse_hint_chars("homerow,0-3").unwrap(), "asdfghjkl0123");
+        assert_eq!(parse_hint_chars("xy,a-c").unwrap(), "xyabc");
+        assert_eq!(parse_hint_chars("dvorak").unwrap().chars().count(), 26);
+        // A dash that isn't between two characters is taken literally.
+        assert_eq!(parse_hint_chars("ab-").unwrap(), "ab-");
+        assert!(parse_hint_chars("z-a").unwrap_err().contains("backwards"));
+        assert!(parse_hint_chars("homerow,a-c")
+            .unwrap_err()
+            .contains("Duplicate hint character 'a'"));
+    }
+
+    #[test]
+    fn test_parse_monitor() {
+        assert_eq!(parse_monitor("all"), Ok(MonitorSelection::All));
+        assert_eq!(parse_monitor("Current"), Ok(MonitorSelection::Current));
+        assert_eq!(parse_monitor("primary"), Ok(MonitorSelection::Primary));
+        assert_eq!(
+            parse_monitor("DP-1"),
+            Ok(MonitorSelection::Name("DP-1".to_string()))
+        );
+        assert!(parse_monitor("").is_err());
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("#fff"), Ok((1.0, 1.0, 1.0, 1.0)));
+        assert_eq!(parse_color("#ff0000"), Ok((1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(parse_color("rgb(0, 255, 0)"), Ok((0.0, 1.0, 0.0, 1.0)));
+        assert_eq!(
+            parse_color("rgba(0, 0, 255, 0.5)"),
+            Ok((0.0, 0.0, 1.0, 0.5))
+        );
+        assert_eq!(parse_color("red"), Ok((1.0, 0.0, 0.0, 1.0)));
+        assert!(parse_color("#ff00").is_ok());
+        assert!(parse_color("#ff00000").is_err());
+        assert!(parse_color("#gggggggg").is_err());
+        assert!(parse_color("notacolor").is_err());
+        assert_eq!(parse_color("RebeccaPurple"), Ok((0.4, 0.2, 0.6, 1.0)));
+        assert_eq!(parse_text_color("auto"), Ok((0.0, 0.0, 0.0, 1.0)));
+        assert!(parse_text_color("notacolor").is_err());
+    }
+
+    #[test]
+    fn test_contrasting_text_color() {
+        let black = (0.0, 0.0, 0.0, 1.0);
+        let white = (1.0, 1.0, 1.0, 1.0);
+        assert_eq!(
+            contrasting_text_color(parse_color("#ffffff").unwrap()),
+            black
+        );
+        assert_eq!(
+            contrasting_text_color(parse_color("yellow").unwrap()),
+            black
+        );
+        assert_eq!(contrasting_text_color(parse_color("navy").unwrap()), white);
+        assert_eq!(
+            contrasting_text_color(parse_color("rgba(30, 30, 30, 0.9)").unwrap()),
+            white
+        );
+        // Pure red is just bright enough for black to have the better contrast.
+        assert_eq!(contrasting_text_color(parse_color("red").unwrap()), black);
+    }
+
+    #[test]
+    fn test_parse_color_hex_alpha() {
+        let (r, g, b, a) = parse_color("#dddddd80").unwrap();
+        assert_eq!((r, g, b), (221.0 / 255.0, 221.0 / 255.0, 221.0 / 255.0));
+        assert!((a - 128.0 / 255.0).abs() < 1e-6);
+        assert_eq!(parse_color("#f00f"), Ok((1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(parse_color("#FF000000"), Ok((1.0, 0.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_parse_class_color() {
+        assert_eq!(
+            parse_class_color("Alacritty=#00ff00"),
+            Ok(ClassColor {
+                class: "Alacritty".to_string(),
+                color: (0.0, 1.0, 0.0, 1.0)
+            })
+        );
+        assert!(parse_class_color("Alacritty").is_err());
+        assert!(parse_class_color("=red").is_err());
+        assert!(parse_class_color("Alacritty=notacolor").is_err());
+    }
+
+    #[test]
+    fn test_parse_pin() {
+        assert_eq!(
+            parse_pin("firefox=f"),
+            Ok(Pin {
+                class: "firefox".to_string(),
+                hint: "f".to_string()
+            })
+        );
+        assert!(parse_pin("firefox").is_err());
+        assert!(parse_pin("=f").is_err());
+        assert!(parse_pin("firefox=").is_err());
+    }
+
+    #[test]
+    fn test_validate_pins() {
+        let validate = |args: &[&str]| {
+            let config = test_config(args);
+            validate_pins(&config.pins, &config.hint_chars).map_err(|e| e.to_string())
+        };
+        assert!(validate(&["wmfocus", "--pin", "firefox=f", "--pin", "Alacritty=j"]).is_ok());
+        assert_eq!(
+            validate(&["wmfocus", "--pin", "firefox=f", "--pin", "Alacritty=f"]),
+            Err("Hint 'f' is pinned to both firefox and Alacritty".to_string())
+        );
+        assert_eq!(
+            validate(&["wmfocus", "--pin", "firefox=f", "--pin", "Alacritty=fj"]),
+            Err("Pinned hint 'f' is a prefix of pinned hint 'fj'".to_string())
+        );
+        assert_eq!(
+            validate(&["wmfocus", "--pin", "firefox=1"]),
+            Err("Pinned hint '1' uses '1' which isn't a hint character".to_string())
+        );
+    }
+
+    #[test]
+    fn test_resolve_auto_text_color() {
+        let args = ["wmfocus", "--bgcolor", "#ffffff"];
+        let resolve_with = |contents: &str, args: &[&str]| {
+            let matches = command_without_fonts().get_matches_from(args);
+            let config = AppConfig::from_arg_matches(&matches).unwrap();
+            resolve(config, &matches, Some(ConfigFile::parse(contents).unwrap())).unwrap()
+        };
+        let dark = contrasting_text_color((1.0, 1.0, 1.0, 1.0));
+
+        let config = resolve_with(r#"textcolor = "auto""#, &args);
+        assert_eq!(config.text_color, dark);
+        let config = resolve_with(
+            r##"
+            textcolor = "auto"
+            bgcolor = "#ffffff"
+            "##,
+            &["wmfocus"],
+        );
+        assert_eq!(config.text_color, dark);
+
+        // An explicit text color wins over `auto` from the config file.
+        let config = resolve_with(
+            r#"textcolor = "auto""#,
+            &["wmfocus", "--bgcolor", "#ffffff", "--textcolor", "#ff0000"],
+        );
+        assert_eq!(config.text_color, (1.0, 0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_resolve_theme() {
+        let matches = command_without_fonts().get_matches_from(["wmfocus", "--theme", "nord"]);
+        let config = AppConfig::from_arg_matches(&matches).unwrap();
+        let config_file = ConfigFile::parse(r##"bgcolor = "#ff0000""##).unwrap();
+        let config = resolve(config, &matches, Some(config_file)).unwrap();
+        let nord = Theme::find("nord").unwrap();
+        assert_eq!(config.bg_color, (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(config.text_color_alt, nord.text_color_alt);
+        assert_eq!(config.text_color, contrasting_text_color(config.bg_color));
+    }
+
+    #[test]
+    fn test_reverse_video() {
+        let mut config = test_config(&[
+            "wmfocus",
+            "--textcolor",
+            "#ffffff",
+            "--textcoloralt",
+            "#cccccc",
+            "--bgcolor",
+            "rgba(0, 0, 0, 0.5)",
+            "--textcolorcurrent",
+            "#ff0000",
+            "--bgcolorcurrent",
+            "#0000ff",
+        ]);
+        let current_alt = config.text_color_current_alt;
+        reverse_video(&mut config);
+        assert_eq!(config.text_color, (0.0, 0.0, 0.0, 1.0));
+        assert_eq!(config.bg_color, (1.0, 1.0, 1.0, 0.5));
+        assert_eq!(config.text_color_current, (0.0, 0.0, 1.0, 1.0));
+        assert_eq!(config.bg_color_current, (1.0, 0.0, 0.0, 1.0));
+        // The typed part stays as far from the text color, towards the background.
+        let alt = config.text_color_alt;
+        assert!((alt.0 - 0.2).abs() < 1e-9 && alt.3 == 1.0);
+        assert_eq!(config.text_color_current_alt.3, current_alt.3);
+
+        reverse_video(&mut config);
+        assert!((config.text_color_alt.0 - 0.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fold_case_pins() {
+        let mut config = test_config(&[
+            "wmfocus",
+            "--chars",
+            "asdFf",
+            "--ignore-case",
+            "--pin",
+            "firefox=f",
+            "--pin",
+            "Alacritty=SA",
+        ]);
+        assert!(validate_pins(&config.pins, &config.hint_chars).is_err());
+        fold_case(&mut config).unwrap();
+        assert_eq!(config.hint_chars, "asdF");
+        let hints: Vec<&str> = config.pins.iter().map(|p| p.hint.as_str()).collect();
+        assert_eq!(hints, ["F", "sa"]);
+        assert!(validate_pins(&config.pins, &config.hint_chars).is_ok());
+    }
+
+    #[test]
+    fn test_parse_exit_key() {
+        assert_eq!(parse_exit_key("Escape"), Ok(0xff1b));
+        assert_eq!(parse_exit_key("XK_q"), Ok(u32::from('q')));
+        assert!(parse_exit_key("Esc").is_err());
+    }
+
+    #[test]
+    fn test_parse_key_alias() {
+        assert_eq!(
+            parse_key_alias("h=j"),
+            Ok(KeyAlias {
+                from: "h".to_string(),
+                to: "j".to_string()
+            })
+        );
+        assert_eq!(parse_key_alias("semicolon=l").unwrap().from, "semicolon");
+        assert!(parse_key_alias("hj").is_err());
+        assert!(parse_key_alias("=j").is_err());
+        assert!(parse_key_alias("h=").is_err());
+    }
+
+    #[test]
+    fn test_parse_dim() {
+        assert_eq!(parse_dim("0"), Ok(0.0));
+        assert_eq!(parse_dim("0.4"), Ok(0.4));
+        assert_eq!(parse_dim("1"), Ok(1.0));
+        assert!(parse_dim("1.5").is_err());
+        assert!(parse_dim("-0.1").is_err());
+        assert!(parse_dim("dark").is_err());
+    }
+
+    #[test]
+    fn test_format_color() {
+        assert_eq!(format_color((1.0, 0.0, 0.0, 1.0)), "#ff0000");
+        assert_eq!(
+            format_color(parse_color("rgba(50, 50, 200, 0.3)").unwrap()),
+            "rgba(50, 50, 200, 0.3)"
+        );
+        for color in ["#123456", "rgba(0, 0, 0, 0.5)"] {
+            assert_eq!(format_color(parse_color(color).unwrap()), color);
+        }
+    }
+
+    #[test]
+    fn test_parse_max_hint_length() {
+        assert_eq!(parse_max_hint_length("3"), Ok(3));
+        assert!(parse_max_hint_length("0").is_err());
+        assert!(parse_max_hint_length("-1").is_err());
+        assert!(parse_max_hint_length("long").is_err());
+    }
+
+    #[test]
+    fn test_log_level() {
+        assert_eq!(log_level(false, 0), None);
+        assert_eq!(log_level(true, 0), Some(log::LevelFilter::Off));
+        assert_eq!(log_level(false, 2), Some(log::LevelFilter::Info));
+        assert_eq!(log_level(false, 9), Some(log::LevelFilter::Trace));
+    }
+
+    #[test]
+    fn test_raw_log_level() {
+        let level = |args: &[&str]| raw_log_level(args);
+        assert_eq!(level(&["wmfocus"]), None);
+        assert_eq!(level(&["wmfocus", "-vv"]), Some(log::LevelFilter::Info));
+        assert_eq!(
+            level(&["wmfocus", "-v", "--verbose", "-pv"]),
+            Some(log::LevelFilter::Debug)
+        );
+        assert_eq!(level(&["wmfocus", "--quiet"]), Some(log::LevelFilter::Off));
+        // Values of other options don't count, even if they look like flags.
+        assert_eq!(level(&["wmfocus", "-c", "qv", "-fvv:12"]), None);
+        assert_eq!(level(&["wmfocus", "--chars", "v", "--", "-v"]), None);
+        assert_eq!(
+            level(&["wmfocus", "--cache", "-v"]),
+            Some(log::LevelFilter::Warn)
+        );
+    }
+
+    #[test]
+    fn test_parse_region() {
+        assert_eq!(
+            parse_region("0,100,800,600"),
+            Ok(Rect::new(0, 100, 800, 600))
+        );
+        assert_eq!(parse_region("-10, 0, 5, 5"), Ok(Rect::new(-10, 0, 5, 5)));
+        assert!(parse_region("0,0,800").is_err());
+        assert!(parse_region("0,0,800,600,1").is_err());
+        assert!(parse_region("0,0,0,600").is_err());
+        assert!(parse_region("0,0,800,-1").is_err());
+        assert!(parse_region("a,b,c,d").is_err());
+    }
+
+    #[test]
+    fn test_parse_max_windows() {
+        assert_eq!(parse_max_windows("200"), Ok(200));
+        assert!(parse_max_windows("0").is_err());
+        assert!(parse_max_windows("many").is_err());
+    }
+
+    #[test]
+    fn test_font_size_from_window_args() {
+        let config = test_config(&["wmfocus", "--font-size-from-window", "0.05"]);
+        assert_eq!(config.font_size_from_window, Some(0.05));
+        assert_eq!((config.min_font_size, config.max_font_size), (8.0, 72.0));
+        let parse = |args: &[&str]| command_without_fonts().try_get_matches_from(args).is_ok();
+        assert!(parse(&["wmfocus", "--auto-fit", "--min-font-size", "4"]));
+        assert!(parse(&[
+            "wmfocus",
+            "--font-size-from-window",
+            "0.05",
+            "--min-font-size",
+            "4",
+            "--auto-fit"
+        ]));
+        assert!(!parse(&["wmfocus", "--min-font-size", "4"]));
+        assert!(!parse(&["wmfocus", "--max-font-size", "40"]));
+    }
 }
@@ -0,0 +1,245 @@
+//! The display servers hints can be shown on.
+
+use std::collections::HashSet;
+use std::env;
+use std::thread::sleep;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::{debug, info, warn};
+use x11rb::xcb_ffi::XCBConnection;
+use x11rb::{
+    connection::Connection,
+    protocol::randr::{self, ConnectionExt as _},
+    protocol::xproto::{self, ConnectionExt as _},
+    wrapper::ConnectionExt as _,
+};
+
+use crate::args::{self, AppConfig, BackendSelection};
+use crate::{monitor, properties, utils, DesktopWindow, Selection};
+
+/// A display server that finds windows, shows hints for them and acts on the selected one.
+pub(crate) trait Backend {
+    /// The monitors windows are sorted onto.
+    fn monitors(&self) -> &[monitor::Monitor];
+
+    /// Get the windows that should get a hint.
+    fn list_windows(&mut self, app_config: &AppConfig) -> Result<Vec<DesktopWindow>>;
+
+    /// Show hints for `desktop_windows` and let the user select one.
+    ///
+    /// Returns `None` if the hints have to be shown again, eg because the screen changed.
+    fn show_hints(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_windows: Vec<DesktopWindow>,
+    ) -> Result<Option<Selection>>;
+
+    /// Ask for the monitors again after the screen changed.
+    fn update_monitors(&mut self, app_config: &AppConfig) -> Result<()>;
+
+    /// Perform `app_config.action` on the selected `desktop_window`.
+    fn perform_action(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_window: &DesktopWindow,
+    ) -> Result<()>;
+
+    /// Forget about the events caused by our own action before showing hints again.
+    fn settle(&mut self) -> Result<()>;
+}
+
+/// Decide which display server to use for `selection`.
+///
+/// Without a hint from the user we only go for Wayland if there is no X11 display to fall back on.
+pub(crate) fn select(selection: BackendSelection) -> BackendSelection {
+    match selection {
+        BackendSelection::Auto
+            if env::var_os("DISPLAY").is_none() && env::var_os("WAYLAND_DISPLAY").is_some() =>
+        {
+            BackendSelection::Wayland
+        }
+        BackendSelection::Auto => BackendSelection::X11,
+        selection => selection,
+    }
+}
+
+/// Show hints on an X11 display.
+pub(crate) struct XcbBackend<'c> {
+    conn: &'c XCBConnection,
+    screen: &'c xproto::Screen,
+    atoms: properties::Atoms,
+    monitors: Vec<monitor::Monitor>,
+    keymap: Option<utils::Keymap>,
+    /// Keyboard and mouse grab, held on to across all rounds of `--repeat`.
+    grab: Option<utils::InputGrab<'c, XCBConnection>>,
+    held_keys: HashSet<u8>,
+}
+
+impl<'c> XcbBackend<'c> {
+    /// Prepare showing hints on screen `screen_num` of `conn`.
+    ///
+    /// Font sizes given in points are converted to pixels at the screen's DPI.
+    pub(crate) fn new(
+        conn: &'c XCBConnection,
+        screen_num: usize,
+        app_config: &mut AppConfig,
+    ) -> Result<XcbBackend<'c>> {
+        let screen = &conn.setup().roots[screen_num];
+
+        if app_config.font_unit == args::FontUnit::Pt {
+            let dpi = monitor::screen_dpi(screen);
+            app_config.font.font_size = monitor::points_to_pixels(app_config.font.font_size, dpi);
+            info!(
+                "Using a font size of {}px at {:.0} DPI",
+                app_config.font.font_size, dpi
+            );
+        }
+
+        let mut backend = XcbBackend {
+            conn,
+            screen,
+            atoms: properties::Atoms::intern(conn)?,
+            monitors: vec![],
+            keymap: None,
+            grab: None,
+            held_keys: HashSet::new(),
+        };
+        backend.update_monitors(app_config)?;
+        Ok(backend)
+    }
+
+    /// Set up everything that is only needed to select a window rather than list them.
+    fn grab_input(&mut self, app_config: &AppConfig) -> Result<()> {
+        let conn = self.conn;
+        let screen = self.screen;
+
+        // Hotplugging a monitor moves things around so we need to know when that happens.
+        conn.randr_select_input(screen.root, randr::NotifyMask::SCREEN_CHANGE)?;
+
+        // Our keyboard grab is on the root window so that's where we learn about losing it.
+        if app_config.exit_on_focus_loss {
+            conn.change_window_attributes(
+                screen.root,
+                &xproto::ChangeWindowAttributesAux::new()
+                    .event_mask(xproto::EventMask::FOCUS_CHANGE),
+            )?;
+            conn.flush()?;
+        }
+
+        // Remember which keys produce the hint characters right now so that they keep selecting
+        // the same hints after switching the layout.
+        self.keymap = match app_config.match_by {
+            args::MatchBy::Keysym => None,
+            args::MatchBy::Keycode => {
+                let keymap = utils::Keymap::get(conn)?;
+                let missing = if app_config.ignore_case || app_config.uppercase_labels {
+                    keymap.missing_chars(&app_config.hint_chars.to_lowercase())
+                } else {
+                    keymap.missing_chars(&app_config.hint_chars)
+                };
+                if !missing.is_empty() {
+                    warn!(
+                        "No key produces the hint characters {:?} in the current layout",
+                        missing
+                    );
+                }
+                Some(keymap)
+            }
+        };
+
+        // Give the keys of the keybinding that started us time to be released and forget about
+        // everything typed meanwhile.
+        if app_config.delay > 0 {
+            sleep(Duration::from_millis(app_config.delay));
+            conn.sync()?;
+            while conn.poll_for_event()?.is_some() {}
+        }
+        self.grab = Some(utils::InputGrab::new(conn, screen, app_config.input)?);
+        self.held_keys = utils::get_held_keys(conn)?;
+        if !self.held_keys.is_empty() {
+            debug!("Ignoring keys held down at grab time: {:?}", self.held_keys);
+        }
+        Ok(())
+    }
+}
+
+impl Backend for XcbBackend<'_> {
+    fn monitors(&self) -> &[monitor::Monitor] {
+        &self.monitors
+    }
+
+    fn list_windows(&mut self, app_config: &AppConfig) -> Result<Vec<DesktopWindow>> {
+        crate::candidate_windows(
+            app_config,
+            self.conn,
+            &self.atoms,
+            self.screen,
+            &self.monitors,
+        )
+    }
+
+    fn show_hints(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_windows: Vec<DesktopWindow>,
+    ) -> Result<Option<Selection>> {
+        if self.grab.is_none() {
+            self.grab_input(app_config)?;
+        }
+        crate::show_hints(
+            app_config,
+            self.conn,
+            &self.atoms,
+            self.screen,
+            &self.monitors,
+            desktop_windows,
+            self.keymap.as_ref(),
+            &mut self.held_keys,
+        )
+    }
+
+    fn update_monitors(&mut self, app_config: &AppConfig) -> Result<()> {
+        self.monitors = if app_config.dpi_scale
+            || app_config.group_by_monitor
+            || app_config.monitor != args::MonitorSelection::All
+        {
+            monitor::get_monitors(self.conn, self.screen.root).context("Couldn't get monitors")?
+        } else {
+            vec![]
+        };
+        Ok(())
+    }
+
+    fn perform_action(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_window: &DesktopWindow,
+    ) -> Result<()> {
+        crate::perform_action(
+            app_config,
+            self.conn,
+            &self.atoms,
+            self.screen,
+            desktop_window,
+        )?;
+        // The focus from before the grab is out of date once an action changed it.
+        if let Some(grab) = &mut self.grab {
+            grab.keep_focus();
+        }
+        Ok(())
+    }
+
+    fn settle(&mut self) -> Result<()> {
+        // Our own action causes focus and visibility changes which mustn't end the next round.
+        self.conn.sync()?;
+        while self.conn.poll_for_event()?.is_some() {}
+        Ok(())
+    }
+}
+
+/// Complain about a Wayland backend that wasn't built in.
+#[cfg(not(feature = "wayland"))]
+pub(crate) fn no_wayland() -> Result<()> {
+    anyhow::bail!("wmfocus was built without the wayland feature, please enable XWayland and use --backend x11")
+}
@@ -0,0 +1,292 @@
+//! Remember the windows found through the window manager for a short while with `--cache`.
+//!
+//! Invoking us again right away can then skip asking for them. The cache is only used while it's
+//! fresh, for the same window source and while the same window is active.
+//!
+//! Outside of `$XDG_RUNTIME_DIR` the cache ends up in a directory everyone can write to, so it's
+//! never followed through a symlink, replaced rather than written to in place and only read back
+//! if it belongs to us and nobody else can write to it.
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Write};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::DesktopWindow;
+
+/// Location of the cache, in `$XDG_RUNTIME_DIR` or the temporary directory otherwise.
+pub fn default_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR").filter(|p| !p.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("wmfocus-windows.json"),
+        None => {
+            let user = env::var("USER").unwrap_or_default();
+            env::temp_dir().join(format!("wmfocus-{user}-windows.json"))
+        }
+    }
+}
+
+/// Milliseconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis() as u64)
+}
+
+/// A list of windows along with what it depends on.
+#[derive(Debug)]
+pub struct Cache {
+    /// When the windows were found in milliseconds since the Unix epoch.
+    created: u64,
+    /// Describes the options that decided which windows were found.
+    source: String,
+    active_window: Option<u32>,
+    windows: Vec<DesktopWindow>,
+}
+
+fn window_to_json(window: &DesktopWindow) -> Value {
+    json!({
+        "id": window.id,
+        "x_window_id": window.x_window_id,
+        "class": window.class,
+        "instance": window.instance,
+        "title": window.title,
+        "target": window.target,
+        "x": window.pos.0,
+        "y": window.pos.1,
+        "width": window.size.0,
+        "height": window.size.1,
+        "is_focused": window.is_focused,
+    })
+}
+
+fn window_from_json(value: &Value) -> Result<DesktopWindow> {
+    let string = |key: &str| value.get(key).and_then(Value::as_str).map(String::from);
+    let int = |key: &str| {
+        value
+            .get(key)
+            .and_then(Value::as_i64)
+            .ok_or_else(|| anyhow!("Missing {}", key))
+    };
+    let int32 = |key: &str| i32::try_from(int(key)?).map_err(|_| anyhow!("Invalid {}", key));
+    Ok(DesktopWindow {
+        id: int("id")?,
+        x_window_id: int32("x_window_id").ok(),
+        class: string("class"),
+        instance: string("instance"),
+        title: string("title"),
+        target: string("target"),
+        pos: (int32("x")?, int32("y")?),
+        size: (int32("width")?, int32("height")?),
+        is_focused: value
+            .get("is_focused")
+            .and_then(Value::as_bool)
+            .unwrap_or(false),
+    })
+}
+
+impl Cache {
+    pub fn new(source: String, active_window: Option<u32>, windows: Vec<DesktopWindow>) -> Cache {
+        Cache {
+            created: now(),
+            source,
+            active_window,
+            windows,
+        }
+    }
+
+    /// Parse a cache written by `to_json`.
+    pub fn parse(contents: &str) -> Result<Cache> {
+        let value: Value = serde_json::from_str(contents).context("Malformed JSON")?;
+        let windows = value
+            .get("windows")
+            .and_then(Value::as_array)
+            .context("Missing windows")?
+            .iter()
+            .map(window_from_json)
+            .collect::<Result<_>>()?;
+        Ok(Cache {
+            created: value
+                .get("created")
+                .and_then(Value::as_u64)
+                .context("Missing creation time")?,
+            source: value
+                .get("source")
+                .and_then(Value::as_str)
+                .context("Missing source")?
+                .to_string(),
+            active_window: value
+                .get("active_window")
+                .and_then(Value::as_u64)
+                .and_then(|w| u32::try_from(w).ok()),
+            windows,
+        })
+    }
+
+    pub fn to_json(&self) -> String {
+        json!({
+            "created": self.created,
+            "source": self.source,
+            "active_window": self.active_window,
+            "windows": self.windows.iter().map(window_to_json).collect::<Vec<_>>(),
+        })
+        .to_string()
+    }
+
+    /// Load the cache at `path` if it's a regular file of ours that only we can write to.
+    pub fn load(path: &Path) -> Result<Cache> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .open(path)
+            .with_context(|| format!("Couldn't open cache {}", path.display()))?;
+        check_owner(&file).with_context(|| format!("Not using cache {}", path.display()))?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)
+            .with_context(|| format!("Couldn't read cache {}", path.display()))?;
+        Cache::parse(&contents).with_context(|| format!("Couldn't parse cache {}", path.display()))
+    }
+
+    /// Write the cache to `path` so that only we can read it, titles can be private.
+    ///
+    /// The cache is written to a new file next to `path` first and then renamed onto it, which
+    /// replaces whatever was there instead of writing through it.
+    pub fn store(&self, path: &Path) -> Result<()> {
+        let mut temp_name = path.as_os_str().to_owned();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = PathBuf::from(temp_name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .custom_flags(libc::O_NOFOLLOW)
+            .mode(0o600)
+            .open(&temp_path)
+            .with_context(|| format!("Couldn't create cache {}", temp_path.display()))?;
+        let written = file
+            .write_all(self.to_json().as_bytes())
+            .with_context(|| format!("Couldn't write cache {}", temp_path.display()))
+            .and_then(|()| {
+                fs::rename(&temp_path, path)
+                    .with_context(|| format!("Couldn't replace cache {}", path.display()))
+            });
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        written
+    }
+
+    /// The cached windows unless they are older than `ttl` at `now`, were found for another
+    /// `source` or another window than `active_window` was active back then.
+    pub fn into_windows(
+        self,
+        now: u64,
+        ttl: Duration,
+        source: &str,
+        active_window: Option<u32>,
+    ) -> Option<Vec<DesktopWindow>> {
+        let fresh = now.saturating_sub(self.created) <= ttl.as_millis() as u64;
+        (fresh && self.source == source && self.active_window == active_window)
+            .then_some(self.windows)
+    }
+}
+
+/// Fail unless `file` is a regular file owned by us that nobody else can write to.
+fn check_owner(file: &File) -> Result<()> {
+    let metadata = file.metadata().context("Couldn't get metadata")?;
+    if !metadata.is_file() {
+        bail!("Not a regular file");
+    }
+    // SAFETY: geteuid has no preconditions and can't fail.
+    let uid = unsafe { libc::geteuid() };
+    if metadata.uid() != uid {
+        bail!("Owned by uid {} instead of {}", metadata.uid(), uid);
+    }
+    if metadata.mode() & 0o022 != 0 {
+        bail!("Writable by others");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window() -> DesktopWindow {
+        DesktopWindow {
+            id: 94,
+            x_window_id: Some(0x1200003),
+            class: Some("Alacritty".to_string()),
+            instance: None,
+            title: Some("vim".to_string()),
+            target: None,
+            pos: (10, 20),
+            size: (800, 600),
+            is_focused: true,
+        }
+    }
+
+    #[test]
+    fn test_cache_round_trip() {
+        let cache = Cache::new("i3".to_string(), Some(0x1200003), vec![window()]);
+        let cache = Cache::parse(&cache.to_json()).unwrap();
+        assert_eq!(cache.source, "i3");
+        assert_eq!(cache.active_window, Some(0x1200003));
+        let windows = cache.windows;
+        assert_eq!(windows.len(), 1);
+        assert_eq!(windows[0].id, 94);
+        assert_eq!(windows[0].x_window_id, Some(0x1200003));
+        assert_eq!(windows[0].class.as_deref(), Some("Alacritty"));
+        assert_eq!(windows[0].instance, None);
+        assert_eq!(windows[0].pos, (10, 20));
+        assert_eq!(windows[0].size, (800, 600));
+        assert!(windows[0].is_focused);
+        assert!(Cache::parse("{\"windows\": []}").is_err());
+    }
+
+    #[test]
+    fn test_cache_store_load() {
+        let dir = env::temp_dir().join(format!("wmfocus-test-cache-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("windows.json");
+        let target = dir.join("target");
+        fs::write(&target, "untouched").unwrap();
+
+        // A symlink in place of the cache is replaced rather than written through or read.
+        std::os::unix::fs::symlink(&target, &path).unwrap();
+        assert!(Cache::load(&path).is_err());
+        let cache = Cache::new("i3".to_string(), None, vec![window()]);
+        cache.store(&path).unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "untouched");
+        assert!(!fs::symlink_metadata(&path).unwrap().is_symlink());
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        assert_eq!(Cache::load(&path).unwrap().windows.len(), 1);
+
+        // Others mustn't be able to slip in windows of their own.
+        let mut permissions = fs::metadata(&path).unwrap().permissions();
+        std::os::unix::fs::PermissionsExt::set_mode(&mut permissions, 0o622);
+        fs::set_permissions(&path, permissions).unwrap();
+        assert!(Cache::load(&path).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_cache_validity() {
+        let cache = || Cache {
+            created: 10_000,
+            source: "i3".to_string(),
+            active_window: Some(1),
+            windows: vec![window()],
+        };
+        let ttl = Duration::from_secs(2);
+        assert!(cache().into_windows(11_000, ttl, "i3", Some(1)).is_some());
+        assert!(cache().into_windows(13_000, ttl, "i3", Some(1)).is_none());
+        assert!(cache().into_windows(11_000, ttl, "ewmh", Some(1)).is_none());
+        assert!(cache().into_windows(11_000, ttl, "i3", Some(2)).is_none());
+        assert!(cache().into_windows(11_000, ttl, "i3", None).is_none());
+    }
+}
@@ -0,0 +1,660 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use clap::{ArgMatches, CommandFactory, ValueEnum};
+use log::info;
+
+use crate::args::{
+    self, Action, Antialias, AppConfig, BackendSelection, ClassColor, FocusMethod, FontConfig,
+    FontSlant, FontUnit, FontWeight, GeometrySource, HintAnchor, HintLength, HintOrder,
+    HorizontalAlign, InputMethod, KeyAlias, LegendPosition, Margin, MatchBy, MonitorSelection,
+    Offset, Pin, PrintFormat, Renderer, TextDirection, VerticalAlign, WindowType,
+    WorkspaceSelection,
+};
+use crate::error::{Error, Result};
+use crate::geometry::Rect;
+use crate::template::Template;
+use crate::utils;
+
+type Color = (f64, f64, f64, f64);
+
+/// Values read from a TOML config file.
+///
+/// The keys mirror the long names of the CLI flags. Every value is optional; missing values are
+/// left to the CLI defaults.
+#[derive(Debug, Default)]
+pub struct ConfigFile {
+    font: Option<FontConfig>,
+    hint_chars: Option<String>,
+    margin: Option<Margin>,
+    text_color: Option<Color>,
+    /// Whether `textcolor` is `auto`, `text_color` is black then.
+    auto_text_color: bool,
+    text_color_alt: Option<Color>,
+    bg_color: Option<Color>,
+    text_color_current: Option<Color>,
+    text_color_current_alt: Option<Color>,
+    bg_color_current: Option<Color>,
+    horizontal_align: Option<HorizontalAlign>,
+    vertical_align: Option<VerticalAlign>,
+    fill: Option<bool>,
+}
+
+/// Default location of the config file, `$XDG_CONFIG_HOME/wmfocus/config.toml`.
+pub fn default_path() -> Option<PathBuf> {
+    let config_home = env::var_os("XDG_CONFIG_HOME")
+        .filter(|p| !p.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config_home.join("wmfocus").join("config.toml"))
+}
+
+fn as_str<'a>(key: &str, value: &'a toml::Value) -> Result<&'a str> {
+    value
+        .as_str()
+        .ok_or_else(|| Error::wrong_type(key, "a string"))
+}
+
+fn as_color(key: &str, value: &toml::Value) -> Result<Color> {
+    let s = as_str(key, value)?;
+    args::parse_color(s).map_err(|e| Error::invalid_value(key, format!("'{s}': {e}")))
+}
+
+impl ConfigFile {
+    /// Parse the contents of a config file.
+    pub fn parse(contents: &str) -> Result<ConfigFile> {
+        let table: toml::Table = contents.parse()?;
+        let mut config = ConfigFile::default();
+        for (key, value) in &table {
+            match key.as_str() {
+                "font" => {
+                    config.font = Some(
+                        args::parse_truetype_font(as_str(key, value)?)
+                            .map_err(|e| Error::invalid_value(key, format!("{e:#}")))?,
+                    )
+                }
+                "chars" => {
+                    config.hint_chars = Some(
+                        args::parse_hint_chars(as_str(key, value)?)
+                            .map_err(|e| Error::invalid_value(key, e))?,
+                    )
+                }
+                "margin" => {
+                    let margin = match value {
+                        toml::Value::Float(f) => f.to_string(),
+                        toml::Value::Integer(i) => i.to_string(),
+                        toml::Value::String(s) => s.clone(),
+                        _ => {
+                            return Err(Error::wrong_type(key, "a number or pixels like \"12px\""))
+                        }
+                    };
+                    config.margin = Some(
+                        args::parse_margin(&margin).map_err(|e| Error::invalid_value(key, e))?,
+                    );
+                }
+                "textcolor" => {
+                    let s = as_str(key, value)?;
+                    config.text_color = Some(
+                        args::parse_text_color(s)
+                            .map_err(|e| Error::invalid_value(key, format!("'{s}': {e}")))?,
+                    );
+                    config.auto_text_color = args::is_auto_text_color(s);
+                }
+                "textcoloralt" => config.text_color_alt = Some(as_color(key, value)?),
+                "bgcolor" => config.bg_color = Some(as_color(key, value)?),
+                "textcolorcurrent" => config.text_color_current = Some(as_color(key, value)?),
+                "textcolorcurrentalt" => {
+                    config.text_color_current_alt = Some(as_color(key, value)?)
+                }
+                "bgcolorcurrent" => config.bg_color_current = Some(as_color(key, value)?),
+                "halign" => {
+                    config.horizontal_align = Some(
+                        HorizontalAlign::from_str(as_str(key, value)?, true)
+                            .map_err(|e| Error::invalid_value(key, e))?,
+                    )
+                }
+                "valign" => {
+                    config.vertical_align = Some(
+                        VerticalAlign::from_str(as_str(key, value)?, true)
+                            .map_err(|e| Error::invalid_value(key, e))?,
+                    )
+                }
+                "fill" => {
+                    config.fill = Some(
+                        value
+                            .as_bool()
+                            .ok_or_else(|| Error::wrong_type(key, "a boolean"))?,
+                    )
+                }
+                _ => return Err(Error::UnknownKey(key.clone())),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Load the config file at `path`.
+    pub fn load(path: &Path) -> Result<ConfigFile> {
+        info!("Loading config file {}", path.display());
+        let contents = fs::read_to_string(path).map_err(|source| Error::ReadConfig {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        ConfigFile::parse(&contents).map_err(|source| Error::ParseConfig {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    /// Take the values of all options a config file can set from `config`.
+    pub fn from_app_config(config: &AppConfig) -> ConfigFile {
+        ConfigFile {
+            font: Some(config.font.clone()),
+            hint_chars: Some(config.hint_chars.clone()),
+            margin: Some(config.margin),
+            text_color: Some(config.text_color),
+            auto_text_color: false,
+            text_color_alt: Some(config.text_color_alt),
+            bg_color: Some(config.bg_color),
+            text_color_current: Some(config.text_color_current),
+            text_color_current_alt: Some(config.text_color_current_alt),
+            bg_color_current: Some(config.bg_color_current),
+            horizontal_align: Some(config.horizontal_align),
+            vertical_align: Some(config.vertical_align),
+            fill: Some(config.fill),
+        }
+    }
+
+    /// Serialize the values of this file to TOML that `parse` reads back.
+    pub fn to_toml(&self) -> String {
+        let mut table = toml::Table::new();
+        let mut insert = |key: &str, value: Option<toml::Value>| {
+            if let Some(value) = value {
+                table.insert(key.to_string(), value);
+            }
+        };
+        let color = |color: Option<Color>| color.map(|c| args::format_color(c).into());
+        insert(
+            "font",
+            self.font
+                .as_ref()
+                .map(|f| format!("{}:{}", f.font_family, f.font_size).into()),
+        );
+        insert("chars", self.hint_chars.clone().map(toml::Value::from));
+        insert(
+            "margin",
+            self.margin.map(|margin| match margin {
+                // Go through the f32's shortest representation to not print noise digits.
+                Margin::Factor(factor) => {
+                    toml::Value::Float(factor.to_string().parse().unwrap_or(f64::from(factor)))
+                }
+                Margin::Pixels(pixels) => format!("{pixels}px").into(),
+            }),
+        );
+        if self.auto_text_color {
+            insert("textcolor", Some(args::AUTO_TEXT_COLOR.into()));
+        } else {
+            insert("textcolor", color(self.text_color));
+        }
+        insert("textcoloralt", color(self.text_color_alt));
+        insert("bgcolor", color(self.bg_color));
+        insert("textcolorcurrent", color(self.text_color_current));
+        insert("textcolorcurrentalt", color(self.text_color_current_alt));
+        insert("bgcolorcurrent", color(self.bg_color_current));
+        insert(
+            "halign",
+            self.horizontal_align
+                .and_then(|a| a.to_possible_value())
+                .map(|v| v.get_name().into()),
+        );
+        insert(
+            "valign",
+            self.vertical_align
+                .and_then(|a| a.to_possible_value())
+                .map(|v| v.get_name().into()),
+        );
+        insert("fill", self.fill.map(toml::Value::from));
+        table.to_string()
+    }
+
+    /// Whether the text color should be picked to be readable on the background.
+    pub fn auto_text_color(&self) -> bool {
+        self.auto_text_color
+    }
+
+    /// Apply the values of this file onto `config` for all options that weren't explicitly set on
+    /// the command line or in the environment.
+    pub fn apply(self, config: &mut AppConfig, matches: &ArgMatches) {
+        macro_rules! apply {
+            ($field:ident) => {
+                if let Some(value) = self.$field {
+                    if !args::is_explicit(matches, stringify!($field)) {
+                        config.$field = value;
+                    }
+                }
+            };
+        }
+
+        apply!(font);
+        apply!(hint_chars);
+        apply!(margin);
+        apply!(text_color);
+        apply!(text_color_alt);
+        apply!(bg_color);
+        apply!(text_color_current);
+        apply!(text_color_current_alt);
+        apply!(bg_color_current);
+        apply!(horizontal_align);
+        apply!(vertical_align);
+        apply!(fill);
+    }
+}
+
+/// An option as it is written on the command line, for `--dump-config`.
+///
+/// `None` for options that aren't set.
+trait DumpValue {
+    fn dump_value(&self) -> Option<toml::Value>;
+}
+
+macro_rules! dump_as_string {
+    ($($ty:ty),* $(,)?) => {
+        $(impl DumpValue for $ty {
+            fn dump_value(&self) -> Option<toml::Value> {
+                Some(self.to_string().into())
+            }
+        })*
+    };
+}
+
+macro_rules! dump_as_integer {
+    ($($ty:ty),* $(,)?) => {
+        $(impl DumpValue for $ty {
+            fn dump_value(&self) -> Option<toml::Value> {
+                i64::try_from(*self).ok().map(toml::Value::Integer)
+            }
+        })*
+    };
+}
+
+macro_rules! dump_value_enum {
+    ($($ty:ty),* $(,)?) => {
+        $(impl DumpValue for $ty {
+            fn dump_value(&self) -> Option<toml::Value> {
+                self.to_possible_value().map(|v| v.get_name().into())
+            }
+        })*
+    };
+}
+
+dump_as_string!(String, Template, utils::Sequence);
+dump_as_integer!(u8, u32, u64, usize);
+dump_value_enum!(
+    Action,
+    Antialias,
+    BackendSelection,
+    FocusMethod,
+    FontSlant,
+    FontUnit,
+    FontWeight,
+    GeometrySource,
+    HintAnchor,
+    HintLength,
+    HintOrder,
+    InputMethod,
+    LegendPosition,
+    MatchBy,
+    PrintFormat,
+    Renderer,
+    TextDirection,
+    WindowType,
+    WorkspaceSelection,
+);
+
+impl DumpValue for bool {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some((*self).into())
+    }
+}
+
+impl DumpValue for f64 {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some((*self).into())
+    }
+}
+
+impl DumpValue for Color {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(args::format_color(*self).into())
+    }
+}
+
+impl DumpValue for PathBuf {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(self.display().to_string().into())
+    }
+}
+
+impl DumpValue for Offset {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(format!("{},{}", self.x, self.y).into())
+    }
+}
+
+impl DumpValue for Rect {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(format!("{},{},{},{}", self.x, self.y, self.w, self.h).into())
+    }
+}
+
+impl DumpValue for KeyAlias {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(format!("{}={}", self.from, self.to).into())
+    }
+}
+
+impl DumpValue for Pin {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(format!("{}={}", self.class, self.hint).into())
+    }
+}
+
+impl DumpValue for ClassColor {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(format!("{}={}", self.class, args::format_color(self.color)).into())
+    }
+}
+
+impl DumpValue for MonitorSelection {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(
+            match self {
+                MonitorSelection::All => "all",
+                MonitorSelection::Current => "current",
+                MonitorSelection::Primary => "primary",
+                MonitorSelection::Name(name) => name,
+            }
+            .into(),
+        )
+    }
+}
+
+impl<T: DumpValue> DumpValue for Option<T> {
+    fn dump_value(&self) -> Option<toml::Value> {
+        self.as_ref().and_then(T::dump_value)
+    }
+}
+
+impl<T: DumpValue> DumpValue for Vec<T> {
+    fn dump_value(&self) -> Option<toml::Value> {
+        Some(
+            self.iter()
+                .filter_map(T::dump_value)
+                .collect::<Vec<_>>()
+                .into(),
+        )
+    }
+}
+
+/// Put `table` into comments below `heading` so that the config file skips it.
+fn commented(heading: &str, table: &toml::Table) -> String {
+    let mut out = String::new();
+    for line in heading.lines() {
+        out.push_str(&format!("# {line}\n"));
+    }
+    for line in table.to_string().lines() {
+        out.push_str(&format!("# {line}\n"));
+    }
+    out
+}
+
+/// All options in effect in `config`, after merging every source.
+///
+/// The options a config file can set come first and read back as a config file. All others
+/// follow in comments, along with the font file fontconfig picked.
+pub fn dump(config: &AppConfig) -> String {
+    let command = AppConfig::command();
+    let mut table = toml::Table::new();
+    let mut insert = |id: &str, value: Option<toml::Value>| {
+        let key = command
+            .get_arguments()
+            .find(|arg| arg.get_id() == id)
+            .and_then(|arg| arg.get_long())
+            .unwrap_or(id);
+        if let Some(value) = value {
+            table.insert(key.to_string(), value);
+        }
+    };
+    macro_rules! dump {
+        ($($field:ident),* $(,)?) => {
+            $(insert(stringify!($field), config.$field.dump_value());)*
+        };
+    }
+    dump!(
+        font_weight,
+        font_weight_current,
+        font_slant,
+        antialias,
+        font_unit,
+        fast_metrics,
+        dpi_scale,
+        auto_fit,
+        min_font_size,
+        font_size_from_window,
+        max_font_size,
+        ignore_case,
+        uppercase_labels,
+        text_padding,
+        theme,
+        match_color,
+        class_colors,
+        reverse_video,
+        corner_radius,
+        shadow_color,
+        shadow_offset,
+        shadow_blur,
+        dim,
+        fade_in,
+        opaque_input,
+        backend,
+        renderer,
+        border_color,
+        border_width,
+        geometry_source,
+        hint_position_relative_to,
+        legend,
+        connector_color,
+        show_title,
+        text_direction,
+        fallback_font,
+        title_max_width,
+        action,
+        focus_method,
+        raise,
+        repeat,
+        print_only,
+        list,
+        quiet,
+        verbose,
+        windows_from_stdin,
+        print_format,
+        format,
+        stdout_on_cancel,
+        offset,
+        exit_keys,
+        key_aliases,
+        hint_length,
+        max_hint_length,
+        group_by_monitor,
+        monitor_chars,
+        pins,
+        order,
+        max_windows,
+        workspace,
+        all_desktops,
+        cache,
+        monitor,
+        region,
+        class_globs,
+        instance_globs,
+        skip_class_globs,
+        same_class,
+        same_class_auto,
+        once_per_class,
+        include_types,
+        skip_minimized,
+        include_transparent,
+        include_hidden,
+        include_override_redirect,
+        case_sensitive,
+        input,
+        match_by,
+        no_bell,
+        timeout,
+        delay,
+        exit_on_focus_loss,
+        config,
+    );
+    let cancel_keys: Vec<String> = config
+        .cancel_keys
+        .iter()
+        .filter_map(|&ksym| xkeysym::name(ksym))
+        .map(|name| name.replace("XK_", ""))
+        .collect();
+    insert("cancel_keys", cancel_keys.dump_value());
+
+    let mut font = toml::Table::new();
+    if let Some(file) = args::font_file(&config.font.font_family) {
+        font.insert("font-file".to_string(), file.display().to_string().into());
+    }
+
+    format!(
+        "{}\n{}\n{}",
+        ConfigFile::from_app_config(config).to_toml(),
+        commented(
+            "Options the config file can't set, they can only be given on the command line or in\n\
+             the environment. Options that aren't set are left out.",
+            &table
+        ),
+        commented(
+            "The file fontconfig picked for the font family above.",
+            &font
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = ConfigFile::parse(
+            r##"
+            chars = "asdf"
+            margin = 0.5
+            bgcolor = "#ff0000"
+            halign = "right"
+            fill = true
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.hint_chars.as_deref(), Some("asdf"));
+        assert_eq!(config.margin, Some(Margin::Factor(0.5)));
+        assert_eq!(config.bg_color, Some((1.0, 0.0, 0.0, 1.0)));
+        assert_eq!(config.horizontal_align, Some(HorizontalAlign::Right));
+        assert_eq!(config.fill, Some(true));
+    }
+
+    #[test]
+    fn test_parse_config_auto_text_color() {
+        let config = ConfigFile::parse(r#"textcolor = "auto""#).unwrap();
+        assert!(config.auto_text_color());
+        assert_eq!(config.text_color, Some((0.0, 0.0, 0.0, 1.0)));
+        assert_eq!(config.to_toml().trim(), r#"textcolor = "auto""#);
+
+        let config = ConfigFile::parse(r##"textcolor = "#ffffff""##).unwrap();
+        assert!(!config.auto_text_color());
+        assert!(ConfigFile::parse(r#"textcolor = "automatic""#).is_err());
+    }
+
+    #[test]
+    fn test_to_toml_round_trip() {
+        let contents = r##"
+            chars = "asdf"
+            margin = "12px"
+            textcolor = "rgba(10, 20, 30, 0.5)"
+            bgcolor = "#ff0000"
+            valign = "bottom"
+            fill = false
+            "##;
+        let toml = ConfigFile::parse(contents).unwrap().to_toml();
+        assert!(toml.contains("bgcolor = \"#ff0000\""));
+        assert!(toml.contains("textcolor = \"rgba(10, 20, 30, 0.5)\""));
+        assert!(!toml.contains("halign"));
+        let config = ConfigFile::parse(&toml).unwrap();
+        assert_eq!(config.hint_chars.as_deref(), Some("asdf"));
+        assert_eq!(config.margin, Some(Margin::Pixels(12)));
+        assert_eq!(config.vertical_align, Some(VerticalAlign::Bottom));
+        assert_eq!(config.fill, Some(false));
+
+        let factor = ConfigFile::parse("margin = 0.3").unwrap().to_toml();
+        assert_eq!(factor.trim(), "margin = 0.3");
+    }
+
+    #[test]
+    fn test_dump() {
+        let config = args::test_config(&[
+            "wmfocus",
+            "--chars",
+            "asdf",
+            "--pin",
+            "firefox=f",
+            "--print-only",
+            "--format",
+            r"{id}\t{class}",
+            "--exit-key",
+            "q",
+        ]);
+        let dump = dump(&config);
+        assert!(dump.contains("chars = \"asdf\"\n"));
+        assert!(dump.contains("# pin = [\"firefox=f\"]\n"));
+        assert!(dump.contains("# format = '{id}\\t{class}'\n"));
+        assert!(dump.contains("# action = \"focus\"\n"));
+        assert!(dump.contains("\"q\""));
+        assert!(!dump.contains("max-hint-length"));
+
+        // Everything the config file can't read back is commented out. The font is left out as
+        // parsing it loads the font.
+        let without_font: String = dump
+            .lines()
+            .filter(|line| !line.starts_with("font ="))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        let parsed = ConfigFile::parse(&without_font).unwrap();
+        assert_eq!(parsed.hint_chars.as_deref(), Some("asdf"));
+    }
+
+    #[test]
+    fn test_parse_config_unknown_key() {
+        let err = ConfigFile::parse("colour = \"red\"").unwrap_err();
+        assert!(matches!(err, Error::UnknownKey(ref key) if key == "colour"));
+        assert!(err.to_string().contains("Unknown key 'colour'"));
+    }
+
+    #[test]
+    fn test_parse_config_wrong_type() {
+        let err = ConfigFile::parse("fill = \"yes\"").unwrap_err();
+        assert_eq!(err.to_string(), "Expected a boolean for 'fill'");
+        assert!(matches!(
+            ConfigFile::parse("chars = ").unwrap_err(),
+            Error::Toml(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_config_malformed_color() {
+        let err = ConfigFile::parse("textcolor = \"notacolor\"").unwrap_err();
+        assert!(err.to_string().contains("textcolor"));
+    }
+}
@@ -0,0 +1,89 @@
+//! Errors from talking to the X server and the window manager and from reading the config file.
+//!
+//! They are still printed through `anyhow` but can be told apart by their variant.
+
+use std::io;
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("No Xorg connection")]
+    XConnect(#[from] x11rb::errors::ConnectError),
+
+    #[error("Lost the connection to the X server")]
+    XConnection(#[from] x11rb::errors::ConnectionError),
+
+    /// The X server couldn't answer a request for `what`.
+    #[error("Couldn't get {what}")]
+    XReply {
+        what: &'static str,
+        #[source]
+        source: x11rb::errors::ReplyError,
+    },
+
+    #[error("The window manager doesn't provide {0}")]
+    MissingProperty(&'static str),
+
+    #[cfg(feature = "i3")]
+    #[error("Couldn't acquire i3 connection")]
+    I3Connect(#[from] i3ipc::EstablishError),
+
+    #[cfg(feature = "i3")]
+    #[error("Couldn't communicate with i3")]
+    I3Message(#[from] i3ipc::MessageError),
+
+    #[error("Couldn't find the node of workspace '{0}'")]
+    MissingWorkspace(String),
+
+    #[error("Couldn't read config file {}", path.display())]
+    ReadConfig {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Couldn't parse config file {}", path.display())]
+    ParseConfig {
+        path: PathBuf,
+        #[source]
+        source: Box<Error>,
+    },
+
+    #[error("Malformed TOML")]
+    Toml(#[from] toml::de::Error),
+
+    #[error("Unknown key '{0}'")]
+    UnknownKey(String),
+
+    #[error("Expected {expected} for '{key}'")]
+    WrongType { key: String, expected: &'static str },
+
+    #[error("Invalid value for '{key}': {message}")]
+    InvalidValue { key: String, message: String },
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+impl Error {
+    /// Wrap an error for `--key` or `key` in the config file.
+    pub(crate) fn invalid_value(key: &str, message: impl std::fmt::Display) -> Error {
+        Error::InvalidValue {
+            key: key.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub(crate) fn wrong_type(key: &str, expected: &'static str) -> Error {
+        Error::WrongType {
+            key: key.to_string(),
+            expected,
+        }
+    }
+
+    /// Build a closure for `map_err` that wraps a reply error for `what`.
+    pub(crate) fn reply(what: &'static str) -> impl FnOnce(x11rb::errors::ReplyError) -> Error {
+        move |source| Error::XReply { what, source }
+    }
+}
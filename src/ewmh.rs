@@ -0,0 +1,214 @@
+//! Find windows through the `_NET_CLIENT_LIST` of an EWMH compliant window manager.
+//!
+//! Unlike walking the window manager's tree, this also finds windows on other virtual desktops
+//! which many window managers unmap while the desktop isn't shown.
+
+use log::{debug, warn};
+use x11rb::connection::Connection;
+use x11rb::cookie::Cookie;
+use x11rb::properties::{WmClass, WmClassCookie};
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ClientMessageEvent, ConnectionExt, EventMask, GetGeometryReply,
+    GetPropertyReply, TranslateCoordinatesReply,
+};
+
+use crate::error::{Error, Result};
+use crate::properties::Atoms;
+use crate::DesktopWindow;
+
+/// `_NET_WM_DESKTOP` of windows that are shown on all desktops.
+const ALL_DESKTOPS: u32 = 0xFFFFFFFF;
+
+/// Read a single CARDINAL property of `window`, `name` is only used in errors.
+fn get_cardinal(
+    conn: &impl Connection,
+    window: u32,
+    property: Atom,
+    name: &'static str,
+) -> Result<Option<u32>> {
+    let reply = conn
+        .get_property(false, window, property, AtomEnum::CARDINAL, 0, 1)?
+        .reply()
+        .map_err(Error::reply(name))?;
+    Ok(reply.value32().and_then(|mut values| values.next()))
+}
+
+/// The requests for everything we need to know about a client, sent before waiting for any
+/// reply so that enumerating many clients costs a single round trip.
+struct ClientCookies<'c, C: Connection> {
+    geometry: Cookie<'c, C, GetGeometryReply>,
+    position: Cookie<'c, C, TranslateCoordinatesReply>,
+    wm_class: WmClassCookie<'c, C>,
+    net_wm_name: Cookie<'c, C, GetPropertyReply>,
+    wm_name: Cookie<'c, C, GetPropertyReply>,
+}
+
+impl<'c, C: Connection> ClientCookies<'c, C> {
+    fn send(conn: &'c C, root: u32, window: u32, atoms: &Atoms) -> Result<Self> {
+        Ok(ClientCookies {
+            geometry: conn.get_geometry(window)?,
+            position: conn.translate_coordinates(window, root, 0, 0)?,
+            wm_class: WmClass::get(conn, window)?,
+            // WM_NAME is only needed without _NET_WM_NAME but asking for both up front saves
+            // another round trip.
+            net_wm_name: conn.get_property(
+                false,
+                window,
+                atoms._NET_WM_NAME,
+                atoms.UTF8_STRING,
+                0,
+                u32::MAX,
+            )?,
+            wm_name: conn.get_property(
+                false,
+                window,
+                AtomEnum::WM_NAME,
+                AtomEnum::STRING,
+                0,
+                u32::MAX,
+            )?,
+        })
+    }
+
+    fn reply(self, window: u32) -> Result<DesktopWindow> {
+        let geometry = self.geometry.reply().map_err(Error::reply("geometry"))?;
+        let pos = self.position.reply().map_err(Error::reply("position"))?;
+        let (instance, class) = match self.wm_class.reply() {
+            Ok(wm_class) => (
+                Some(String::from_utf8_lossy(wm_class.instance()).into_owned()),
+                Some(String::from_utf8_lossy(wm_class.class()).into_owned()),
+            ),
+            Err(_) => (None, None),
+        };
+        let net_wm_name = self
+            .net_wm_name
+            .reply()
+            .map_err(Error::reply("_NET_WM_NAME"))?;
+        let wm_name = self.wm_name.reply().map_err(Error::reply("WM_NAME"))?;
+        Ok(DesktopWindow {
+            id: window.into(),
+            x_window_id: Some(window as i32),
+            class,
+            instance,
+            title: title(&net_wm_name.value, &wm_name.value),
+            target: None,
+            pos: (pos.dst_x.into(), pos.dst_y.into()),
+            size: (geometry.width.into(), geometry.height.into()),
+            is_focused: false,
+        })
+    }
+}
+
+/// The title of a window, preferring the UTF-8 `_NET_WM_NAME` over `WM_NAME`.
+fn title(net_wm_name: &[u8], wm_name: &[u8]) -> Option<String> {
+    let name = if net_wm_name.is_empty() {
+        wm_name
+    } else {
+        net_wm_name
+    };
+    Some(String::from_utf8_lossy(name).into_owned()).filter(|t| !t.is_empty())
+}
+
+/// Look up the class, title and geometry of `windows`, skipping those that went away.
+///
+/// The requests for all windows are sent before collecting any reply, which keeps enumeration
+/// fast on connections with a high latency such as X forwarded over SSH.
+pub(crate) fn describe_windows(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+    windows: &[u32],
+) -> Result<Vec<DesktopWindow>> {
+    let mut cookies = vec![];
+    for &window in windows {
+        cookies.push((window, ClientCookies::send(conn, root, window, atoms)?));
+    }
+
+    let mut desktop_windows = vec![];
+    for (window, cookies) in cookies {
+        match cookies.reply(window) {
+            Ok(desktop_window) => {
+                debug!("Found {:?}", desktop_window);
+                desktop_windows.push(desktop_window);
+            }
+            // Windows can go away while we look at them.
+            Err(e) => warn!("Skipping window 0x{:x}: {:#}", window, e),
+        }
+    }
+    Ok(desktop_windows)
+}
+
+/// Return all windows in the `_NET_CLIENT_LIST` of the window manager, on all desktops.
+pub fn get_windows(conn: &impl Connection, atoms: &Atoms, root: u32) -> Result<Vec<DesktopWindow>> {
+    let client_list = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_CLIENT_LIST,
+            AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()
+        .map_err(Error::reply("_NET_CLIENT_LIST"))?;
+    let clients: Vec<u32> = client_list
+        .value32()
+        .ok_or(Error::MissingProperty("_NET_CLIENT_LIST"))?
+        .collect();
+    let active_window = crate::utils::get_active_window(conn, atoms, root)?;
+
+    let mut windows = describe_windows(conn, atoms, root, &clients)?;
+    for window in &mut windows {
+        window.is_focused = window.x_window_id.map(|id| id as u32) == active_window;
+    }
+    Ok(windows)
+}
+
+/// Switch to the desktop that `window` is on unless it's already shown.
+pub fn switch_to_desktop_of(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+    window: u32,
+) -> Result<()> {
+    let Some(desktop) = get_cardinal(conn, window, atoms._NET_WM_DESKTOP, "_NET_WM_DESKTOP")?
+    else {
+        return Ok(());
+    };
+    let current = get_cardinal(
+        conn,
+        root,
+        atoms._NET_CURRENT_DESKTOP,
+        "_NET_CURRENT_DESKTOP",
+    )?;
+    if desktop == ALL_DESKTOPS || current == Some(desktop) {
+        return Ok(());
+    }
+    debug!("Switching to desktop {} of window 0x{:x}", desktop, window);
+    let event = ClientMessageEvent::new(
+        32,
+        root,
+        atoms._NET_CURRENT_DESKTOP,
+        [desktop, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title() {
+        assert_eq!(title(b"caf\xc3\xa9", b"cafe").as_deref(), Some("café"));
+        assert_eq!(title(b"", b"xterm").as_deref(), Some("xterm"));
+        assert_eq!(title(b"", b""), None);
+    }
+}
@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use regex::{Regex, RegexBuilder};
+
+use crate::args::{ClassColor, WindowType};
+use crate::DesktopWindow;
+
+/// Compile a shell-style `glob` into a `Regex` that matches the whole string.
+///
+/// `*` matches any number of characters and `?` matches exactly one character.
+fn compile_glob(glob: &str, case_sensitive: bool) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    for c in glob.chars() {
+        match c {
+            '*' => pattern.push_str(".*"),
+            '?' => pattern.push('.'),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .with_context(|| format!("Invalid glob '{glob}'"))
+}
+
+fn compile_globs(globs: &[String], case_sensitive: bool) -> Result<Vec<Regex>> {
+    globs
+        .iter()
+        .map(|g| compile_glob(g, case_sensitive))
+        .collect()
+}
+
+fn any_match(matchers: &[Regex], value: Option<&str>) -> bool {
+    value.is_some_and(|v| matchers.iter().any(|m| m.is_match(v)))
+}
+
+/// Decides which windows get a hint.
+#[derive(Debug, Default, Clone)]
+pub struct WindowFilter {
+    class: Vec<Regex>,
+    instance: Vec<Regex>,
+    skip_class: Vec<Regex>,
+}
+
+impl WindowFilter {
+    pub fn new(
+        class: &[String],
+        instance: &[String],
+        skip_class: &[String],
+        case_sensitive: bool,
+    ) -> Result<WindowFilter> {
+        Ok(WindowFilter {
+            class: compile_globs(class, case_sensitive)?,
+            instance: compile_globs(instance, case_sensitive)?,
+            skip_class: compile_globs(skip_class, case_sensitive)?,
+        })
+    }
+
+    /// Returns true if `window` should get a hint.
+    ///
+    /// If any class or instance filters are given, a window has to match at least one of them.
+    /// Windows matching a skipped class never get a hint.
+    pub fn matches(&self, window: &DesktopWindow) -> bool {
+        if any_match(&self.skip_class, window.class.as_deref()) {
+            return false;
+        }
+        if self.class.is_empty() && self.instance.is_empty() {
+            return true;
+        }
+        any_match(&self.class, window.class.as_deref())
+            || any_match(&self.instance, window.instance.as_deref())
+    }
+}
+
+/// Picks the background color of hints by the class of their window.
+#[derive(Debug, Default, Clone)]
+pub struct ClassPalette {
+    colors: Vec<(Regex, (f64, f64, f64, f64))>,
+}
+
+impl ClassPalette {
+    pub fn new(class_colors: &[ClassColor], case_sensitive: bool) -> Result<ClassPalette> {
+        let colors = class_colors
+            .iter()
+            .map(|c| Ok((compile_glob(&c.class, case_sensitive)?, c.color)))
+            .collect::<Result<_>>()?;
+        Ok(ClassPalette { colors })
+    }
+
+    /// The color of the first class matching `window`, if any.
+    pub fn color_for(&self, window: &DesktopWindow) -> Option<(f64, f64, f64, f64)> {
+        let class = window.class.as_deref()?;
+        self.colors
+            .iter()
+            .find(|(glob, _)| glob.is_match(class))
+            .map(|(_, color)| *color)
+    }
+}
+
+/// Returns true if a window with the `_NET_WM_WINDOW_TYPE`s `types` should be skipped.
+///
+/// `types` are the atom names without the `_NET_WM_WINDOW_TYPE_` prefix, eg `DOCK`. Windows
+/// without any type are normal windows.
+pub fn is_skipped_type(types: &[String], include: &[WindowType]) -> bool {
+    WindowType::value_variants()
+        .iter()
+        .filter(|t| !include.contains(t))
+        .filter_map(|t| t.to_possible_value())
+        .any(|t| {
+            types
+                .iter()
+                .any(|name| t.get_name().eq_ignore_ascii_case(name))
+        })
+}
+
+/// Windows with a lower `_NET_WM_WINDOW_OPACITY` than this are considered invisible.
+const MIN_OPACITY: f64 = 0.01;
+
+/// Returns true if a window with the `_NET_WM_WINDOW_OPACITY` `opacity` can't be seen.
+///
+/// Windows without an opacity are opaque.
+pub fn is_transparent(opacity: Option<f64>) -> bool {
+    opacity.is_some_and(|opacity| opacity < MIN_OPACITY)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(class: Option<&str>, instance: Option<&str>) -> DesktopWindow {
+        DesktopWindow {
+            id: 1,
+            x_window_id: Some(1),
+            class: class.map(String::from),
+            instance: instance.map(String::from),
+            title: None,
+            target: None,
+            pos: (0, 0),
+            size: (100, 100),
+            is_focused: false,
+        }
+    }
+
+    fn strings(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_glob() {
+        let glob = compile_glob("Fire*", false).unwrap();
+        assert!(glob.is_match("firefox"));
+        assert!(!glob.is_match("Thunderbird"));
+        let glob = compile_glob("x?erm", true).unwrap();
+        assert!(glob.is_match("xterm"));
+        assert!(!glob.is_match("XTerm"));
+        assert!(!glob.is_match("uxterm"));
+        assert!(compile_glob("a.b", false).unwrap().is_match("a.b"));
+        assert!(!compile_glob("a.b", false).unwrap().is_match("axb"));
+    }
+
+    #[test]
+    fn test_no_filters() {
+        let filter = WindowFilter::default();
+        assert!(filter.matches(&window(None, None)));
+        assert!(filter.matches(&window(Some("Alacritty"), None)));
+    }
+
+    #[test]
+    fn test_class_and_instance_filters() {
+        let filter = WindowFilter::new(
+            &strings(&["alacritty"]),
+            &strings(&["navigator"]),
+            &[],
+            false,
+        )
+        .unwrap();
+        assert!(filter.matches(&window(Some("Alacritty"), None)));
+        assert!(filter.matches(&window(Some("firefox"), Some("Navigator"))));
+        assert!(!filter.matches(&window(Some("Thunderbird"), Some("Mail"))));
+        assert!(!filter.matches(&window(None, None)));
+    }
+
+    #[test]
+    fn test_skip_class() {
+        let filter = WindowFilter::new(&[], &[], &strings(&["Steam*"]), false).unwrap();
+        assert!(!filter.matches(&window(Some("steamwebhelper"), None)));
+        assert!(filter.matches(&window(Some("firefox"), None)));
+    }
+
+    #[test]
+    fn test_class_palette() {
+        let blue = (0.0, 0.0, 1.0, 1.0);
+        let green = (0.0, 1.0, 0.0, 1.0);
+        let class_color = |class: &str, color| ClassColor {
+            class: class.to_string(),
+            color,
+        };
+        let palette = ClassPalette::new(
+            &[class_color("firefox", blue), class_color("*", green)],
+            false,
+        )
+        .unwrap();
+        assert_eq!(
+            palette.color_for(&window(Some("Firefox"), None)),
+            Some(blue)
+        );
+        assert_eq!(palette.color_for(&window(Some("xterm"), None)), Some(green));
+        assert_eq!(palette.color_for(&window(None, None)), None);
+        assert_eq!(
+            ClassPalette::default().color_for(&window(Some("xterm"), None)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_skipped_types() {
+        assert!(!is_skipped_type(&[], &[]));
+        assert!(!is_skipped_type(&strings(&["NORMAL"]), &[]));
+        assert!(is_skipped_type(&strings(&["DOCK"]), &[]));
+        assert!(is_skipped_type(&strings(&["NOTIFICATION"]), &[]));
+        assert!(!is_skipped_type(&strings(&["DOCK"]), &[WindowType::Dock]));
+        assert!(is_skipped_type(
+            &strings(&["DOCK", "MENU"]),
+            &[WindowType::Dock]
+        ));
+    }
+
+    #[test]
+    fn test_is_transparent() {
+        assert!(!is_transparent(None));
+        assert!(is_transparent(Some(0.0)));
+        assert!(is_transparent(Some(0.001)));
+        assert!(!is_transparent(Some(0.5)));
+        assert!(!is_transparent(Some(1.0)));
+    }
+}
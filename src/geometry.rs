@@ -0,0 +1,335 @@
+//! Rectangle math used to place hints.
+//!
+//! All coordinates are in pixels with the origin in the top-left corner of the screen.
+
+/// An axis-aligned rectangle.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub w: i32,
+    pub h: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Rect {
+        Rect { x, y, w, h }
+    }
+
+    /// Area in pixels, 0 for empty rects.
+    pub fn area(&self) -> i64 {
+        if self.w <= 0 || self.h <= 0 {
+            return 0;
+        }
+        i64::from(self.w) * i64::from(self.h)
+    }
+
+    /// Returns true if `self` and `other` overlap. Rects that only touch don't overlap.
+    pub fn intersects(&self, other: &Rect) -> bool {
+        self.x < other.x + other.w
+            && self.x + self.w > other.x
+            && self.y < other.y + other.h
+            && self.y + self.h > other.y
+    }
+
+    /// Returns true if `other` lies completely within `self`.
+    ///
+    /// A rect that is identical to `self` counts as contained.
+    pub fn contains(&self, other: &Rect) -> bool {
+        other.x >= self.x
+            && other.y >= self.y
+            && other.x + other.w <= self.x + self.w
+            && other.y + other.h <= self.y + self.h
+    }
+
+    /// Returns true if more than half of the area of `self` lies within `other`.
+    pub fn mostly_within(&self, other: &Rect) -> bool {
+        let inside = self.intersection(other).map_or(0, |overlap| overlap.area());
+        2 * inside > self.area()
+    }
+
+    /// Returns true if the point `(x, y)` lies within `self`.
+    pub fn contains_point(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+
+    /// The part of `self` that is also covered by `other`, if any.
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        if !self.intersects(other) {
+            return None;
+        }
+        let (left, top) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.w).min(other.x + other.w);
+        let bottom = (self.y + self.h).min(other.y + other.h);
+        Some(Rect::new(left, top, right - left, bottom - top))
+    }
+
+    /// Grow `self` by the given number of pixels on each side.
+    pub fn expand(&self, left: i32, right: i32, top: i32, bottom: i32) -> Rect {
+        Rect::new(
+            self.x - left,
+            self.y - top,
+            self.w + left + right,
+            self.h + top + bottom,
+        )
+    }
+
+    /// Move `self` so that it lies within `container`.
+    ///
+    /// If `self` is larger than `container`, it is aligned with the top-left corner of
+    /// `container`.
+    pub fn clamp_into(&self, container: &Rect) -> Rect {
+        let x = self
+            .x
+            .min(container.x + container.w - self.w)
+            .max(container.x);
+        let y = self
+            .y
+            .min(container.y + container.h - self.h)
+            .max(container.y);
+        Rect::new(x, y, self.w, self.h)
+    }
+
+    /// Cut `cut` out of `self` and return the up to four rects that are left over.
+    pub fn subtract(&self, cut: &Rect) -> Vec<Rect> {
+        let Some(inner) = self.intersection(cut) else {
+            return vec![*self];
+        };
+        let bottom = inner.y + inner.h;
+        let right = inner.x + inner.w;
+        let pieces = [
+            // Above and below the cut span the whole width.
+            Rect::new(self.x, self.y, self.w, inner.y - self.y),
+            Rect::new(self.x, bottom, self.w, self.y + self.h - bottom),
+            // Left and right of the cut only span its height.
+            Rect::new(self.x, inner.y, inner.x - self.x, inner.h),
+            Rect::new(right, inner.y, self.x + self.w - right, inner.h),
+        ];
+        pieces.into_iter().filter(|r| r.area() > 0).collect()
+    }
+
+    /// Area of `self` that isn't covered by any of `covers`.
+    pub fn visible_area(&self, covers: &[Rect]) -> i64 {
+        let mut visible = vec![*self];
+        for cover in covers {
+            visible = visible.iter().flat_map(|r| r.subtract(cover)).collect();
+            if visible.is_empty() {
+                return 0;
+            }
+        }
+        visible.iter().map(Rect::area).sum()
+    }
+}
+
+/// Rects covering a line of `thickness` pixels from `from` to `to`, both ends included.
+///
+/// The line is walked pixel by pixel along its longer axis and every run of pixels on the same
+/// row or column becomes one rect.
+pub fn line_region(from: (i32, i32), to: (i32, i32), thickness: i32) -> Vec<Rect> {
+    let half = thickness / 2;
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let steps = dx.abs().max(dy.abs());
+    if steps == 0 {
+        return vec![Rect::new(
+            from.0 - half,
+            from.1 - half,
+            thickness,
+            thickness,
+        )];
+    }
+    // Work in (major, minor) coordinates and swap them back for steep lines.
+    let steep = dy.abs() > dx.abs();
+    let (major, minor, d_major, d_minor) = if steep {
+        (from.1, from.0, dy, dx)
+    } else {
+        (from.0, from.1, dx, dy)
+    };
+    let run = |start: i32, end: i32, minor: i32| {
+        let (low, length) = (start.min(end), (end - start).abs() + 1);
+        if steep {
+            Rect::new(minor - half, low, thickness, length)
+        } else {
+            Rect::new(low, minor - half, length, thickness)
+        }
+    };
+
+    let mut rects = vec![];
+    let mut run_start = major;
+    let mut run_minor = minor;
+    for i in 1..=steps {
+        let m = major + i * d_major.signum();
+        let n = minor + (f64::from(i * d_minor) / f64::from(steps)).round() as i32;
+        if n != run_minor {
+            rects.push(run(run_start, m - d_major.signum(), run_minor));
+            run_start = m;
+            run_minor = n;
+        }
+    }
+    rects.push(run(run_start, major + d_major, run_minor));
+    rects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_intersects() {
+        assert!(Rect::new(1905, 705, 31, 82).intersects(&Rect::new(1905, 723, 38, 64)));
+    }
+
+    #[test]
+    fn test_no_intersect() {
+        assert!(!Rect::new(1905, 705, 31, 82).intersects(&Rect::new(2000, 723, 38, 64)));
+        // Touching edges don't count.
+        assert!(!Rect::new(0, 0, 10, 10).intersects(&Rect::new(10, 0, 10, 10)));
+    }
+
+    #[test]
+    fn test_area() {
+        assert_eq!(Rect::new(5, 5, 10, 20).area(), 200);
+        assert_eq!(Rect::new(5, 5, 0, 20).area(), 0);
+        assert_eq!(Rect::new(5, 5, -10, 20).area(), 0);
+    }
+
+    #[test]
+    fn test_intersection() {
+        let rect = Rect::new(0, 0, 100, 100);
+        assert_eq!(
+            rect.intersection(&Rect::new(50, 60, 100, 100)),
+            Some(Rect::new(50, 60, 50, 40))
+        );
+        assert_eq!(rect.intersection(&rect), Some(rect));
+        assert_eq!(rect.intersection(&Rect::new(200, 0, 10, 10)), None);
+    }
+
+    #[test]
+    fn test_mostly_within() {
+        let region = Rect::new(0, 0, 1000, 1000);
+        assert!(Rect::new(100, 100, 200, 200).mostly_within(&region));
+        assert!(Rect::new(900, 0, 150, 100).mostly_within(&region));
+        assert!(!Rect::new(950, 0, 100, 100).mostly_within(&region));
+        assert!(!Rect::new(2000, 0, 100, 100).mostly_within(&region));
+        assert!(!Rect::new(100, 100, 0, 0).mostly_within(&region));
+    }
+
+    #[test]
+    fn test_contains_inside() {
+        assert!(Rect::new(0, 0, 1920, 1080).contains(&Rect::new(100, 100, 50, 50)));
+    }
+
+    #[test]
+    fn test_contains_identical() {
+        let rect = Rect::new(10, 20, 300, 400);
+        assert!(rect.contains(&rect));
+    }
+
+    #[test]
+    fn test_contains_straddling_edges() {
+        let container = Rect::new(100, 100, 200, 200);
+        // Left
+        assert!(!container.contains(&Rect::new(90, 150, 50, 50)));
+        // Top
+        assert!(!container.contains(&Rect::new(150, 90, 50, 50)));
+        // Right
+        assert!(!container.contains(&Rect::new(280, 150, 50, 50)));
+        // Bottom
+        assert!(!container.contains(&Rect::new(150, 280, 50, 50)));
+    }
+
+    #[test]
+    fn test_contains_vertical_uses_container_y() {
+        // The rect sits above the container but to the right of its x-origin, so it would be
+        // misclassified if y was compared against x.
+        assert!(!Rect::new(0, 500, 1920, 500).contains(&Rect::new(100, 100, 50, 50)));
+        assert!(Rect::new(500, 0, 500, 1080).contains(&Rect::new(600, 100, 50, 50)));
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let rect = Rect::new(100, 100, 200, 200);
+        assert!(rect.contains_point(100, 100));
+        assert!(rect.contains_point(299, 299));
+        assert!(!rect.contains_point(300, 150));
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            Rect::new(100, 100, 200, 200).expand(1, 2, 20, 3),
+            Rect::new(99, 80, 203, 223)
+        );
+        assert_eq!(
+            Rect::new(5, 5, 10, 10).expand(0, 0, 0, 0),
+            Rect::new(5, 5, 10, 10)
+        );
+    }
+
+    #[test]
+    fn test_clamp_into() {
+        let container = Rect::new(100, 100, 200, 200);
+        let clamp = |x, y, w, h| {
+            let clamped = Rect::new(x, y, w, h).clamp_into(&container);
+            (clamped.x, clamped.y)
+        };
+        assert_eq!(clamp(150, 150, 50, 50), (150, 150));
+        assert_eq!(clamp(50, 280, 50, 50), (100, 250));
+        assert_eq!(clamp(280, 50, 50, 50), (250, 100));
+        assert_eq!(clamp(150, 150, 300, 300), (100, 100));
+    }
+
+    #[test]
+    fn test_subtract() {
+        let rect = Rect::new(0, 0, 100, 100);
+        assert_eq!(rect.subtract(&Rect::new(200, 200, 10, 10)), vec![rect]);
+        assert!(rect.subtract(&Rect::new(-10, -10, 200, 200)).is_empty());
+        // A cut in the middle leaves a frame of four rects.
+        let frame = rect.subtract(&Rect::new(25, 25, 50, 50));
+        assert_eq!(frame.len(), 4);
+        let area: i64 = frame.iter().map(Rect::area).sum();
+        assert_eq!(area, 100 * 100 - 50 * 50);
+        // Cutting off the right half.
+        assert_eq!(
+            rect.subtract(&Rect::new(50, -10, 100, 200)),
+            vec![Rect::new(0, 0, 50, 100)]
+        );
+    }
+
+    #[test]
+    fn test_visible_area() {
+        let rect = Rect::new(0, 0, 100, 100);
+        let left = Rect::new(0, 0, 50, 100);
+        let right = Rect::new(50, 0, 50, 100);
+        assert_eq!(rect.visible_area(&[]), 10000);
+        assert_eq!(rect.visible_area(&[right]), 5000);
+        // Overlapping covers don't count twice.
+        assert_eq!(rect.visible_area(&[right, Rect::new(25, 0, 50, 100)]), 2500);
+        assert_eq!(rect.visible_area(&[left, right]), 0);
+    }
+
+    #[test]
+    fn test_line_region() {
+        assert_eq!(
+            line_region((0, 0), (10, 0), 2),
+            vec![Rect::new(0, -1, 11, 2)]
+        );
+        assert_eq!(
+            line_region((5, 10), (5, 0), 2),
+            vec![Rect::new(4, 0, 2, 11)]
+        );
+        assert_eq!(line_region((3, 3), (3, 3), 2), vec![Rect::new(2, 2, 2, 2)]);
+        assert_eq!(
+            line_region((0, 0), (2, 2), 1),
+            vec![
+                Rect::new(0, 0, 1, 1),
+                Rect::new(1, 1, 1, 1),
+                Rect::new(2, 2, 1, 1)
+            ]
+        );
+        // A shallow line going left and up steps down one row halfway.
+        assert_eq!(
+            line_region((10, 1), (0, 0), 1),
+            vec![Rect::new(6, 1, 5, 1), Rect::new(0, 0, 6, 1)]
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! A panel on the edge of the screen listing every hint with the class and title of its window.
+
+use anyhow::{Context, Result};
+
+use crate::args::{AppConfig, LegendPosition};
+use crate::geometry::Rect;
+use crate::measure::TextMeasurer;
+use crate::{utils, DesktopWindow};
+
+/// Separates the class from the title in a row.
+const SEPARATOR: &str = " — ";
+
+#[derive(Debug)]
+struct Row {
+    hint: String,
+    /// The hint as it's drawn, eg in uppercase.
+    shown_hint: String,
+    description: String,
+}
+
+/// The measured rows of a legend.
+#[derive(Debug)]
+pub struct Legend {
+    rows: Vec<Row>,
+    font_size: f64,
+    ascent: f64,
+    line_height: f64,
+    padding: f64,
+    /// Width of the hint column including the gap to the descriptions.
+    hint_width: f64,
+    /// Size of the whole legend in the format (w, h).
+    pub size: (i32, i32),
+}
+
+/// The class and title of `window` as shown next to its hint.
+fn description(window: &DesktopWindow) -> String {
+    let class = window.class.as_deref().or(window.instance.as_deref());
+    let title = window.title.as_deref().filter(|t| !t.is_empty());
+    match (class, title) {
+        (Some(class), Some(title)) => format!("{class}{SEPARATOR}{title}"),
+        (Some(text), None) | (None, Some(text)) => text.to_string(),
+        (None, None) => format!("0x{:x}", window.x_window_id.unwrap_or(0)),
+    }
+}
+
+impl Legend {
+    /// Measure a legend for `hinted_windows`, sorted by hint.
+    ///
+    /// Descriptions are shortened to `--title-max-width` just like the titles after hints.
+    pub fn new(
+        app_config: &AppConfig,
+        measurer: &mut TextMeasurer,
+        hinted_windows: &[(String, DesktopWindow)],
+    ) -> Result<Legend> {
+        let family = &app_config.font.font_family;
+        let font_size = app_config.font.font_size;
+        measurer.set_font_style(
+            app_config.font_slant.into(),
+            utils::font_weight(app_config, false),
+        );
+
+        let mut rows = vec![];
+        for (hint, window) in hinted_windows {
+            let shown_hint = if app_config.uppercase_labels {
+                hint.to_uppercase()
+            } else {
+                hint.clone()
+            };
+            let description = measurer
+                .elide(
+                    &description(window),
+                    family,
+                    font_size,
+                    f64::from(app_config.title_max_width),
+                )
+                .context("Couldn't elide legend row")?;
+            rows.push(Row {
+                hint: hint.clone(),
+                shown_hint,
+                description,
+            });
+        }
+        rows.sort_by(|a, b| a.hint.cmp(&b.hint));
+
+        let font_extents = measurer.font_extents(family, font_size)?;
+        let line_height = font_extents.ascent() + font_extents.descent();
+        // The margin and padding around the text of a box go around the whole legend.
+        let padding = (app_config.margin.box_size(line_height) - line_height) / 2.0
+            + f64::from(app_config.text_padding);
+        let gap = measurer
+            .extents_for_text("  ", family, font_size)?
+            .x_advance();
+        let mut hint_width: f64 = 0.0;
+        let mut description_width: f64 = 0.0;
+        for row in &rows {
+            let hint_extents = measurer.extents_for_text(&row.shown_hint, family, font_size)?;
+            hint_width = hint_width.max(hint_extents.x_advance());
+            let description_extents =
+                measurer.extents_for_text(&row.description, family, font_size)?;
+            description_width = description_width.max(description_extents.x_advance());
+        }
+        let hint_width = hint_width + gap;
+        let size = (
+            (2.0 * padding + hint_width + description_width).ceil() as i32,
+            (2.0 * padding + line_height * rows.len() as f64).ceil() as i32,
+        );
+
+        Ok(Legend {
+            rows,
+            font_size,
+            ascent: font_extents.ascent(),
+            line_height,
+            padding,
+            hint_width,
+            size,
+        })
+    }
+
+    /// Where to show the legend on `screen`, vertically centered on the edge at `position`.
+    pub fn rect(&self, position: LegendPosition, screen: Rect) -> Rect {
+        let (w, h) = (self.size.0.min(screen.w), self.size.1.min(screen.h));
+        let x = match position {
+            LegendPosition::Right => screen.x + screen.w - w,
+            LegendPosition::Off | LegendPosition::Left => screen.x,
+        };
+        Rect::new(x, screen.y + (screen.h - h) / 2, w, h)
+    }
+
+    /// Draw the rows of all hints starting with `pressed_keys` onto `context`.
+    ///
+    /// Rows keep their place while typing so that the remaining hints don't jump around.
+    pub fn draw(
+        &self,
+        context: &cairo::Context,
+        app_config: &AppConfig,
+        pressed_keys: &str,
+    ) -> Result<()> {
+        let set_color = |color: (f64, f64, f64, f64)| {
+            context.set_source_rgba(color.0, color.1, color.2, color.3);
+        };
+        context.set_operator(cairo::Operator::Source);
+        let bg_color = app_config.bg_color;
+        context.set_source_rgb(bg_color.0, bg_color.1, bg_color.2);
+        context.paint().context("Error trying to draw")?;
+        context.set_operator(cairo::Operator::Over);
+
+        context.select_font_face(
+            &app_config.font.font_family,
+            app_config.font_slant.into(),
+            utils::font_weight(app_config, false),
+        );
+        context.set_font_size(self.font_size);
+        let typed = pressed_keys.chars().count();
+        for (i, row) in self.rows.iter().enumerate() {
+            if !row.hint.starts_with(pressed_keys) {
+                continue;
+            }
+            let baseline = self.padding + self.line_height * i as f64 + self.ascent;
+            context.move_to(self.padding, baseline);
+            let typed_part: String = row.shown_hint.chars().take(typed).collect();
+            let rest: String = row.shown_hint.chars().skip(typed).collect();
+            set_color(app_config.match_color.unwrap_or(app_config.text_color_alt));
+            context
+                .show_text(&typed_part)
+                .context("Couldn't display text")?;
+            set_color(app_config.text_color);
+            context.show_text(&rest).context("Couldn't display text")?;
+            context.move_to(self.padding + self.hint_width, baseline);
+            context
+                .show_text(&row.description)
+                .context("Couldn't display text")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(class: Option<&str>, title: Option<&str>) -> DesktopWindow {
+        DesktopWindow {
+            id: 1,
+            x_window_id: Some(0x2a),
+            class: class.map(String::from),
+            instance: None,
+            title: title.map(String::from),
+            target: None,
+            pos: (0, 0),
+            size: (100, 100),
+            is_focused: false,
+        }
+    }
+
+    fn legend(size: (i32, i32)) -> Legend {
+        Legend {
+            rows: vec![],
+            font_size: 12.0,
+            ascent: 10.0,
+            line_height: 14.0,
+            padding: 2.0,
+            hint_width: 20.0,
+            size,
+        }
+    }
+
+    #[test]
+    fn test_description() {
+        assert_eq!(
+            description(&window(Some("firefox"), Some("Mozilla Firefox"))),
+            "firefox — Mozilla Firefox"
+        );
+        assert_eq!(description(&window(Some("xterm"), Some(""))), "xterm");
+        assert_eq!(description(&window(None, Some("Untitled"))), "Untitled");
+        assert_eq!(description(&window(None, None)), "0x2a");
+    }
+
+    #[test]
+    fn test_legend_rect() {
+        let screen = Rect::new(0, 0, 1920, 1080);
+        assert_eq!(
+            legend((200, 100)).rect(LegendPosition::Left, screen),
+            Rect::new(0, 490, 200, 100)
+        );
+        assert_eq!(
+            legend((200, 100)).rect(LegendPosition::Right, screen),
+            Rect::new(1720, 490, 200, 100)
+        );
+        // Legends taller than the screen are cut off at the bottom.
+        assert_eq!(
+            legend((200, 2000)).rect(LegendPosition::Right, screen),
+            Rect::new(1720, 0, 200, 1080)
+        );
+    }
+}
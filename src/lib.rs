@@ -0,0 +1,2303 @@
+//! Visually focus windows by label.
+//!
+//! Besides the `wmfocus` binary, this crate can be used as a library: build an [`AppConfig`]
+//! (either via [`args::parse_args`] or by modifying [`AppConfig::default`]) and pass it to
+//! `run`. Text measurement is available separately via [`TextMeasurer`].
+
+use std::collections::{HashMap, HashSet};
+use std::iter::Iterator;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use x11rb::xcb_ffi::XCBConnection;
+
+use x11rb::{
+    self,
+    connection::Connection,
+    protocol::shape::{self, ConnectionExt as _},
+    protocol::xproto::{self, ConnectionExt as _},
+    protocol::Event,
+    wrapper::ConnectionExt as _,
+};
+
+pub mod args;
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+mod backend;
+mod cache;
+mod config;
+pub mod error;
+mod ewmh;
+mod filter;
+pub mod geometry;
+mod legend;
+pub mod measure;
+mod monitor;
+pub mod properties;
+pub mod template;
+mod themes;
+mod utils;
+#[cfg(feature = "wayland")]
+mod wayland;
+mod window_list;
+
+#[cfg(feature = "i3")]
+extern crate i3ipc;
+
+#[cfg(feature = "i3")]
+mod wm_i3;
+
+#[cfg(feature = "i3")]
+use crate::wm_i3 as wm;
+
+pub use crate::args::AppConfig;
+pub use crate::geometry::Rect;
+pub use crate::measure::TextMeasurer;
+
+/// Number of steps a `--fade-in` is split into.
+const FADE_STEPS: u32 = 10;
+
+/// Pixels between the cells of `--hint-position-relative-to pointer`.
+const POINTER_GRID_GAP: i32 = 4;
+
+/// A window as reported by the window manager.
+#[derive(Debug, Clone)]
+pub struct DesktopWindow {
+    /// The window manager's id for this window.
+    pub id: i64,
+    pub x_window_id: Option<i32>,
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+    /// Id of the area inside the X window that this hint is for, eg a terminal pane. A window can
+    /// be split into several targets which each get their own hint but all share the X window.
+    pub target: Option<String>,
+    pub pos: (i32, i32),
+    pub size: (i32, i32),
+    pub is_focused: bool,
+}
+
+impl DesktopWindow {
+    /// Position and size of this window.
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.pos.0, self.pos.1, self.size.0, self.size.1)
+    }
+}
+
+/// The result of a call to `run`.
+#[derive(Debug, Clone)]
+pub enum Selection {
+    /// The user selected this window by typing its hint.
+    Window { window: DesktopWindow, hint: String },
+    /// The user cancelled the selection.
+    Cancelled,
+    /// No key was pressed before `--timeout` ran out.
+    TimedOut,
+    /// There were no windows to give a hint to.
+    NoWindows,
+}
+
+#[derive(Debug)]
+pub(crate) struct RenderWindow<'a> {
+    desktop_window: &'a DesktopWindow,
+    xcb_window_id: u32,
+    cairo_context: cairo::Context,
+    title: Option<String>,
+    /// Family to draw the title in if it isn't the font's.
+    title_font: Option<String>,
+    font_size: f64,
+    draw_pos: (f64, f64),
+    /// Position of the box inside of the window which also includes the shadow.
+    box_pos: (f64, f64),
+    /// Size of the box in the format (w, h).
+    box_size: (i32, i32),
+    rect: Rect,
+    /// The shown parts of the window, relative to `rect`.
+    shape: Vec<xproto::Rectangle>,
+    /// Line from the box to the center of its window in screen coordinates.
+    connector: Option<((i32, i32), (i32, i32))>,
+}
+
+impl<'a> RenderWindow<'a> {
+    /// Draw `hint_box` with `cairo_context`, which draws onto the X window `xcb_window_id`.
+    fn new(
+        hint_box: HintBox<'a>,
+        xcb_window_id: u32,
+        cairo_context: cairo::Context,
+    ) -> RenderWindow<'a> {
+        RenderWindow {
+            desktop_window: hint_box.desktop_window,
+            xcb_window_id,
+            cairo_context,
+            title: hint_box.title,
+            title_font: hint_box.title_font,
+            font_size: hint_box.font_size,
+            draw_pos: hint_box.draw_pos,
+            box_pos: hint_box.box_pos,
+            box_size: hint_box.box_size,
+            rect: hint_box.rect,
+            shape: hint_box.shape,
+            connector: hint_box.connector,
+        }
+    }
+}
+
+/// A box placed on the screen by `layout_hints` that has nothing to draw onto yet.
+#[derive(Debug)]
+pub(crate) struct HintBox<'a> {
+    hint: &'a str,
+    desktop_window: &'a DesktopWindow,
+    title: Option<String>,
+    /// Family to draw the title in if it isn't the font's.
+    title_font: Option<String>,
+    font_size: f64,
+    draw_pos: (f64, f64),
+    /// Position of the box inside of `rect` which also includes the shadow.
+    box_pos: (f64, f64),
+    /// Size of the box in the format (w, h).
+    box_size: (i32, i32),
+    rect: Rect,
+    /// The shown parts of `rect`, relative to it.
+    shape: Vec<xproto::Rectangle>,
+    /// Line from the box to the center of its window in screen coordinates.
+    connector: Option<((i32, i32), (i32, i32))>,
+}
+
+/// Connect to the X server the overlays are drawn on.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn connect() -> error::Result<(XCBConnection, usize)> {
+    Ok(XCBConnection::connect(None)?)
+}
+
+/// Get the windows from the window manager.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn wm_windows(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+) -> Result<Vec<DesktopWindow>> {
+    if app_config.all_desktops {
+        ewmh::get_windows(conn, atoms, screen.root).context("Couldn't get EWMH client list")
+    } else {
+        wm::get_windows(app_config.workspace).context("Couldn't get desktop windows")
+    }
+}
+
+/// Get the windows from the window manager unless the last run found them less than `ttl` ago.
+///
+/// Problems with the cache aren't fatal, we ask the window manager instead.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn cached_windows(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    ttl: Duration,
+) -> Result<Vec<DesktopWindow>> {
+    let path = cache::default_path();
+    let source = format!(
+        "all_desktops={} workspace={:?}",
+        app_config.all_desktops, app_config.workspace
+    );
+    let active_window = utils::get_active_window(conn, atoms, screen.root)?;
+    match cache::Cache::load(&path) {
+        Ok(cache) => match cache.into_windows(cache::now(), ttl, &source, active_window) {
+            Some(windows) => {
+                info!(
+                    "Using {} cached windows from {}",
+                    windows.len(),
+                    path.display()
+                );
+                return Ok(windows);
+            }
+            None => debug!("Cached windows are out of date"),
+        },
+        Err(e) => debug!("No usable window cache: {:#}", e),
+    }
+    let windows = wm_windows(app_config, conn, atoms, screen)?;
+    if let Err(e) = cache::Cache::new(source, active_window, windows.clone()).store(&path) {
+        warn!("Couldn't cache windows: {:#}", e);
+    }
+    Ok(windows)
+}
+
+/// Keep only the topmost of the windows of every class.
+///
+/// The window manager's `_NET_CLIENT_LIST_STACKING` decides which one that is. Without it, we go by
+/// the stacking order of the top-level windows.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn once_per_class(
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    desktop_windows: Vec<DesktopWindow>,
+) -> Result<Vec<DesktopWindow>> {
+    let mut ranks = HashMap::new();
+    if let Some(stack) = utils::get_client_stacking(conn, atoms, screen.root)? {
+        for (rank, window) in stack.into_iter().enumerate() {
+            ranks.insert(window, rank);
+        }
+    } else {
+        debug!("No _NET_CLIENT_LIST_STACKING, going by the top-level windows");
+        let stack = utils::get_stacking_order(conn, screen.root)?;
+        for x_window_id in desktop_windows.iter().filter_map(|w| w.x_window_id) {
+            let toplevel = utils::get_toplevel(conn, screen.root, x_window_id as u32)?;
+            if let Some(rank) = stack.iter().position(|(w, _)| *w == toplevel) {
+                ranks.insert(x_window_id as u32, rank);
+            }
+        }
+    }
+    Ok(utils::once_per_class(desktop_windows, |w| {
+        w.x_window_id
+            .and_then(|id| ranks.get(&(id as u32)).copied())
+    }))
+}
+
+/// Remove all windows that are completely covered by other windows or not shown at all.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn remove_hidden_windows(
+    conn: &XCBConnection,
+    screen: &xproto::Screen,
+    desktop_windows: Vec<DesktopWindow>,
+) -> Result<Vec<DesktopWindow>> {
+    let stack = utils::get_stacking_order(conn, screen.root)?;
+    let mut visible_windows = vec![];
+    for desktop_window in desktop_windows {
+        let Some(x_window_id) = desktop_window.x_window_id else {
+            visible_windows.push(desktop_window);
+            continue;
+        };
+        let toplevel = match utils::get_toplevel(conn, screen.root, x_window_id as u32) {
+            Ok(toplevel) => toplevel,
+            Err(e) => {
+                warn!(
+                    "Couldn't find top-level window of {}: {:#}",
+                    desktop_window.id, e
+                );
+                visible_windows.push(desktop_window);
+                continue;
+            }
+        };
+        let Some(index) = stack.iter().position(|(w, _)| *w == toplevel) else {
+            debug!("Skipping window {} as it isn't shown", desktop_window.id);
+            continue;
+        };
+        let covers: Vec<_> = stack[index + 1..].iter().map(|(_, rect)| *rect).collect();
+        if desktop_window.rect().visible_area(&covers) == 0 {
+            debug!("Skipping window {} as it is covered", desktop_window.id);
+            continue;
+        }
+        visible_windows.push(desktop_window);
+    }
+    Ok(visible_windows)
+}
+
+/// Get the windows that should get a hint in the order in which hints are assigned to them.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn candidate_windows(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    monitors: &[monitor::Monitor],
+) -> Result<Vec<DesktopWindow>> {
+    // Get the windows from each specific window manager implementation.
+    let mut desktop_windows_raw = if app_config.windows_from_stdin {
+        window_list::get_windows()?
+    } else if let Some(ttl) = app_config.cache {
+        cached_windows(app_config, conn, atoms, screen, Duration::from_secs(ttl))?
+    } else {
+        wm_windows(app_config, conn, atoms, screen)?
+    };
+
+    // The window manager doesn't know about override-redirect windows so we have to find them
+    // ourselves.
+    if app_config.include_override_redirect && !app_config.windows_from_stdin {
+        let override_redirect = utils::get_override_redirect_windows(conn, atoms, screen.root)
+            .context("Couldn't get override-redirect windows")?;
+        for w in override_redirect {
+            if !desktop_windows_raw
+                .iter()
+                .any(|other| other.x_window_id == w.x_window_id)
+            {
+                desktop_windows_raw.push(w);
+            }
+        }
+    }
+
+    // Reparenting window managers put the windows into frames that we can place boxes in instead.
+    // Targets inside a window keep their own area.
+    if app_config.geometry_source == args::GeometrySource::Frame {
+        for w in &mut desktop_windows_raw {
+            let Some(x_window_id) = w.x_window_id.filter(|_| w.target.is_none()) else {
+                continue;
+            };
+            match utils::get_frame_rect(conn, atoms, screen.root, x_window_id as u32) {
+                Ok(frame) => {
+                    w.pos = (frame.x, frame.y);
+                    w.size = (frame.w, frame.h);
+                }
+                Err(e) => warn!("Couldn't get frame of window {}: {:#}", w.id, e),
+            }
+        }
+    }
+
+    // Not every source knows which window is focused, the window manager might tell us instead.
+    if !desktop_windows_raw.iter().any(|w| w.is_focused) {
+        match utils::get_active_window(conn, atoms, screen.root) {
+            Ok(Some(active_window)) => {
+                for w in &mut desktop_windows_raw {
+                    w.is_focused = w.x_window_id == Some(active_window as i32);
+                }
+            }
+            Ok(None) => debug!("No active window reported"),
+            Err(e) => warn!("Couldn't get active window: {:#}", e),
+        }
+    }
+
+    // Only windows that pass the filters get a hint. What the filters need to know about the X
+    // windows is asked for all of them at once.
+    let desktop_windows_raw: Vec<DesktopWindow> = desktop_windows_raw
+        .into_iter()
+        .filter(|w| app_config.filter.matches(w))
+        .collect();
+    // Targets inside a window all share its X window.
+    let x_window_ids: Vec<u32> = desktop_windows_raw
+        .iter()
+        .filter_map(|w| w.x_window_id)
+        .map(|id| id as u32)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    let window_properties: HashMap<u32, _> = x_window_ids
+        .iter()
+        .copied()
+        .zip(properties::get_window_properties(
+            conn,
+            atoms,
+            &x_window_ids,
+        )?)
+        .collect();
+    let desktop_windows_filtered: Vec<DesktopWindow> = desktop_windows_raw
+        .into_iter()
+        .filter(|w| {
+            let Some(x_window_id) = w.x_window_id else {
+                return true;
+            };
+            let properties = match window_properties.get(&(x_window_id as u32)) {
+                Some(Ok(properties)) => properties,
+                Some(Err(e)) => {
+                    warn!("Couldn't get properties of window {}: {:#}", w.id, e);
+                    return true;
+                }
+                None => return true,
+            };
+            let skipped = filter::is_skipped_type(&properties.types, &app_config.include_types)
+                || (!app_config.include_override_redirect && properties.override_redirect)
+                || (!app_config.include_transparent && filter::is_transparent(properties.opacity))
+                || (app_config.skip_minimized && properties.minimized);
+            !skipped
+        })
+        .collect();
+
+    // Only other instances of the active window's application get a hint.
+    let desktop_windows_filtered = if app_config.same_class {
+        let active_window = utils::get_active_window(conn, atoms, screen.root)?
+            .context("No active window to take the class from")?;
+        let class = utils::get_window_class(conn, active_window)?;
+        info!("Only hinting other windows of class '{}'", class);
+        desktop_windows_filtered
+            .into_iter()
+            .filter(|w| {
+                w.class.as_deref() == Some(class.as_str())
+                    && w.x_window_id != Some(active_window as i32)
+            })
+            .collect()
+    } else {
+        desktop_windows_filtered
+    };
+
+    // Windows that are completely covered by other windows can't be seen so there's no point in
+    // hinting them.
+    // Windows on other desktops or workspaces are never shown, so --all-desktops and
+    // --workspace all keep them all.
+    let desktop_windows_filtered = if app_config.include_hidden
+        || app_config.all_desktops
+        || app_config.workspace == args::WorkspaceSelection::All
+    {
+        desktop_windows_filtered
+    } else {
+        remove_hidden_windows(conn, screen, desktop_windows_filtered)?
+    };
+
+    // Every application only gets a hint for its topmost window.
+    let desktop_windows_filtered = if app_config.once_per_class {
+        once_per_class(conn, atoms, screen, desktop_windows_filtered)?
+    } else {
+        desktop_windows_filtered
+    };
+
+    // Sort to make hint position more deterministic.
+    let mut desktop_windows = match app_config.order {
+        args::HintOrder::Position => utils::sort_by_pos(desktop_windows_filtered),
+        args::HintOrder::Class => utils::sort_by_class(desktop_windows_filtered),
+        args::HintOrder::Stacking => desktop_windows_filtered,
+    };
+
+    // Only hint windows that are mostly on the selected monitor.
+    let pointer = if app_config.monitor == args::MonitorSelection::Current {
+        let reply = conn
+            .query_pointer(screen.root)?
+            .reply()
+            .context("Couldn't query pointer position")?;
+        (reply.root_x.into(), reply.root_y.into())
+    } else {
+        (0, 0)
+    };
+    if let Some(selected) = monitor::select_monitor(monitors, &app_config.monitor, pointer)? {
+        info!("Only hinting windows on monitor {}", selected.name);
+        desktop_windows.retain(|w| {
+            monitor::monitor_for_window(monitors, w).is_some_and(|m| m.name == selected.name)
+        });
+    }
+
+    if let Some(region) = app_config.region {
+        info!("Only hinting windows in {:?}", region);
+        desktop_windows.retain(|w| w.rect().mostly_within(&region));
+    }
+
+    if let Some(max_windows) = app_config.max_windows {
+        if desktop_windows.len() > max_windows {
+            warn!(
+                "Found {} windows, only hinting the first {}",
+                desktop_windows.len(),
+                max_windows
+            );
+            desktop_windows.truncate(max_windows);
+        }
+    }
+
+    Ok(desktop_windows)
+}
+
+/// What the keys typed so far mean for the shown hints.
+#[derive(Debug, PartialEq, Eq)]
+enum HintMatch {
+    /// This hint was typed in full or is the only one left that starts with the keys.
+    Unique(String),
+    /// Several hints still start with the keys.
+    Partial,
+    /// No hint starts with the keys or none were typed yet.
+    None,
+}
+
+/// Match `pressed_keys` against `hints`.
+fn match_hint<'a>(hints: impl IntoIterator<Item = &'a String>, pressed_keys: &str) -> HintMatch {
+    if pressed_keys.is_empty() {
+        return HintMatch::None;
+    }
+    let matching: Vec<&String> = hints
+        .into_iter()
+        .filter(|hint| hint.starts_with(pressed_keys))
+        .collect();
+    match matching.as_slice() {
+        [] => HintMatch::None,
+        [hint] => HintMatch::Unique(hint.to_string()),
+        _ => match matching.iter().find(|hint| hint.as_str() == pressed_keys) {
+            Some(hint) => HintMatch::Unique(hint.to_string()),
+            None => HintMatch::Partial,
+        },
+    }
+}
+
+/// What a key press means for the shown hints.
+#[derive(Debug, PartialEq, Eq)]
+enum KeyPress {
+    /// Stop showing hints without selecting a window.
+    Cancel,
+    /// The keys typed so far select this hint.
+    Select(String),
+    /// The typed keys changed, the hints have to be shown according to them.
+    Update,
+    /// The key was rejected. If `close` is set, the hints are closed as well.
+    Reject { close: bool },
+    /// The key doesn't change anything, eg because it's part of an exit sequence.
+    Ignore,
+}
+
+/// The keys typed while hints are shown.
+struct HintKeys<'a> {
+    app_config: &'a AppConfig,
+    /// The hint characters typed so far.
+    pressed_keys: String,
+    /// All keys that are held down, to be matched against `--exit-keys`.
+    sequence: utils::Sequence,
+}
+
+impl<'a> HintKeys<'a> {
+    fn new(app_config: &'a AppConfig) -> HintKeys<'a> {
+        HintKeys {
+            app_config,
+            pressed_keys: String::default(),
+            sequence: utils::Sequence::new(None),
+        }
+    }
+
+    /// Name of `ksym` without the `XK_` prefix.
+    fn key_name(ksym: u32) -> Result<String> {
+        Ok(xkeysym::name(ksym)
+            .context("Couldn't convert ksym to string")?
+            .replace("XK_", ""))
+    }
+
+    /// Forget that the key of `ksym` is held down.
+    fn release(&mut self, ksym: u32) -> Result<()> {
+        self.sequence.remove(&Self::key_name(ksym)?);
+        Ok(())
+    }
+
+    /// Add the key of `ksym` to the typed keys and match them against `hints`.
+    fn press<'b>(
+        &mut self,
+        ksym: u32,
+        hints: impl IntoIterator<Item = &'b String>,
+    ) -> Result<KeyPress> {
+        let app_config = self.app_config;
+        let kstr = Self::key_name(ksym)?;
+        self.sequence.push(kstr.to_owned());
+
+        // Translate aliased keys into the key they stand for.
+        let kstr = match app_config.key_aliases.iter().find(|a| a.from == kstr) {
+            Some(alias) => {
+                debug!("Translating '{}' to '{}'", alias.from, alias.to);
+                alias.to.clone()
+            }
+            None => kstr,
+        };
+
+        // Map the typed key onto the hint character that only differs in case.
+        let kstr = if app_config.ignore_case || app_config.uppercase_labels {
+            utils::match_hint_char_case(&kstr, &app_config.hint_chars)
+        } else {
+            kstr
+        };
+
+        // Backspace removes the last typed character and brings back all hints that match again.
+        if ksym == xkeysym::KEY_BackSpace {
+            self.pressed_keys.pop();
+            info!("Current key sequence: '{}'", self.pressed_keys);
+            return Ok(KeyPress::Update);
+        }
+
+        // With --group-by-monitor, hints start with the monitor's character.
+        let is_monitor_char = app_config.group_by_monitor
+            && self.pressed_keys.is_empty()
+            && app_config.monitor_chars.contains(&kstr);
+        // Windows past --max-hint-length get numeric hints.
+        let is_digit = app_config.max_hint_length.is_some()
+            && kstr.len() == 1
+            && kstr.chars().all(|c| c.is_ascii_digit());
+        let is_hint_char = is_monitor_char || is_digit || app_config.hint_chars.contains(&kstr);
+        if is_hint_char {
+            info!("Adding '{}' to key sequence", kstr);
+            self.pressed_keys.push_str(&kstr);
+        } else {
+            warn!("Pressed key '{}' is not a valid hint characters", kstr);
+        }
+
+        info!("Current key sequence: '{}'", self.pressed_keys);
+
+        if app_config.cancel_keys.contains(&ksym) || app_config.exit_keys.contains(&self.sequence) {
+            info!("{:?} is exit sequence", self.sequence);
+            return Ok(KeyPress::Cancel);
+        }
+
+        // Attempt to match the current sequence of keys as a string to the window hints shown.
+        // If there is an exact match or only a single hint is left that starts with the current
+        // sequence, we're done. We'll then focus the window and exit.
+        // However, we also want to check whether there is still any chance to focus any windows
+        // from the current key sequence. If there is not then we will also just exit and focus no
+        // new window.
+        // If there still is a chance we might find a window then we'll hide all hints that can't
+        // match anymore and keep going for now.
+        if self.sequence.is_started() {
+            utils::remove_last_key(&mut self.pressed_keys, &kstr);
+            return Ok(KeyPress::Ignore);
+        }
+        Ok(match match_hint(hints, &self.pressed_keys) {
+            HintMatch::Unique(hint) => {
+                info!("Found matching window, focusing");
+                KeyPress::Select(hint)
+            }
+            // Keys that aren't hint characters don't change which hints match.
+            HintMatch::Partial if !is_hint_char => KeyPress::Reject { close: false },
+            HintMatch::Partial => KeyPress::Update,
+            HintMatch::None => {
+                warn!("No more matches possible with current key sequence");
+                if is_hint_char {
+                    utils::remove_last_key(&mut self.pressed_keys, &kstr);
+                }
+                KeyPress::Reject {
+                    close: app_config.exit_keys.is_empty(),
+                }
+            }
+        })
+    }
+}
+
+/// Generate `count` hints of `hint_length`.
+fn generate_hints(
+    hint_chars: &str,
+    hint_length: args::HintLength,
+    count: usize,
+) -> Result<Vec<String>> {
+    let hints = match hint_length {
+        args::HintLength::Uniform => {
+            utils::get_uniform_hints(hint_chars, count).context("Couldn't get hints")?
+        }
+        args::HintLength::Shortest => {
+            utils::get_shortest_hints(hint_chars, count).context("Couldn't get hints")?
+        }
+    };
+    Ok(hints)
+}
+
+/// Pair every window with the hint it gets.
+///
+/// The first window of a class that is pinned gets the pinned hint. Once hints would have to be
+/// longer than `max_hint_length` characters, the remaining windows get numeric hints instead.
+fn assign_hints(
+    desktop_windows: Vec<DesktopWindow>,
+    hint_chars: &str,
+    hint_length: args::HintLength,
+    max_hint_length: Option<u32>,
+    pins: &[args::Pin],
+) -> Result<Vec<(String, DesktopWindow)>> {
+    let count = desktop_windows.len();
+    let max_count = max_hint_length.map_or(usize::MAX, |length| {
+        utils::max_hint_count(hint_chars, length)
+    });
+
+    let mut pinned: Vec<Option<String>> = vec![None; count];
+    for pin in pins {
+        let index = desktop_windows.iter().enumerate().position(|(i, w)| {
+            pinned[i].is_none()
+                && w.class
+                    .as_deref()
+                    .is_some_and(|class| class.eq_ignore_ascii_case(&pin.class))
+        });
+        if let Some(index) = index {
+            debug!(
+                "Pinning hint '{}' to window {}",
+                pin.hint, desktop_windows[index].id
+            );
+            pinned[index] = Some(pin.hint.clone());
+        }
+    }
+
+    // The other windows can't get a hint that either is a prefix of a pinned hint or starts with
+    // one. We generate more hints until there are enough left after dropping those.
+    let used_pins: Vec<&String> = pinned.iter().flatten().collect();
+    let collides = |hint: &str| {
+        used_pins
+            .iter()
+            .any(|pin| pin.starts_with(hint) || hint.starts_with(pin.as_str()))
+    };
+    let needed = count - used_pins.len();
+    let mut generated = needed;
+    let mut hints = loop {
+        let hints: Vec<String> = generate_hints(hint_chars, hint_length, generated.min(max_count))?
+            .into_iter()
+            .filter(|hint| !collides(hint))
+            .collect();
+        if hints.len() >= needed || generated >= max_count {
+            break hints;
+        }
+        generated += needed - hints.len();
+    };
+    hints.truncate(needed);
+    if hints.len() < needed {
+        let numeric = needed - hints.len();
+        info!(
+            "Giving {} windows numeric hints, hints of at most {} characters from '{}' ran out",
+            numeric,
+            max_hint_length.unwrap_or_default(),
+            hint_chars
+        );
+        hints.extend(utils::get_numeric_hints(numeric));
+    }
+    let mut hints = hints.into_iter();
+
+    desktop_windows
+        .into_iter()
+        .zip(pinned)
+        .map(|(desktop_window, pin)| {
+            let hint = match pin {
+                Some(hint) => hint,
+                None => hints.next().context("Ran out of hints")?,
+            };
+            Ok((hint, desktop_window))
+        })
+        .collect()
+}
+
+/// Pair every window with a hint that starts with the character of `monitor_chars` belonging to
+/// the monitor the window is on.
+///
+/// Windows that aren't on any monitor share the character after the last monitor's.
+fn assign_monitor_hints(
+    desktop_windows: Vec<DesktopWindow>,
+    monitors: &[monitor::Monitor],
+    monitor_chars: &str,
+    hint_chars: &str,
+    hint_length: args::HintLength,
+    max_hint_length: Option<u32>,
+    pins: &[args::Pin],
+) -> Result<Vec<(String, DesktopWindow)>> {
+    let mut groups = vec![vec![]; monitors.len() + 1];
+    for desktop_window in desktop_windows {
+        let index = monitor::monitor_for_window(monitors, &desktop_window)
+            .and_then(|found| monitors.iter().position(|m| m.name == found.name))
+            .unwrap_or(monitors.len());
+        groups[index].push(desktop_window);
+    }
+
+    let mut hinted_windows = vec![];
+    for (index, group) in groups.into_iter().enumerate() {
+        if group.is_empty() {
+            continue;
+        }
+        let prefix = monitor_chars.chars().nth(index).with_context(|| {
+            format!("Not enough monitor characters in '{monitor_chars}' for all monitors")
+        })?;
+        let hinted_group = assign_hints(group, hint_chars, hint_length, max_hint_length, pins)?;
+        for (hint, desktop_window) in hinted_group {
+            hinted_windows.push((format!("{prefix}{hint}"), desktop_window));
+        }
+    }
+    Ok(hinted_windows)
+}
+
+/// Pair every window with the hint it gets according to `app_config`.
+fn hint_windows(
+    app_config: &AppConfig,
+    desktop_windows: Vec<DesktopWindow>,
+    monitors: &[monitor::Monitor],
+) -> Result<Vec<(String, DesktopWindow)>> {
+    if app_config.group_by_monitor {
+        assign_monitor_hints(
+            desktop_windows,
+            monitors,
+            &app_config.monitor_chars,
+            &app_config.hint_chars,
+            app_config.hint_length,
+            app_config.max_hint_length,
+            &app_config.pins,
+        )
+    } else {
+        assign_hints(
+            desktop_windows,
+            &app_config.hint_chars,
+            app_config.hint_length,
+            app_config.max_hint_length,
+            &app_config.pins,
+        )
+    }
+}
+
+/// Give each of `desktop_windows` a hint made of `hint_chars`, keyed by the window's `id`.
+///
+/// All hints are unique and `hint_length` decides whether they all have the same length. Unlike
+/// `run`, this doesn't apply pinned hints or `--max-hint-length`.
+pub fn hint_map(
+    desktop_windows: &[DesktopWindow],
+    hint_chars: &str,
+    hint_length: args::HintLength,
+) -> Result<HashMap<i64, String>> {
+    let hints = generate_hints(hint_chars, hint_length, desktop_windows.len())?;
+    let mut map = HashMap::with_capacity(hints.len());
+    for (desktop_window, hint) in desktop_windows.iter().zip(hints) {
+        if map.insert(desktop_window.id, hint).is_some() {
+            bail!("Window {} is listed more than once", desktop_window.id);
+        }
+    }
+    Ok(map)
+}
+
+/// All options in effect in `app_config`, those the config file can set in its format and all
+/// others in comments.
+pub fn dump_config(app_config: &AppConfig) -> String {
+    config::dump(app_config)
+}
+
+/// List the windows that would get hints along with their hints without showing anything.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+pub fn list(app_config: &AppConfig) -> Result<Vec<(String, DesktopWindow)>> {
+    let mut app_config = app_config.clone();
+    with_backend(&mut app_config, |backend, app_config| {
+        let desktop_windows = backend.list_windows(app_config)?;
+        hint_windows(app_config, desktop_windows, backend.monitors())
+    })
+}
+
+/// Create a click-through black window covering the whole screen at `opacity`.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn create_dim_window(
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    opacity: f64,
+) -> Result<u32> {
+    // The size in the setup goes stale once monitors are added or removed.
+    let root_geometry = conn
+        .get_geometry(screen.root)?
+        .reply()
+        .context("Couldn't get screen size")?;
+    let window = conn.generate_id()?;
+    let win_aux = xproto::CreateWindowAux::new()
+        .background_pixel(screen.black_pixel)
+        .override_redirect(1);
+    xproto::create_window(
+        conn,
+        x11rb::COPY_FROM_PARENT as u8,
+        window,
+        screen.root,
+        0,
+        0,
+        root_geometry.width,
+        root_geometry.height,
+        0,
+        xproto::WindowClass::INPUT_OUTPUT,
+        screen.root_visual,
+        &win_aux,
+    )?;
+    conn.shape_rectangles(
+        shape::SO::SET,
+        shape::SK::INPUT,
+        xproto::ClipOrdering::UNSORTED,
+        window,
+        0,
+        0,
+        &[],
+    )?;
+    utils::set_opacity(conn, atoms, window, opacity)?;
+    conn.map_window(window)?;
+    conn.flush()?;
+    debug!("Created dim window 0x{:x}", window);
+    Ok(window)
+}
+
+/// Position of a box of `width` by `height` inside `container` according to `horizontal_align`,
+/// `vertical_align` and `offset`.
+fn align_box(
+    container: Rect,
+    (width, height): (i32, i32),
+    horizontal_align: args::HorizontalAlign,
+    vertical_align: args::VerticalAlign,
+    offset: &args::Offset,
+) -> (i32, i32) {
+    let x = match horizontal_align {
+        args::HorizontalAlign::Left => container.x + offset.x,
+        args::HorizontalAlign::Center => container.x + container.w / 2 - width / 2 + offset.x,
+        args::HorizontalAlign::Right => container.x + container.w - width - offset.x,
+    };
+    let y = match vertical_align {
+        args::VerticalAlign::Top => container.y + offset.y,
+        args::VerticalAlign::Center => container.y + container.h / 2 - height / 2 + offset.y,
+        args::VerticalAlign::Bottom => container.y + container.h - height - offset.y,
+    };
+    (x, y)
+}
+
+/// Create a mapped hint window covering `rect` and a cairo surface to draw onto it.
+///
+/// Only the parts of the window in `shape` are shown, all of it if it's `None`.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn create_hint_window(
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    visual: &utils::HintVisual,
+    app_config: &AppConfig,
+    rect: Rect,
+    shape: Option<&[xproto::Rectangle]>,
+) -> Result<(u32, cairo::XCBSurface)> {
+    let xcb_window_id = conn.generate_id()?;
+
+    let mut event_mask = xproto::EventMask::EXPOSURE
+        | xproto::EventMask::KEY_PRESS
+        | xproto::EventMask::BUTTON_PRESS
+        | xproto::EventMask::BUTTON_RELEASE;
+    if app_config.exit_on_focus_loss {
+        event_mask |= xproto::EventMask::VISIBILITY_CHANGE;
+    }
+    let mut win_aux = xproto::CreateWindowAux::new()
+        .event_mask(event_mask)
+        .backing_pixel(screen.black_pixel)
+        .override_redirect(1);
+    // Windows of a depth other than the root's need their own colormap and border.
+    if let Some(colormap) = visual.colormap {
+        win_aux = win_aux
+            .colormap(colormap)
+            .border_pixel(0)
+            .background_pixel(0);
+    }
+
+    // Create the actual window.
+    xproto::create_window(
+        conn,
+        visual.depth,
+        xcb_window_id,
+        screen.root,
+        rect.x as i16,
+        rect.y as i16,
+        rect.w as u16,
+        rect.h as u16,
+        0,
+        xproto::WindowClass::INPUT_OUTPUT,
+        visual.visual.visual_id,
+        &win_aux,
+    )?;
+
+    if let Some(shape) = shape {
+        conn.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::BOUNDING,
+            xproto::ClipOrdering::UNSORTED,
+            xcb_window_id,
+            0,
+            0,
+            shape,
+        )?;
+    }
+
+    // Let clicks pass through to the windows below by giving the window an empty input region.
+    if !app_config.opaque_input {
+        conn.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::INPUT,
+            xproto::ClipOrdering::UNSORTED,
+            xcb_window_id,
+            0,
+            0,
+            &[],
+        )?;
+    }
+
+    conn.map_window(xcb_window_id)?;
+
+    // Set transparency. Fading in starts out invisible.
+    let opacity = if app_config.fade_in > 0 {
+        0.0
+    } else {
+        app_config.bg_color.3
+    };
+    utils::set_opacity(conn, atoms, xcb_window_id, opacity)?;
+
+    conn.flush()?;
+
+    let mut visual: utils::xcb_visualtype_t = visual.visual.into();
+    let cairo_conn =
+        unsafe { cairo::XCBConnection::from_raw_none(conn.get_raw_xcb_connection() as _) };
+    let cairo_visual = unsafe { cairo::XCBVisualType::from_raw_none(&mut visual as *mut _ as _) };
+
+    let surface = cairo::XCBSurface::create(
+        &cairo_conn,
+        &cairo::XCBDrawable(xcb_window_id),
+        &cairo_visual,
+        rect.w,
+        rect.h,
+    )
+    .context("Couldn't create Cairo Surface")?;
+    Ok((xcb_window_id, surface))
+}
+
+/// Top-left corners of boxes of `sizes` laid out in a grid that is centered on `pointer`.
+///
+/// The grid is about as wide as it is high with `gap` pixels between cells and is moved to lie
+/// within `bounds`. Every box is centered in its cell.
+fn pointer_grid(
+    sizes: &[(i32, i32)],
+    pointer: (i32, i32),
+    bounds: Rect,
+    gap: i32,
+) -> Vec<(i32, i32)> {
+    if sizes.is_empty() {
+        return vec![];
+    }
+    let columns = (sizes.len() as f64).sqrt().ceil() as usize;
+    let rows = sizes.len().div_ceil(columns);
+    let mut column_widths = vec![0; columns];
+    let mut row_heights = vec![0; rows];
+    for (i, &(width, height)) in sizes.iter().enumerate() {
+        column_widths[i % columns] = column_widths[i % columns].max(width);
+        row_heights[i / columns] = row_heights[i / columns].max(height);
+    }
+    let starts = |lengths: &[i32]| -> Vec<i32> {
+        lengths
+            .iter()
+            .scan(0, |start, length| {
+                let this = *start;
+                *start += length + gap;
+                Some(this)
+            })
+            .collect()
+    };
+    let (column_starts, row_starts) = (starts(&column_widths), starts(&row_heights));
+    let grid_width = column_starts[columns - 1] + column_widths[columns - 1];
+    let grid_height = row_starts[rows - 1] + row_heights[rows - 1];
+    let grid = Rect::new(
+        pointer.0 - grid_width / 2,
+        pointer.1 - grid_height / 2,
+        grid_width,
+        grid_height,
+    )
+    .clamp_into(&bounds);
+
+    sizes
+        .iter()
+        .enumerate()
+        .map(|(i, &(width, height))| {
+            let (column, row) = (i % columns, i / columns);
+            (
+                grid.x + column_starts[column] + (column_widths[column] - width) / 2,
+                grid.y + row_starts[row] + (row_heights[row] - height) / 2,
+            )
+        })
+        .collect()
+}
+
+/// Measure and place the boxes for all `hinted_windows`.
+///
+/// `pointer` is the position of the mouse pointer and the bounds of the screen, boxes are laid out
+/// in a grid around it if given.
+fn layout_hints<'a>(
+    app_config: &AppConfig,
+    hinted_windows: &'a [(String, DesktopWindow)],
+    monitors: &[monitor::Monitor],
+    text_measurer: &mut measure::TextMeasurer,
+    pointer: Option<((i32, i32), Rect)>,
+) -> Result<Vec<HintBox<'a>>> {
+    let box_size = |text_size: f64| {
+        app_config.margin.box_size(text_size) + 2.0 * f64::from(app_config.text_padding)
+    };
+    let mut boxes = vec![];
+    for (hint, desktop_window) in hinted_windows {
+        text_measurer.set_font_style(
+            app_config.font_slant.into(),
+            utils::font_weight(app_config, desktop_window.is_focused),
+        );
+
+        // We need to estimate the font size before rendering because we want the window to only be
+        // the size of the font.
+        // The configured font size is the size at 96 DPI if we scale by DPI.
+        let font_size = match app_config.font_size_from_window {
+            Some(factor) => utils::font_size_for_window(
+                factor,
+                desktop_window.size,
+                app_config.min_font_size,
+                app_config.max_font_size,
+            ),
+            None => app_config.font.font_size,
+        };
+        let font_size = match monitor::monitor_for_window(monitors, desktop_window) {
+            Some(monitor) if app_config.dpi_scale => font_size * monitor.dpi_scale(),
+            _ => font_size,
+        };
+
+        let title = desktop_window
+            .title
+            .as_deref()
+            .filter(|title| app_config.show_title && !title.is_empty());
+
+        // Titles that the font lacks some characters of are set in the fallback font instead.
+        let title_font = match (title, &app_config.fallback_font) {
+            (Some(title), Some(fallback_font))
+                if app_config.text_direction == args::TextDirection::Ltr
+                    && !text_measurer.has_glyphs(
+                        title,
+                        &app_config.font.font_family,
+                        font_size,
+                    )? =>
+            {
+                debug!(
+                    "Using fallback font {} for title '{}'",
+                    fallback_font, title
+                );
+                Some(fallback_font.clone())
+            }
+            _ => None,
+        };
+        let title = match title {
+            Some(title) => Some(
+                text_measurer
+                    .elide(
+                        title,
+                        title_font
+                            .as_deref()
+                            .unwrap_or(&app_config.font.font_family),
+                        font_size,
+                        f64::from(app_config.title_max_width),
+                    )
+                    .context("Couldn't elide title")?,
+            ),
+            None => None,
+        };
+        let shown_hint = if app_config.uppercase_labels {
+            hint.to_uppercase()
+        } else {
+            hint.clone()
+        };
+        // The hint and the space after it are set in the font, the title in the fallback font.
+        let hint_run = format!("{shown_hint} ");
+        let runs = match (&title, &title_font) {
+            (Some(title), Some(title_font)) => Some([
+                (hint_run.as_str(), app_config.font.font_family.as_str()),
+                (title.as_str(), title_font.as_str()),
+            ]),
+            _ => None,
+        };
+        let label = match &title {
+            Some(title) => format!("{shown_hint} {title}"),
+            None => shown_hint,
+        };
+
+        // Shrink the font until the box fits into the window.
+        let font_size = if app_config.auto_fit && !app_config.fill {
+            let (window_width, window_height) = (
+                f64::from(desktop_window.size.0),
+                f64::from(desktop_window.size.1),
+            );
+            let fits = |extents: &cairo::TextExtents| {
+                box_size(extents.width()) <= window_width
+                    && box_size(extents.height()) <= window_height
+            };
+            match &runs {
+                Some(runs) => text_measurer.fit_font_size_for_runs(
+                    runs,
+                    font_size,
+                    app_config.min_font_size,
+                    fits,
+                ),
+                None => text_measurer.fit_font_size(
+                    &label,
+                    &app_config.font.font_family,
+                    font_size,
+                    app_config.min_font_size,
+                    app_config.text_direction,
+                    fits,
+                ),
+            }
+            .context("Couldn't fit font size")?
+        } else {
+            font_size
+        };
+
+        // Figure out how large the window actually needs to be.
+        let text_extents = match &runs {
+            Some(runs) => text_measurer.extents_for_runs(runs, font_size),
+            None => text_measurer.extents_in_direction(
+                &label,
+                &app_config.font.font_family,
+                font_size,
+                app_config.text_direction,
+            ),
+        }
+        .context("Couldn't create extents for text")?;
+        let (width, height, margin_width, margin_height) = if app_config.fill {
+            // The background covers the whole window but the text sits where the alignment would
+            // put a regular box.
+            let box_width = box_size(text_extents.width()).min(f64::from(desktop_window.size.0));
+            let box_height = box_size(text_extents.height()).min(f64::from(desktop_window.size.1));
+            let (box_x, box_y) = align_box(
+                Rect::new(0, 0, desktop_window.size.0, desktop_window.size.1),
+                (box_width.round() as i32, box_height.round() as i32),
+                app_config.horizontal_align,
+                app_config.vertical_align,
+                &app_config.offset,
+            );
+            (
+                desktop_window.size.0 as u16,
+                desktop_window.size.1 as u16,
+                f64::from(box_x) + (box_width - text_extents.width()) / 2.0,
+                f64::from(box_y) + (box_height - text_extents.height()) / 2.0,
+            )
+        } else {
+            let mut box_width = box_size(text_extents.width());
+            let mut box_height = box_size(text_extents.height());
+
+            // A box larger than its window would end up covering other windows.
+            if box_width > f64::from(desktop_window.size.0)
+                || box_height > f64::from(desktop_window.size.1)
+            {
+                warn!(
+                    "Margin {:?} and padding {} make the box larger than window {}, shrinking it",
+                    app_config.margin, app_config.text_padding, desktop_window.id
+                );
+                box_width = box_width
+                    .min(f64::from(desktop_window.size.0))
+                    .max(text_extents.width());
+                box_height = box_height
+                    .min(f64::from(desktop_window.size.1))
+                    .max(text_extents.height());
+            }
+            (
+                box_width.round() as u16,
+                box_height.round() as u16,
+                (box_width - text_extents.width()) / 2.0,
+                (box_height - text_extents.height()) / 2.0,
+            )
+        };
+
+        // Due to the way cairo lays out text, we'll have to calculate the actual coordinates to
+        // put the cursor. See:
+        // https://www.cairographics.org/samples/text_align_center/
+        // https://www.cairographics.org/samples/text_extents/
+        // https://www.cairographics.org/tutorial/#L1understandingtext
+        let draw_pos = (
+            margin_width - text_extents.x_bearing(),
+            text_extents.height() + margin_height
+                - (text_extents.height() + text_extents.y_bearing()),
+        );
+        boxes.push((
+            hint,
+            desktop_window,
+            title,
+            title_font,
+            font_size,
+            draw_pos,
+            width,
+            height,
+        ));
+    }
+
+    // Boxes near the pointer are laid out all at once so that they form a grid.
+    let shadow_padding = utils::shadow_padding(&app_config.shadow_offset, app_config.shadow_blur);
+    let grid = pointer.map(|(pointer, bounds)| {
+        let sizes: Vec<(i32, i32)> = boxes
+            .iter()
+            .map(|(_, _, _, _, _, _, width, height)| {
+                (
+                    i32::from(*width) + shadow_padding.0 + shadow_padding.2,
+                    i32::from(*height) + shadow_padding.1 + shadow_padding.3,
+                )
+            })
+            .collect();
+        pointer_grid(&sizes, pointer, bounds, POINTER_GRID_GAP)
+    });
+
+    let mut hint_boxes: Vec<HintBox> = vec![];
+    for (i, (hint, desktop_window, title, title_font, font_size, draw_pos, width, height)) in
+        boxes.into_iter().enumerate()
+    {
+        let (mut x, mut y) = match &grid {
+            Some(grid) => (
+                (grid[i].0 + shadow_padding.0) as i16,
+                (grid[i].1 + shadow_padding.1) as i16,
+            ),
+            None => {
+                let window_rect = desktop_window.rect();
+                let (x, y) = align_box(
+                    window_rect,
+                    (width.into(), height.into()),
+                    app_config.horizontal_align,
+                    app_config.vertical_align,
+                    &app_config.offset,
+                );
+
+                // Don't let the offset push the box out of its window.
+                let box_rect = Rect::new(x, y, width.into(), height.into());
+                if window_rect.contains(&box_rect) {
+                    (x as i16, y as i16)
+                } else {
+                    warn!(
+                        "Box for window {} would leave the window, moving it back inside",
+                        desktop_window.id
+                    );
+                    let clamped = box_rect.clamp_into(&window_rect);
+                    (clamped.x as i16, clamped.y as i16)
+                }
+            }
+        };
+
+        // Grow the window around the box to make room for its shadow.
+        let box_size = (i32::from(width), i32::from(height));
+        x -= shadow_padding.0 as i16;
+        y -= shadow_padding.1 as i16;
+        let width = width + (shadow_padding.0 + shadow_padding.2) as u16;
+        let height = height + (shadow_padding.1 + shadow_padding.3) as u16;
+
+        // If this is overlapping then we'll nudge the new box a little bit out of the way.
+        let mut overlaps = utils::find_overlaps(
+            &hint_boxes,
+            Rect::new(x.into(), y.into(), width.into(), height.into()),
+        );
+        while !overlaps.is_empty() {
+            x += overlaps.pop().unwrap().w as i16;
+            overlaps = utils::find_overlaps(
+                &hint_boxes,
+                Rect::new(x.into(), y.into(), width.into(), height.into()),
+            );
+        }
+
+        // Cut the window down to the box and its shadow so that the rounded corners and
+        // everything around the shadow are see-through.
+        let radius = utils::clamp_corner_radius(app_config.corner_radius, box_size.0, box_size.1);
+        let shape = if radius > 0.0 || utils::has_shadow(app_config) {
+            let mut rects =
+                utils::rounded_rect_region(box_size.0 as u16, box_size.1 as u16, radius);
+            for rect in &mut rects {
+                rect.x += shadow_padding.0 as i16;
+                rect.y += shadow_padding.1 as i16;
+            }
+            if utils::has_shadow(app_config) {
+                let blur = app_config.shadow_blur as i32;
+                rects.push(xproto::Rectangle {
+                    x: (shadow_padding.0 + app_config.shadow_offset.x - blur) as i16,
+                    y: (shadow_padding.1 + app_config.shadow_offset.y - blur) as i16,
+                    width: (box_size.0 + 2 * blur) as u16,
+                    height: (box_size.1 + 2 * blur) as u16,
+                });
+            }
+            rects
+        } else {
+            vec![xproto::Rectangle {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }]
+        };
+
+        let rect = Rect::new(x.into(), y.into(), width.into(), height.into());
+
+        // Boxes away from their windows point at them. The line is part of the box's shape so
+        // that it's hidden along with it.
+        let box_rect = Rect::new(
+            rect.x + shadow_padding.0,
+            rect.y + shadow_padding.1,
+            box_size.0,
+            box_size.1,
+        );
+        let window_rect = desktop_window.rect();
+        let connector = (app_config.connector_color.is_some() && !window_rect.contains(&box_rect))
+            .then(|| {
+                (
+                    (box_rect.x + box_rect.w / 2, box_rect.y + box_rect.h / 2),
+                    (
+                        window_rect.x + window_rect.w / 2,
+                        window_rect.y + window_rect.h / 2,
+                    ),
+                )
+            });
+        let mut shape = shape;
+        if let Some((from, to)) = connector {
+            shape.extend(
+                geometry::line_region(from, to, utils::CONNECTOR_WIDTH)
+                    .into_iter()
+                    .map(|r| xproto::Rectangle {
+                        x: (r.x - rect.x) as i16,
+                        y: (r.y - rect.y) as i16,
+                        width: r.w as u16,
+                        height: r.h as u16,
+                    }),
+            );
+        }
+
+        hint_boxes.push(HintBox {
+            hint,
+            desktop_window,
+            title,
+            title_font,
+            font_size,
+            draw_pos,
+            box_pos: (shadow_padding.0.into(), shadow_padding.1.into()),
+            box_size,
+            rect,
+            shape,
+            connector,
+        });
+    }
+    Ok(hint_boxes)
+}
+
+/// Show hints for `desktop_windows` once and let the user select one.
+///
+/// The input has to be grabbed already. Presses and releases of the `held_keys` are ignored, a key
+/// is removed once it's released. All hint windows are destroyed again before returning.
+/// Returns `None` if the screen layout changed while the hints were shown, in which case they have
+/// to be placed again.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+#[allow(clippy::too_many_arguments)]
+fn show_hints(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    monitors: &[monitor::Monitor],
+    desktop_windows: Vec<DesktopWindow>,
+    keymap: Option<&utils::Keymap>,
+    held_keys: &mut HashSet<u8>,
+) -> Result<Option<Selection>> {
+    let hinted_windows = hint_windows(app_config, desktop_windows, monitors)?;
+
+    // There's nothing to choose from if there is only one other window of the same class.
+    if let [(hint, desktop_window)] = hinted_windows.as_slice() {
+        if app_config.same_class_auto {
+            info!("Only one other window of the same class, selecting it right away");
+            return Ok(Some(Selection::Window {
+                window: desktop_window.clone(),
+                hint: hint.clone(),
+            }));
+        }
+    }
+
+    // The dimming layer has to be mapped first so that it ends up beneath the hints.
+    let dim_window = if app_config.dim > 0.0 {
+        Some(create_dim_window(conn, atoms, screen, app_config.dim)?)
+    } else {
+        None
+    };
+
+    let mut text_measurer = measure::TextMeasurer::new()?;
+    text_measurer.set_fast_metrics(app_config.fast_metrics);
+    let root_geometry = conn
+        .get_geometry(screen.root)?
+        .reply()
+        .context("Couldn't get screen size")?;
+    let screen_rect = Rect::new(
+        0,
+        0,
+        root_geometry.width.into(),
+        root_geometry.height.into(),
+    );
+    let pointer = if app_config.hint_position_relative_to == args::HintAnchor::Pointer {
+        let pointer = conn
+            .query_pointer(screen.root)?
+            .reply()
+            .context("Couldn't query pointer position")?;
+        Some(((pointer.root_x.into(), pointer.root_y.into()), screen_rect))
+    } else {
+        None
+    };
+    let hint_boxes = layout_hints(
+        app_config,
+        &hinted_windows,
+        monitors,
+        &mut text_measurer,
+        pointer,
+    )?;
+
+    let visual = utils::HintVisual::new(conn, screen)?;
+
+    // With a single renderer all hints are drawn onto one window that covers the whole screen.
+    let overlay = if app_config.renderer == args::Renderer::Single {
+        Some(create_hint_window(
+            conn,
+            atoms,
+            screen,
+            &visual,
+            app_config,
+            screen_rect,
+            Some(&[]),
+        )?)
+    } else {
+        None
+    };
+
+    // Assemble RenderWindows from the laid out boxes.
+    let mut render_windows = HashMap::new();
+    for hint_box in hint_boxes {
+        debug!(
+            "Spawning RenderWindow for this DesktopWindow: {:?}",
+            hint_box.desktop_window
+        );
+        let rect = hint_box.rect;
+        let (xcb_window_id, cairo_context) = match &overlay {
+            Some((overlay_window, overlay_surface)) => {
+                let surface = overlay_surface
+                    .create_for_rectangle(cairo::Rectangle::new(
+                        rect.x.into(),
+                        rect.y.into(),
+                        rect.w.into(),
+                        rect.h.into(),
+                    ))
+                    .context("Couldn't create Cairo Surface")?;
+                (*overlay_window, cairo::Context::new(&surface))
+            }
+            None => {
+                let (window, surface) = create_hint_window(
+                    conn,
+                    atoms,
+                    screen,
+                    &visual,
+                    app_config,
+                    rect,
+                    Some(&hint_box.shape),
+                )?;
+                (window, cairo::Context::new(&surface))
+            }
+        };
+        let cairo_context = cairo_context.context("Couldn't create Cairo Context")?;
+        utils::set_antialias(&cairo_context, app_config.antialias)?;
+        render_windows.insert(
+            hint_box.hint.to_string(),
+            RenderWindow::new(hint_box, xcb_window_id, cairo_context),
+        );
+    }
+
+    // The legend is mapped last so that it ends up above the hints.
+    let legend = if app_config.legend != args::LegendPosition::Off {
+        let legend = legend::Legend::new(app_config, &mut text_measurer, &hinted_windows)?;
+        let rect = legend.rect(app_config.legend, screen_rect);
+        let (window, surface) =
+            create_hint_window(conn, atoms, screen, &visual, app_config, rect, None)?;
+        let context = cairo::Context::new(&surface).context("Couldn't create Cairo Context")?;
+        utils::set_antialias(&context, app_config.antialias)?;
+        Some((legend, window, context))
+    } else {
+        None
+    };
+    let draw_legend = |pressed_keys: &str| -> Result<()> {
+        if let Some((legend, _, context)) = &legend {
+            legend
+                .draw(context, app_config, pressed_keys)
+                .context("Couldn't draw legend")?;
+        }
+        Ok(())
+    };
+
+    // The overlay only shows its parts that are covered by hints.
+    let mut hint_windows: Vec<u32> = match &overlay {
+        Some((overlay_window, _)) => {
+            utils::update_hint_visibility(conn, &render_windows, "", Some(*overlay_window))?;
+            vec![*overlay_window]
+        }
+        None => render_windows.values().map(|rw| rw.xcb_window_id).collect(),
+    };
+    hint_windows.extend(legend.as_ref().map(|(_, window, _)| *window));
+    let overlay_window = overlay.as_ref().map(|(window, _)| *window);
+    let connector_context = match (&overlay, app_config.connector_color) {
+        (Some((_, overlay_surface)), Some(_)) => {
+            Some(cairo::Context::new(overlay_surface).context("Couldn't create Cairo Context")?)
+        }
+        _ => None,
+    };
+
+    // Since we might have lots of windows on the desktop, it might be required
+    // to enter a sequence in order to get to the correct window.
+    // We'll have to track the keys pressed so far.
+    let mut keys = HintKeys::new(app_config);
+
+    // Without a timeout we can simply block until the next event arrives. Otherwise, we'll have
+    // to poll so that we notice when the user has been inactive for too long.
+    let timeout = (app_config.timeout > 0).then(|| Duration::from_secs(app_config.timeout));
+    let mut last_activity = Instant::now();
+    let mut selection = Selection::Cancelled;
+
+    // Fading in also needs polling so that we get to update the opacity in between events.
+    let fade_in = Duration::from_millis(app_config.fade_in);
+    let fade_start = Instant::now();
+    let mut fade_step = (!fade_in.is_zero()).then_some(0);
+    let set_hints_opacity = |opacity: f64| -> Result<()> {
+        for &window in &hint_windows {
+            utils::set_opacity(conn, atoms, window, opacity)?;
+        }
+        conn.flush()?;
+        Ok(())
+    };
+
+    let mut screen_changed = false;
+    let mut closed = false;
+    while !closed {
+        if let Some(step) = fade_step {
+            let elapsed = fade_start.elapsed().as_secs_f64() / fade_in.as_secs_f64();
+            let current_step = ((elapsed * f64::from(FADE_STEPS)) as u32).min(FADE_STEPS);
+            if current_step > step {
+                let progress = f64::from(current_step) / f64::from(FADE_STEPS);
+                set_hints_opacity(app_config.bg_color.3 * progress)?;
+            }
+            fade_step = (current_step < FADE_STEPS).then_some(current_step);
+        }
+        let event_option = if timeout.is_some() || fade_step.is_some() {
+            let event = conn.poll_for_event().context("No events")?;
+            if event.is_none() {
+                if fade_step.is_some()
+                    || timeout.is_some_and(|timeout| last_activity.elapsed() < timeout)
+                {
+                    sleep(Duration::from_millis(10));
+                    continue;
+                }
+                info!(
+                    "No key pressed within {:?}, exiting",
+                    timeout.unwrap_or_default()
+                );
+                selection = Selection::TimedOut;
+            }
+            event
+        } else {
+            Some(conn.wait_for_event().context("No events")?)
+        };
+        if let Some(e) = event_option {
+            match e {
+                Event::Expose(_) => {
+                    // The boxes are drawn on top of the lines leading away from them.
+                    if let (Some(context), Some(color)) =
+                        (&connector_context, app_config.connector_color)
+                    {
+                        utils::draw_connectors(context, &render_windows, color)
+                            .context("Couldn't draw connectors")?;
+                    }
+                    for (hint, rw) in &render_windows {
+                        utils::draw_hint_text(rw, app_config, hint, &keys.pressed_keys)
+                            .context("Couldn't draw hint text")?;
+                        conn.flush()?;
+                    }
+                    draw_legend(&keys.pressed_keys)?;
+                    conn.flush()?;
+                }
+                Event::ButtonPress(_) => {
+                    closed = true;
+                }
+                Event::FocusOut(e) if e.mode != xproto::NotifyMode::GRAB => {
+                    warn!("Lost keyboard focus ({:?}), exiting", e.mode);
+                    closed = true;
+                }
+                Event::VisibilityNotify(e) if e.state == xproto::Visibility::FULLY_OBSCURED => {
+                    warn!("Hint window 0x{:x} got obscured, exiting", e.window);
+                    closed = true;
+                }
+                Event::RandrScreenChangeNotify(e) => {
+                    info!(
+                        "Screen changed to {}x{}, placing hints again",
+                        e.width, e.height
+                    );
+                    screen_changed = true;
+                    closed = true;
+                }
+                // Keys held down since before the grab, like the ones of the keybinding that
+                // started us, repeat or get released without the user typing anything.
+                _ if utils::event_keycode(&e).is_some_and(|k| held_keys.contains(&k)) => {
+                    if matches!(e, Event::KeyRelease(_) | Event::XinputRawKeyRelease(_)) {
+                        held_keys.retain(|&k| Some(k) != utils::event_keycode(&e));
+                    }
+                }
+                Event::KeyRelease(_) | Event::XinputRawKeyRelease(_) => {
+                    let ksym = utils::get_pressed_symbol(conn, e, keymap)?;
+                    keys.release(ksym)?;
+                }
+                Event::KeyPress(_) | Event::XinputRawKeyPress(_) => {
+                    last_activity = Instant::now();
+
+                    // Don't make the user wait for the fade to finish.
+                    if fade_step.take().is_some() {
+                        set_hints_opacity(app_config.bg_color.3)?;
+                    }
+                    let ksym = utils::get_pressed_symbol(conn, e, keymap)?;
+                    match keys.press(ksym, render_windows.keys())? {
+                        KeyPress::Cancel => closed = true,
+                        KeyPress::Select(hint) => {
+                            selection = Selection::Window {
+                                window: render_windows[&hint].desktop_window.clone(),
+                                hint,
+                            };
+                            closed = true;
+                        }
+                        KeyPress::Update => {
+                            // Only the hints that still match the typed keys are shown.
+                            utils::update_hint_visibility(
+                                conn,
+                                &render_windows,
+                                &keys.pressed_keys,
+                                overlay_window,
+                            )?;
+                            for (hint, rw) in &render_windows {
+                                utils::draw_hint_text(rw, app_config, hint, &keys.pressed_keys)
+                                    .context("Couldn't draw hint text")?;
+                            }
+                            draw_legend(&keys.pressed_keys)?;
+                            conn.flush()?;
+                        }
+                        KeyPress::Reject { close } => {
+                            if !app_config.no_bell {
+                                conn.bell(0)?;
+                                conn.flush()?;
+                            }
+                            closed = close;
+                        }
+                        KeyPress::Ignore => {}
+                    }
+                }
+                _ => {}
+            }
+        } else {
+            closed = true;
+        }
+    }
+
+    for &window in &hint_windows {
+        conn.destroy_window(window)?;
+    }
+    if let Some(dim_window) = dim_window {
+        conn.destroy_window(dim_window)?;
+    }
+    visual.free(conn)?;
+    conn.flush()?;
+
+    if screen_changed {
+        return Ok(None);
+    }
+    Ok(Some(selection))
+}
+
+/// Draw the hints for `desktop_windows` onto `surface` like they'd be shown on a screen of its size.
+///
+/// This doesn't need an X server. The alpha of `bg_color` is left to the compositor on a screen so
+/// it isn't applied here either.
+#[cfg(test)]
+fn render_to_surface(
+    app_config: &AppConfig,
+    surface: &cairo::ImageSurface,
+    desktop_windows: Vec<DesktopWindow>,
+) -> Result<()> {
+    let hinted_windows = hint_windows(app_config, desktop_windows, &[])?;
+    let mut text_measurer = measure::TextMeasurer::new()?;
+    text_measurer.set_fast_metrics(app_config.fast_metrics);
+    let screen_rect = Rect::new(0, 0, surface.width(), surface.height());
+    // Without a real pointer the grid goes to the center of the screen.
+    let pointer = (app_config.hint_position_relative_to == args::HintAnchor::Pointer)
+        .then_some(((screen_rect.w / 2, screen_rect.h / 2), screen_rect));
+    let hint_boxes = layout_hints(
+        app_config,
+        &hinted_windows,
+        &[],
+        &mut text_measurer,
+        pointer,
+    )?;
+    draw_on_surface(app_config, surface, hint_boxes)?;
+    Ok(())
+}
+
+/// Draw `hint_boxes` onto their parts of `surface` along with the connectors between them.
+///
+/// The returned `RenderWindow`s keep drawing onto `surface` when the typed keys change.
+#[cfg(any(test, feature = "wayland"))]
+fn draw_on_surface<'a>(
+    app_config: &AppConfig,
+    surface: &cairo::ImageSurface,
+    hint_boxes: Vec<HintBox<'a>>,
+) -> Result<HashMap<String, RenderWindow<'a>>> {
+    let mut render_windows = HashMap::new();
+    for hint_box in hint_boxes {
+        let rect = hint_box.rect;
+        let box_surface = surface
+            .create_for_rectangle(cairo::Rectangle::new(
+                rect.x.into(),
+                rect.y.into(),
+                rect.w.into(),
+                rect.h.into(),
+            ))
+            .context("Couldn't create Cairo Surface")?;
+        let cairo_context =
+            cairo::Context::new(&box_surface).context("Couldn't create Cairo Context")?;
+        utils::set_antialias(&cairo_context, app_config.antialias)?;
+        render_windows.insert(
+            hint_box.hint.to_string(),
+            RenderWindow::new(hint_box, 0, cairo_context),
+        );
+    }
+
+    if let Some(color) = app_config.connector_color {
+        let context = cairo::Context::new(surface).context("Couldn't create Cairo Context")?;
+        utils::draw_connectors(&context, &render_windows, color)
+            .context("Couldn't draw connectors")?;
+    }
+    for (hint, rw) in &render_windows {
+        utils::draw_hint_text(rw, app_config, hint, "").context("Couldn't draw hint text")?;
+    }
+    surface.flush();
+    Ok(render_windows)
+}
+
+/// Focus `desktop_window` using `app_config.focus_method`.
+///
+/// Windows without an X window can only be focused by the window manager.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn focus_window(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    desktop_window: &DesktopWindow,
+) -> Result<()> {
+    let x_window_id = match desktop_window.x_window_id {
+        Some(id) if app_config.focus_method != args::FocusMethod::Wm => id as u32,
+        _ => {
+            wm::focus_window(desktop_window).context("Couldn't focus window")?;
+            return match desktop_window.x_window_id {
+                Some(id) if app_config.raise => {
+                    utils::raise_window(conn, atoms, screen.root, id as u32)
+                        .context("Couldn't raise window")
+                }
+                _ => Ok(()),
+            };
+        }
+    };
+    if app_config.all_desktops {
+        ewmh::switch_to_desktop_of(conn, atoms, screen.root, x_window_id)
+            .context("Couldn't switch desktop")?;
+    }
+    let use_ewmh = match app_config.focus_method {
+        args::FocusMethod::Ewmh => true,
+        args::FocusMethod::Xinput => false,
+        _ => utils::supports_active_window(conn, atoms, screen.root)?,
+    };
+    // Activating a window also brings it back if it's minimized.
+    if use_ewmh {
+        utils::activate_window(conn, atoms, screen.root, x_window_id)
+            .context("Couldn't activate window")?;
+    } else {
+        // Unmapped windows can't get the focus. Asking for them to be mapped again lets the window
+        // manager restore them.
+        if utils::is_minimized(conn, atoms, x_window_id)? {
+            info!("Restoring minimized window 0x{:x}", x_window_id);
+            conn.map_window(x_window_id)?;
+            conn.sync()?;
+        }
+        utils::set_input_focus(conn, x_window_id).context("Couldn't focus window")?;
+    }
+    if app_config.raise {
+        utils::raise_window(conn, atoms, screen.root, x_window_id)
+            .context("Couldn't raise window")?;
+    }
+    Ok(())
+}
+
+/// Perform `app_config.action` on the selected `desktop_window`.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn perform_action(
+    app_config: &AppConfig,
+    conn: &XCBConnection,
+    atoms: &properties::Atoms,
+    screen: &xproto::Screen,
+    desktop_window: &DesktopWindow,
+) -> Result<()> {
+    match app_config.action {
+        args::Action::Focus => focus_window(app_config, conn, atoms, screen, desktop_window)?,
+        args::Action::Close => {
+            let x_window_id = desktop_window
+                .x_window_id
+                .context("Window has no X window to close")?;
+            utils::close_window(conn, atoms, x_window_id as u32)
+                .context("Couldn't close window")?;
+        }
+        args::Action::Kill => {
+            let x_window_id = desktop_window
+                .x_window_id
+                .context("Window has no X window to kill")?;
+            info!("Killing client of window 0x{:x}", x_window_id);
+            conn.kill_client(x_window_id as u32)?;
+            conn.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Connect to the display server `app_config.backend` selects and hand it to `f`.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn with_backend<T>(
+    app_config: &mut AppConfig,
+    f: impl FnOnce(&mut dyn backend::Backend, &AppConfig) -> Result<T>,
+) -> Result<T> {
+    match backend::select(app_config.backend) {
+        args::BackendSelection::Wayland => {
+            #[cfg(feature = "wayland")]
+            {
+                let mut backend = wayland::WaylandBackend::connect(app_config)?;
+                f(&mut backend, app_config)
+            }
+            #[cfg(not(feature = "wayland"))]
+            {
+                backend::no_wayland()?;
+                unreachable!()
+            }
+        }
+        _ => {
+            let (conn, screen_num) = connect()?;
+            let mut backend = backend::XcbBackend::new(&conn, screen_num, app_config)?;
+            f(&mut backend, app_config)
+        }
+    }
+}
+
+/// Show hints for all windows and let the user select one.
+///
+/// Unless `app_config.print_only` is set, `app_config.action` is performed on the selected window.
+/// With `app_config.repeat`, hints are shown again after every action until the selection is
+/// cancelled or times out. Unless an action focused another window, the input focus from before is
+/// restored however this returns.
+///
+/// Configs that don't pass `AppConfig::validate` are refused before connecting to anything.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+pub fn run(mut app_config: AppConfig) -> Result<Selection> {
+    app_config.validate()?;
+    with_backend(&mut app_config, run_backend)
+}
+
+/// Show hints on `backend` until a selection ends it.
+#[cfg(any(feature = "i3", feature = "add_some_other_wm_here"))]
+fn run_backend(backend: &mut dyn backend::Backend, app_config: &AppConfig) -> Result<Selection> {
+    loop {
+        // Grabbing the input without showing any hints would leave the user stuck.
+        let desktop_windows = backend.list_windows(app_config)?;
+        if desktop_windows.is_empty() {
+            info!("No windows to hint");
+            return Ok(Selection::NoWindows);
+        }
+
+        let Some(selection) = backend.show_hints(app_config, desktop_windows)? else {
+            backend.update_monitors(app_config)?;
+            continue;
+        };
+        let Selection::Window {
+            window: desktop_window,
+            ..
+        } = &selection
+        else {
+            return Ok(selection);
+        };
+        if !app_config.print_only {
+            backend.perform_action(app_config, desktop_window)?;
+        }
+        if !app_config.repeat {
+            return Ok(selection);
+        }
+
+        backend.settle()?;
+        info!("Repeating, showing hints again");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(id: i64) -> DesktopWindow {
+        DesktopWindow {
+            id,
+            x_window_id: None,
+            class: None,
+            instance: None,
+            title: None,
+            target: None,
+            pos: (0, 0),
+            size: (100, 100),
+            is_focused: false,
+        }
+    }
+
+    #[test]
+    fn test_hint_map() {
+        let windows = vec![window(10), window(20), window(30)];
+        let map = hint_map(&windows, "ab", args::HintLength::Uniform).unwrap();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[&10], "aa");
+        assert_eq!(map[&20], "ab");
+        assert_eq!(map[&30], "ba");
+
+        let map = hint_map(&windows, "ab", args::HintLength::Shortest).unwrap();
+        assert_eq!(map[&10], "a");
+        assert_eq!(map[&30], "bb");
+
+        let err = hint_map(&[window(1), window(1)], "ab", args::HintLength::Uniform).unwrap_err();
+        assert!(err.to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn test_assign_hints() {
+        let windows = vec![window(1), window(2), window(3)];
+        let hints = |hint_length, max_hint_length| -> Vec<(String, i64)> {
+            assign_hints(windows.clone(), "ab", hint_length, max_hint_length, &[])
+                .unwrap()
+                .into_iter()
+                .map(|(h, w)| (h, w.id))
+                .collect()
+        };
+        let uniform = hints(args::HintLength::Uniform, Some(3));
+        assert_eq!(
+            uniform,
+            vec![("aa".into(), 1), ("ab".into(), 2), ("ba".into(), 3)]
+        );
+        assert_eq!(hints(args::HintLength::Uniform, None), uniform);
+        assert_eq!(
+            hints(args::HintLength::Shortest, Some(3)),
+            vec![("a".into(), 1), ("ba".into(), 2), ("bb".into(), 3)]
+        );
+    }
+
+    #[test]
+    fn test_assign_hints_numeric() {
+        let windows: Vec<_> = (1..=13).map(window).collect();
+        let hinted = assign_hints(windows, "ab", args::HintLength::Uniform, Some(1), &[]).unwrap();
+        let hints: Vec<&str> = hinted.iter().map(|(h, _)| h.as_str()).collect();
+        assert_eq!(hints[..4], ["a", "b", "01", "02"]);
+        assert_eq!(hints[12], "11");
+
+        // Pinned hints take away from the hints that fit.
+        let mut windows = vec![window(1), window(2), window(3)];
+        windows[2].class = Some("firefox".to_string());
+        let pins = [args::parse_pin("firefox=a").unwrap()];
+        let hinted =
+            assign_hints(windows, "ab", args::HintLength::Uniform, Some(1), &pins).unwrap();
+        let hints: Vec<(&str, i64)> = hinted.iter().map(|(h, w)| (h.as_str(), w.id)).collect();
+        assert_eq!(hints, vec![("b", 1), ("1", 2), ("a", 3)]);
+    }
+
+    #[test]
+    fn test_assign_hints_no_windows() {
+        for hint_length in [args::HintLength::Uniform, args::HintLength::Shortest] {
+            assert!(assign_hints(vec![], "ab", hint_length, Some(1), &[])
+                .unwrap()
+                .is_empty());
+        }
+        let hinted = assign_monitor_hints(
+            vec![],
+            &[],
+            "12",
+            "ab",
+            args::HintLength::Uniform,
+            None,
+            &[],
+        );
+        assert!(hinted.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_assign_hints_pinned() {
+        let mut windows = vec![window(1), window(2), window(3), window(4)];
+        windows[1].class = Some("Firefox".to_string());
+        windows[2].class = Some("firefox".to_string());
+        let pins = [
+            args::parse_pin("firefox=b").unwrap(),
+            args::parse_pin("Thunderbird=c").unwrap(),
+        ];
+        let hinted = assign_hints(windows, "abc", args::HintLength::Uniform, None, &pins).unwrap();
+        let hints: Vec<(&str, i64)> = hinted.iter().map(|(h, w)| (h.as_str(), w.id)).collect();
+        // Only the first Firefox window is pinned, and no other hint starts with "b".
+        assert_eq!(hints, vec![("aa", 1), ("b", 2), ("ab", 3), ("ac", 4)]);
+    }
+
+    #[test]
+    fn test_match_hint() {
+        let hints = ["aa".to_string(), "ab".to_string(), "ba".to_string()];
+        assert_eq!(match_hint(&hints, ""), HintMatch::None);
+        assert_eq!(match_hint(&hints, "a"), HintMatch::Partial);
+        assert_eq!(
+            match_hint(&hints, "ab"),
+            HintMatch::Unique("ab".to_string())
+        );
+        assert_eq!(match_hint(&hints, "b"), HintMatch::Unique("ba".to_string()));
+        assert_eq!(match_hint(&hints, "c"), HintMatch::None);
+        // A single window isn't selected before anything valid was typed.
+        assert_eq!(match_hint(&hints[..1], ""), HintMatch::None);
+    }
+
+    #[test]
+    fn test_hint_keys() {
+        let app_config = args::test_config(&["wmfocus", "--chars", "ab"]);
+        let hints = ["aa".to_string(), "ab".to_string(), "ba".to_string()];
+        let mut keys = HintKeys::new(&app_config);
+        let mut press = |ksym| {
+            let press = keys.press(ksym, &hints).unwrap();
+            keys.release(ksym).unwrap();
+            press
+        };
+        assert_eq!(press(xkeysym::KEY_a), KeyPress::Update);
+        assert_eq!(press(xkeysym::KEY_x), KeyPress::Reject { close: false });
+        assert_eq!(press(xkeysym::KEY_BackSpace), KeyPress::Update);
+        assert_eq!(press(xkeysym::KEY_b), KeyPress::Select("ba".to_string()));
+        assert_eq!(keys.pressed_keys, "b");
+    }
+
+    #[test]
+    fn test_hint_keys_numeric() {
+        let hints = [
+            "a".to_string(),
+            "b".to_string(),
+            "1".to_string(),
+            "2".to_string(),
+        ];
+        let app_config = args::test_config(&["wmfocus", "--chars", "ab"]);
+        let mut keys = HintKeys::new(&app_config);
+        assert_eq!(
+            keys.press(xkeysym::KEY_2, &hints).unwrap(),
+            KeyPress::Reject { close: true }
+        );
+
+        let app_config = args::test_config(&["wmfocus", "--chars", "ab", "--max-hint-length", "1"]);
+        let mut keys = HintKeys::new(&app_config);
+        assert_eq!(
+            keys.press(xkeysym::KEY_2, &hints).unwrap(),
+            KeyPress::Select("2".to_string())
+        );
+    }
+
+    #[test]
+    fn test_align_box() {
+        use args::{HorizontalAlign, Offset, VerticalAlign};
+        let container = Rect::new(100, 100, 200, 100);
+        let no_offset = Offset { x: 0, y: 0 };
+        assert_eq!(
+            align_box(
+                container,
+                (20, 10),
+                HorizontalAlign::Left,
+                VerticalAlign::Top,
+                &no_offset
+            ),
+            (100, 100)
+        );
+        assert_eq!(
+            align_box(
+                container,
+                (20, 10),
+                HorizontalAlign::Center,
+                VerticalAlign::Bottom,
+                &no_offset
+            ),
+            (190, 190)
+        );
+        // Offsets push boxes away from the edge they are aligned to.
+        assert_eq!(
+            align_box(
+                container,
+                (20, 10),
+                HorizontalAlign::Right,
+                VerticalAlign::Bottom,
+                &Offset { x: 5, y: 5 }
+            ),
+            (275, 185)
+        );
+    }
+
+    #[test]
+    fn test_pointer_grid() {
+        let bounds = Rect::new(0, 0, 1000, 1000);
+        assert!(pointer_grid(&[], (500, 500), bounds, 4).is_empty());
+        // Three boxes fill a 2x2 grid of 20x10 cells, the narrow one is centered in its cell.
+        let sizes = [(20, 10), (20, 10), (10, 10)];
+        assert_eq!(
+            pointer_grid(&sizes, (500, 500), bounds, 4),
+            vec![(478, 488), (502, 488), (483, 502)]
+        );
+        // Near the corner the grid stays on the screen.
+        assert_eq!(
+            pointer_grid(&sizes, (0, 0), bounds, 4),
+            vec![(0, 0), (24, 0), (5, 14)]
+        );
+    }
+
+    #[test]
+    fn test_assign_monitor_hints() {
+        let monitor = |name: &str, x| monitor::Monitor {
+            name: name.to_string(),
+            rect: Rect::new(x, 0, 1000, 1000),
+            size_mm: (0, 0),
+            primary: false,
+            outputs: vec![],
+        };
+        let monitors = [monitor("DP-1", 0), monitor("DP-2", 1000)];
+        let mut windows = vec![window(1), window(2), window(3), window(4)];
+        windows[1].pos = (1100, 0);
+        windows[2].pos = (1200, 0);
+        windows[3].pos = (-500, -500);
+        let hinted = assign_monitor_hints(
+            windows.clone(),
+            &monitors,
+            "123",
+            "ab",
+            args::HintLength::Uniform,
+            None,
+            &[],
+        )
+        .unwrap();
+        let hints: Vec<(&str, i64)> = hinted.iter().map(|(h, w)| (h.as_str(), w.id)).collect();
+        assert_eq!(hints, vec![("1a", 1), ("2a", 2), ("2b", 3), ("3a", 4)]);
+
+        let err = assign_monitor_hints(
+            windows,
+            &monitors,
+            "12",
+            "ab",
+            args::HintLength::Uniform,
+            None,
+            &[],
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("Not enough monitor characters"));
+    }
+
+    /// Compare `surface` to the image `name` in `tests/golden`, which is written instead if
+    /// `WMFOCUS_UPDATE_GOLDEN` is set.
+    ///
+    /// A few pixels may differ as text comes out slightly differently between font renderers.
+    fn assert_golden(mut surface: cairo::ImageSurface, name: &str) {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests")
+            .join("golden")
+            .join(name);
+        if std::env::var_os("WMFOCUS_UPDATE_GOLDEN").is_some() {
+            let mut file = std::fs::File::create(&path).unwrap();
+            surface.write_to_png(&mut file).unwrap();
+            return;
+        }
+        let mut file = std::fs::File::open(&path).unwrap();
+        let mut golden = cairo::ImageSurface::create_from_png(&mut file).unwrap();
+        assert_eq!(
+            (golden.width(), golden.height(), golden.format()),
+            (surface.width(), surface.height(), surface.format())
+        );
+        let (actual, expected) = (surface.data().unwrap(), golden.data().unwrap());
+        let differing = actual
+            .chunks(4)
+            .zip(expected.chunks(4))
+            .filter(|(a, e)| a.iter().zip(e.iter()).any(|(a, e)| a.abs_diff(*e) > 16))
+            .count();
+        let pixels = actual.len() / 4;
+        assert!(
+            differing * 100 < pixels,
+            "{differing} of {pixels} pixels differ from {}",
+            path.display()
+        );
+    }
+
+    #[test]
+    fn test_render_two_windows() {
+        let app_config = args::test_config(&["wmfocus"]);
+        let mut windows = vec![window(1), window(2)];
+        windows[0].size = (400, 300);
+        windows[1].pos = (400, 0);
+        windows[1].size = (400, 300);
+        windows[1].is_focused = true;
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 800, 300).unwrap();
+        render_to_surface(&app_config, &surface, windows).unwrap();
+        assert_golden(surface, "two_windows.png");
+    }
+
+    #[test]
+    fn test_render_title_chip() {
+        let app_config = args::test_config(&["wmfocus", "--show-title", "--font", "Mono:24"]);
+        let mut windows = vec![window(1), window(2)];
+        windows[0].size = (400, 100);
+        windows[0].title = Some("alacritty".to_string());
+        windows[1].pos = (400, 0);
+        windows[1].size = (400, 100);
+        windows[1].title = Some("Mozilla Firefox".to_string());
+        windows[1].is_focused = true;
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 800, 100).unwrap();
+        render_to_surface(&app_config, &surface, windows).unwrap();
+        assert_golden(surface, "title_chip.png");
+    }
+}
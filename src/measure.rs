@@ -0,0 +1,455 @@
+//! Text measurement without drawing anything.
+//!
+//! All extents are in the text coordinate space of cairo: the origin is the left end of the
+//! baseline where drawing would start, x grows to the right and y grows downwards. This means
+//! that `x_bearing` is the horizontal offset from the origin to the left edge of the ink and is
+//! usually small and positive, and `y_bearing` is the vertical offset from the baseline to the top
+//! of the ink and is negative for text that rises above the baseline.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use log::trace;
+
+use crate::args::TextDirection;
+
+/// Measures text extents using a single reusable cairo `Context`.
+///
+/// Creating a surface and a context for every measurement is rather expensive so we keep one
+/// around and only change its font if a different family or size is requested.
+pub struct TextMeasurer {
+    context: cairo::Context,
+    font: Option<(String, f64)>,
+    slant: cairo::FontSlant,
+    weight: cairo::FontWeight,
+    /// Extents of single characters in the current font if fast metrics are enabled.
+    glyphs: Option<HashMap<char, cairo::TextExtents>>,
+}
+
+impl TextMeasurer {
+    /// Create a measurer backed by its own tiny image surface.
+    ///
+    /// Text extents don't depend on the size of the target surface so a single pixel is enough.
+    pub fn new() -> Result<TextMeasurer> {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1)
+            .context("Couldn't create ImageSurface")?;
+        let context = cairo::Context::new(&surface).context("Couldn't create Cairo Surface")?;
+        Ok(TextMeasurer::with_context(context))
+    }
+
+    /// Create a measurer that measures using an existing `context`.
+    ///
+    /// The font face and size of `context` are changed as needed by the measurer.
+    pub fn with_context(context: cairo::Context) -> TextMeasurer {
+        TextMeasurer {
+            context,
+            font: None,
+            slant: cairo::FontSlant::Normal,
+            weight: cairo::FontWeight::Normal,
+            glyphs: None,
+        }
+    }
+
+    /// Measure ASCII text by adding up the extents of its characters from now on.
+    ///
+    /// Each character is only measured once per font. This gives the same extents as measuring the
+    /// whole text because cairo's text API doesn't do kerning.
+    pub fn set_fast_metrics(&mut self, enabled: bool) {
+        self.glyphs = enabled.then(HashMap::new);
+    }
+
+    /// Measure with `slant` and `weight` from now on instead of the normal style.
+    pub fn set_font_style(&mut self, slant: cairo::FontSlant, weight: cairo::FontWeight) {
+        if (slant, weight) != (self.slant, self.weight) {
+            self.slant = slant;
+            self.weight = weight;
+            // Force selecting the font face again on the next measurement.
+            self.font = None;
+        }
+    }
+
+    fn set_font(&mut self, family: &str, size: f64) {
+        let font_changed = self
+            .font
+            .as_ref()
+            .is_none_or(|(f, s)| f != family || *s != size);
+        if font_changed {
+            trace!("Switching measuring font to {}:{}", family, size);
+            self.context
+                .select_font_face(family, self.slant, self.weight);
+            self.context.set_font_size(size);
+            self.font = Some((family.to_string(), size));
+            if let Some(glyphs) = &mut self.glyphs {
+                glyphs.clear();
+            }
+        }
+    }
+
+    /// Extents of `text` when set in `family` at `size`.
+    ///
+    /// See the [module documentation](self) for the meaning of the bearings.
+    pub fn extents_for_text(
+        &mut self,
+        text: &str,
+        family: &str,
+        size: f64,
+    ) -> Result<cairo::TextExtents> {
+        self.set_font(family, size);
+        if self.glyphs.is_some() && text.is_ascii() {
+            return self.extents_from_glyphs(text);
+        }
+        let extents = self
+            .context
+            .text_extents(text)
+            .context("Couldn't create TextExtents")?;
+        trace!("Extents for '{}': {:?}", text, extents);
+        Ok(extents)
+    }
+
+    /// Extents of the font `family` at `size`.
+    pub fn font_extents(&mut self, family: &str, size: f64) -> Result<cairo::FontExtents> {
+        self.set_font(family, size);
+        self.context
+            .font_extents()
+            .context("Couldn't create FontExtents")
+    }
+
+    /// Extents of `text` put together from the extents of its characters.
+    fn extents_from_glyphs(&mut self, text: &str) -> Result<cairo::TextExtents> {
+        let glyphs = self.glyphs.get_or_insert_with(HashMap::new);
+        let mut pen: f64 = 0.0;
+        // The ink box as (left, top, right, bottom).
+        let mut ink: Option<(f64, f64, f64, f64)> = None;
+        for c in text.chars() {
+            let glyph = match glyphs.get(&c) {
+                Some(glyph) => *glyph,
+                None => {
+                    let glyph = self
+                        .context
+                        .text_extents(&c.to_string())
+                        .context("Couldn't create TextExtents")?;
+                    glyphs.insert(c, glyph);
+                    glyph
+                }
+            };
+            add_ink(&mut ink, pen, &glyph);
+            pen += glyph.x_advance();
+        }
+        let extents = extents_from_ink(ink, pen);
+        trace!("Extents for '{}' from glyphs: {:?}", text, extents);
+        Ok(extents)
+    }
+
+    /// Extents of text made of `runs` that are each set in their own family, one after another.
+    ///
+    /// Every run is given as its text and family.
+    pub fn extents_for_runs(
+        &mut self,
+        runs: &[(&str, &str)],
+        size: f64,
+    ) -> Result<cairo::TextExtents> {
+        let mut pen: f64 = 0.0;
+        let mut ink = None;
+        for (text, family) in runs {
+            let extents = self.extents_for_text(text, family, size)?;
+            add_ink(&mut ink, pen, &extents);
+            pen += extents.x_advance();
+        }
+        Ok(extents_from_ink(ink, pen))
+    }
+
+    /// Returns true if the font `family` has a glyph for every character of `text`.
+    ///
+    /// Cairo draws missing glyphs as boxes instead of looking for them in other fonts.
+    pub fn has_glyphs(&mut self, text: &str, family: &str, size: f64) -> Result<bool> {
+        self.set_font(family, size);
+        let (glyphs, _) = self
+            .context
+            .scaled_font()
+            .text_to_glyphs(0.0, 0.0, text)
+            .context("Couldn't convert text to glyphs")?;
+        // Glyph 0 is the box that fonts show for characters they don't have.
+        Ok(glyphs.iter().all(|glyph| glyph.index() != 0))
+    }
+
+    /// Extents of `text` when its characters are stacked in a single column.
+    ///
+    /// The column is as wide as the widest advance of its characters and every row is as high as
+    /// the ascent plus descent of the font. The origin is at the left end of the baseline of the
+    /// first row, so `x_bearing` is 0 and `y_bearing` is the negative ascent.
+    pub fn extents_for_column(
+        &mut self,
+        text: &str,
+        family: &str,
+        size: f64,
+    ) -> Result<cairo::TextExtents> {
+        self.set_font(family, size);
+        let font_extents = self
+            .context
+            .font_extents()
+            .context("Couldn't create FontExtents")?;
+        let row_height = font_extents.ascent() + font_extents.descent();
+        let mut width: f64 = 0.0;
+        for c in text.chars() {
+            let extents = self
+                .context
+                .text_extents(&c.to_string())
+                .context("Couldn't create TextExtents")?;
+            width = width.max(extents.x_advance());
+        }
+        let height = row_height * text.chars().count() as f64;
+        let extents =
+            cairo::TextExtents::new(0.0, -font_extents.ascent(), width, height, 0.0, height);
+        trace!("Column extents for '{}': {:?}", text, extents);
+        Ok(extents)
+    }
+
+    /// Extents of `text` laid out in `direction`.
+    pub fn extents_in_direction(
+        &mut self,
+        text: &str,
+        family: &str,
+        size: f64,
+        direction: TextDirection,
+    ) -> Result<cairo::TextExtents> {
+        match direction {
+            TextDirection::Ltr => self.extents_for_text(text, family, size),
+            TextDirection::Vertical => self.extents_for_column(text, family, size),
+        }
+    }
+
+    /// Find the largest font size of at most `size` at which `fits` accepts the extents of `text`.
+    ///
+    /// The size is reduced in steps of 10% but never below `min_size`.
+    pub fn fit_font_size(
+        &mut self,
+        text: &str,
+        family: &str,
+        size: f64,
+        min_size: f64,
+        direction: TextDirection,
+        fits: impl Fn(&cairo::TextExtents) -> bool,
+    ) -> Result<f64> {
+        self.fit(size, min_size, fits, |measurer, size| {
+            measurer.extents_in_direction(text, family, size, direction)
+        })
+    }
+
+    /// Like `fit_font_size` but for text made of `runs` as measured by `extents_for_runs`.
+    pub fn fit_font_size_for_runs(
+        &mut self,
+        runs: &[(&str, &str)],
+        size: f64,
+        min_size: f64,
+        fits: impl Fn(&cairo::TextExtents) -> bool,
+    ) -> Result<f64> {
+        self.fit(size, min_size, fits, |measurer, size| {
+            measurer.extents_for_runs(runs, size)
+        })
+    }
+
+    fn fit(
+        &mut self,
+        size: f64,
+        min_size: f64,
+        fits: impl Fn(&cairo::TextExtents) -> bool,
+        extents: impl Fn(&mut Self, f64) -> Result<cairo::TextExtents>,
+    ) -> Result<f64> {
+        let mut size = size;
+        while size > min_size {
+            if fits(&extents(self, size)?) {
+                return Ok(size);
+            }
+            size = (size * 0.9).max(min_size);
+        }
+        Ok(min_size)
+    }
+
+    /// Shorten `text` with an ellipsis so that it's at most `max_width` wide.
+    pub fn elide(&mut self, text: &str, family: &str, size: f64, max_width: f64) -> Result<String> {
+        if self.extents_for_text(text, family, size)?.width() <= max_width {
+            return Ok(text.to_string());
+        }
+        let mut chars: Vec<char> = text.chars().collect();
+        while chars.pop().is_some() {
+            let shortened: String = chars.iter().collect();
+            let candidate = format!("{}…", shortened.trim_end());
+            if self.extents_for_text(&candidate, family, size)?.width() <= max_width {
+                return Ok(candidate);
+            }
+        }
+        Ok("…".to_string())
+    }
+}
+
+/// Grow the ink box `ink`, given as (left, top, right, bottom), by `extents` drawn at `pen`.
+fn add_ink(ink: &mut Option<(f64, f64, f64, f64)>, pen: f64, extents: &cairo::TextExtents) {
+    // Text without ink like spaces only moves the pen.
+    if extents.width() <= 0.0 || extents.height() <= 0.0 {
+        return;
+    }
+    let left = pen + extents.x_bearing();
+    let top = extents.y_bearing();
+    let (right, bottom) = (left + extents.width(), top + extents.height());
+    *ink = Some(match *ink {
+        Some((l, t, r, b)) => (l.min(left), t.min(top), r.max(right), b.max(bottom)),
+        None => (left, top, right, bottom),
+    });
+}
+
+/// Extents of text with the ink box `ink` that moves the pen by `advance`.
+fn extents_from_ink(ink: Option<(f64, f64, f64, f64)>, advance: f64) -> cairo::TextExtents {
+    let (left, top, right, bottom) = ink.unwrap_or_default();
+    cairo::TextExtents::new(left, top, right - left, bottom - top, advance, 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_measurer_reuses_context() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let small = measurer.extents_for_text("sa", "Mono", 12.0).unwrap();
+        let large = measurer.extents_for_text("sa", "Mono", 48.0).unwrap();
+        let small_again = measurer.extents_for_text("sa", "Mono", 12.0).unwrap();
+        assert!(large.width() > small.width());
+        assert_eq!(small.width(), small_again.width());
+    }
+
+    #[test]
+    fn test_text_measurer_font_style() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let normal = measurer.extents_for_text("sadf", "Sans", 40.0).unwrap();
+        measurer.set_font_style(cairo::FontSlant::Normal, cairo::FontWeight::Bold);
+        let bold = measurer.extents_for_text("sadf", "Sans", 40.0).unwrap();
+        assert!(bold.x_advance() >= normal.x_advance());
+        measurer.set_font_style(cairo::FontSlant::Normal, cairo::FontWeight::Normal);
+        let normal_again = measurer.extents_for_text("sadf", "Sans", 40.0).unwrap();
+        assert_eq!(normal.x_advance(), normal_again.x_advance());
+    }
+
+    #[test]
+    fn test_text_measurer_large_font() {
+        // The measuring surface is a single pixel, extents far beyond its size must still scale
+        // with the font size instead of being clipped.
+        let mut measurer = TextMeasurer::new().unwrap();
+        let normal = measurer.extents_for_text("WW", "Mono", 200.0).unwrap();
+        let huge = measurer.extents_for_text("WW", "Mono", 2000.0).unwrap();
+        assert!(huge.width() > 1024.0);
+        assert!(huge.height() > 1024.0);
+        assert!((huge.x_advance() / normal.x_advance() - 10.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn test_extents_for_runs() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let whole = measurer.extents_for_text("sa df", "Mono", 24.0).unwrap();
+        let runs = measurer
+            .extents_for_runs(&[("sa ", "Mono"), ("df", "Mono")], 24.0)
+            .unwrap();
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+        assert!(close(runs.x_bearing(), whole.x_bearing()));
+        assert!(close(runs.width(), whole.width()));
+        assert!(close(runs.height(), whole.height()));
+        assert!(close(runs.x_advance(), whole.x_advance()));
+    }
+
+    #[test]
+    fn test_has_glyphs() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        assert!(measurer.has_glyphs("sadf", "Mono", 24.0).unwrap());
+        // A private use character that fonts have no reason to cover.
+        assert!(!measurer.has_glyphs("sa\u{F0000}", "Mono", 24.0).unwrap());
+    }
+
+    #[test]
+    fn test_fast_metrics() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let mut fast = TextMeasurer::new().unwrap();
+        fast.set_fast_metrics(true);
+        for text in ["sadf", "Wg", "a b", " x ", "  "] {
+            for size in [12.0, 72.0] {
+                let expected = measurer.extents_for_text(text, "Sans", size).unwrap();
+                let actual = fast.extents_for_text(text, "Sans", size).unwrap();
+                let close = |a: f64, b: f64| (a - b).abs() < 1e-6;
+                assert!(close(actual.x_bearing(), expected.x_bearing()), "{text}");
+                assert!(close(actual.y_bearing(), expected.y_bearing()), "{text}");
+                assert!(close(actual.width(), expected.width()), "{text}");
+                assert!(close(actual.height(), expected.height()), "{text}");
+                assert!(close(actual.x_advance(), expected.x_advance()), "{text}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_text_measurer_with_context() {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 10, 10).unwrap();
+        let context = cairo::Context::new(&surface).unwrap();
+        let mut measurer = TextMeasurer::with_context(context);
+        let extents = measurer.extents_for_text("Wg", "Mono", 20.0).unwrap();
+        // The W rises above and the g descends below the baseline.
+        assert!(extents.y_bearing() < 0.0);
+        assert!(extents.height() > -extents.y_bearing());
+        assert!(extents.width() > 10.0);
+    }
+
+    #[test]
+    fn test_fit_font_size() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let fits = |e: &cairo::TextExtents| e.width() <= 30.0;
+        let size = measurer
+            .fit_font_size("sadf", "Mono", 72.0, 8.0, TextDirection::Ltr, fits)
+            .unwrap();
+        assert!(size < 72.0);
+        assert!(size >= 8.0);
+        let extents = measurer.extents_for_text("sadf", "Mono", size).unwrap();
+        assert!(size == 8.0 || extents.width() <= 30.0);
+
+        // Text that fits keeps its size.
+        let size = measurer
+            .fit_font_size("s", "Mono", 10.0, 8.0, TextDirection::Ltr, |_| true)
+            .unwrap();
+        assert_eq!(size, 10.0);
+
+        // Text that never fits ends up at the minimum.
+        let size = measurer
+            .fit_font_size("s", "Mono", 72.0, 8.0, TextDirection::Ltr, |_| false)
+            .unwrap();
+        assert_eq!(size, 8.0);
+    }
+
+    #[test]
+    fn test_extents_for_column() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let row = measurer.extents_for_text("sad", "Mono", 20.0).unwrap();
+        let one = measurer.extents_for_column("s", "Mono", 20.0).unwrap();
+        let three = measurer.extents_for_column("sad", "Mono", 20.0).unwrap();
+        assert_eq!(three.height(), 3.0 * one.height());
+        assert!(three.width() < row.width());
+        assert_eq!(three.x_bearing(), 0.0);
+        assert!(three.y_bearing() < 0.0);
+    }
+
+    #[test]
+    fn test_elide() {
+        let mut measurer = TextMeasurer::new().unwrap();
+        let short = measurer.elide("abc", "Mono", 12.0, 1000.0).unwrap();
+        assert_eq!(short, "abc");
+
+        let max_width = measurer
+            .extents_for_text("abcdef…", "Mono", 12.0)
+            .unwrap()
+            .width();
+        let elided = measurer
+            .elide("abcdefghijklmnopqrstuvwxyz", "Mono", 12.0, max_width)
+            .unwrap();
+        assert!(elided.ends_with('…'));
+        assert!(elided.len() < 26);
+        let elided_width = measurer
+            .extents_for_text(&elided, "Mono", 12.0)
+            .unwrap()
+            .width();
+        assert!(elided_width <= max_width);
+    }
+}
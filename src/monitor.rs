@@ -0,0 +1,285 @@
+use anyhow::{bail, Context, Result};
+use log::{debug, warn};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::ConnectionExt as _;
+use x11rb::protocol::xproto::{ConnectionExt as _, Screen, Window};
+
+use crate::args::MonitorSelection;
+use crate::geometry::Rect;
+use crate::DesktopWindow;
+
+/// DPI that a scale of 1.0 corresponds to.
+const BASELINE_DPI: f64 = 96.0;
+
+/// Points per inch.
+const POINTS_PER_INCH: f64 = 72.0;
+
+/// A monitor as reported by RandR.
+#[derive(Debug, Clone)]
+pub struct Monitor {
+    pub name: String,
+    pub rect: Rect,
+    /// Physical size in millimeters in the format (w, h).
+    pub size_mm: (u32, u32),
+    pub primary: bool,
+    /// Names of the RandR outputs showing this monitor, eg HDMI-1.
+    pub outputs: Vec<String>,
+}
+
+impl Monitor {
+    /// Scale factor of this monitor's DPI relative to a 96 DPI baseline.
+    ///
+    /// Monitors that don't report their physical size get a scale of 1.0.
+    pub fn dpi_scale(&self) -> f64 {
+        if self.size_mm.0 == 0 || self.size_mm.1 == 0 {
+            warn!(
+                "Monitor {} doesn't report a physical size, not scaling",
+                self.name
+            );
+            return 1.0;
+        }
+        let dpi = f64::from(self.rect.w) / (f64::from(self.size_mm.0) / 25.4);
+        dpi / BASELINE_DPI
+    }
+
+    /// Returns true if `name` is the name of this monitor or one of its outputs, ignoring case.
+    fn has_name(&self, name: &str) -> bool {
+        self.name.eq_ignore_ascii_case(name)
+            || self.outputs.iter().any(|o| o.eq_ignore_ascii_case(name))
+    }
+
+    /// Area of `window` in pixels that lies on this monitor.
+    fn overlap_area(&self, window: &DesktopWindow) -> i64 {
+        self.rect
+            .intersection(&window.rect())
+            .map_or(0, |overlap| overlap.area())
+    }
+}
+
+/// DPI of the whole X screen, 96 if it doesn't report its physical size.
+pub fn screen_dpi(screen: &Screen) -> f64 {
+    if screen.width_in_millimeters == 0 {
+        warn!("Screen doesn't report a physical size, assuming {BASELINE_DPI} DPI");
+        return BASELINE_DPI;
+    }
+    f64::from(screen.width_in_pixels) / (f64::from(screen.width_in_millimeters) / 25.4)
+}
+
+/// Convert a font size in `points` to pixels at `dpi`.
+pub fn points_to_pixels(points: f64, dpi: f64) -> f64 {
+    points * dpi / POINTS_PER_INCH
+}
+
+/// Query all active monitors using RandR.
+pub fn get_monitors(conn: &impl Connection, root: Window) -> Result<Vec<Monitor>> {
+    let reply = conn
+        .randr_get_monitors(root, true)?
+        .reply()
+        .context("Couldn't query RandR monitors")?;
+    let mut monitors = vec![];
+    for info in reply.monitors {
+        let name = conn
+            .get_atom_name(info.name)?
+            .reply()
+            .context("Couldn't get monitor name")?
+            .name;
+        let mut outputs = vec![];
+        for output in &info.outputs {
+            let output_info = conn
+                .randr_get_output_info(*output, x11rb::CURRENT_TIME)?
+                .reply()
+                .context("Couldn't get output info")?;
+            outputs.push(String::from_utf8_lossy(&output_info.name).into_owned());
+        }
+        let monitor = Monitor {
+            name: String::from_utf8_lossy(&name).into_owned(),
+            rect: Rect::new(
+                info.x.into(),
+                info.y.into(),
+                info.width.into(),
+                info.height.into(),
+            ),
+            size_mm: (info.width_in_millimeters, info.height_in_millimeters),
+            primary: info.primary,
+            outputs,
+        };
+        debug!("Found {:?}", monitor);
+        monitors.push(monitor);
+    }
+    Ok(monitors)
+}
+
+/// Find the monitor that `window` is on.
+///
+/// Windows spanning multiple monitors belong to the one holding the largest part of their area.
+pub fn monitor_for_window<'a>(
+    monitors: &'a [Monitor],
+    window: &DesktopWindow,
+) -> Option<&'a Monitor> {
+    monitors
+        .iter()
+        .map(|m| (m, m.overlap_area(window)))
+        .filter(|(_, area)| *area > 0)
+        // Prefer the first monitor in case of a tie.
+        .rev()
+        .max_by_key(|(_, area)| *area)
+        .map(|(m, _)| m)
+}
+
+/// Resolve `selection` to a single monitor, `None` meaning all monitors.
+///
+/// `pointer` is the position of the mouse pointer which is used to find the current monitor.
+pub fn select_monitor<'a>(
+    monitors: &'a [Monitor],
+    selection: &MonitorSelection,
+    pointer: (i32, i32),
+) -> Result<Option<&'a Monitor>> {
+    let monitor = match selection {
+        MonitorSelection::All => return Ok(None),
+        MonitorSelection::Current => monitors
+            .iter()
+            .find(|m| m.rect.contains_point(pointer.0, pointer.1)),
+        MonitorSelection::Primary => monitors.iter().find(|m| m.primary).or_else(|| {
+            warn!("No primary monitor set, using the first one");
+            monitors.first()
+        }),
+        MonitorSelection::Name(name) => monitors.iter().find(|m| m.has_name(name)),
+    };
+    match monitor {
+        Some(monitor) => Ok(Some(monitor)),
+        None => {
+            let mut names: Vec<&str> = vec![];
+            for m in monitors {
+                names.push(&m.name);
+                names.extend(
+                    m.outputs
+                        .iter()
+                        .map(String::as_str)
+                        .filter(|o| *o != m.name),
+                );
+            }
+            bail!(
+                "Couldn't find {:?} monitor, available monitors: {}",
+                selection,
+                names.join(", ")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(rect: (i32, i32, i32, i32), size_mm: (u32, u32)) -> Monitor {
+        Monitor {
+            name: "TEST-1".to_string(),
+            rect: Rect::new(rect.0, rect.1, rect.2, rect.3),
+            size_mm,
+            primary: false,
+            outputs: vec![],
+        }
+    }
+
+    fn named_monitor(name: &str, x: i32, primary: bool) -> Monitor {
+        Monitor {
+            name: name.to_string(),
+            rect: Rect::new(x, 0, 1920, 1080),
+            size_mm: (508, 286),
+            primary,
+            outputs: vec![name.to_string()],
+        }
+    }
+
+    fn window(pos: (i32, i32), size: (i32, i32)) -> DesktopWindow {
+        DesktopWindow {
+            id: 1,
+            x_window_id: None,
+            class: None,
+            instance: None,
+            title: None,
+            target: None,
+            pos,
+            size,
+            is_focused: false,
+        }
+    }
+
+    #[test]
+    fn test_dpi_scale() {
+        // 1920 pixels on 508mm (20 inches) is exactly 96 DPI.
+        assert_eq!(monitor((0, 0, 1920, 1080), (508, 286)).dpi_scale(), 1.0);
+        assert_eq!(monitor((0, 0, 3840, 2160), (508, 286)).dpi_scale(), 2.0);
+    }
+
+    #[test]
+    fn test_dpi_scale_no_physical_size() {
+        assert_eq!(monitor((0, 0, 3840, 2160), (0, 0)).dpi_scale(), 1.0);
+    }
+
+    #[test]
+    fn test_points_to_pixels() {
+        assert_eq!(points_to_pixels(12.0, 96.0), 16.0);
+        assert_eq!(points_to_pixels(12.0, 72.0), 12.0);
+        assert_eq!(points_to_pixels(10.5, 192.0), 28.0);
+    }
+
+    #[test]
+    fn test_monitor_for_window_majority_area() {
+        let monitors = [
+            named_monitor("DP-1", 0, false),
+            named_monitor("DP-2", 1920, false),
+        ];
+        let on_first = window((100, 100), (800, 600));
+        assert_eq!(
+            monitor_for_window(&monitors, &on_first).unwrap().name,
+            "DP-1"
+        );
+        // Mostly on the second monitor.
+        let spanning = window((1800, 100), (800, 600));
+        assert_eq!(
+            monitor_for_window(&monitors, &spanning).unwrap().name,
+            "DP-2"
+        );
+        let offscreen = window((-1000, -1000), (100, 100));
+        assert!(monitor_for_window(&monitors, &offscreen).is_none());
+    }
+
+    #[test]
+    fn test_select_monitor() {
+        let monitors = [
+            named_monitor("DP-1", 0, false),
+            named_monitor("HDMI-1", 1920, true),
+        ];
+        let select = |selection: MonitorSelection, pointer| {
+            select_monitor(&monitors, &selection, pointer)
+                .unwrap()
+                .map(|m| m.name.clone())
+        };
+        assert_eq!(select(MonitorSelection::All, (0, 0)), None);
+        assert_eq!(
+            select(MonitorSelection::Current, (2000, 10)).as_deref(),
+            Some("HDMI-1")
+        );
+        assert_eq!(
+            select(MonitorSelection::Primary, (0, 0)).as_deref(),
+            Some("HDMI-1")
+        );
+        assert_eq!(
+            select(MonitorSelection::Name("DP-1".to_string()), (0, 0)).as_deref(),
+            Some("DP-1")
+        );
+        // Output names work regardless of case.
+        assert_eq!(
+            select(MonitorSelection::Name("hdmi-1".to_string()), (0, 0)).as_deref(),
+            Some("HDMI-1")
+        );
+        let err = select_monitor(
+            &monitors,
+            &MonitorSelection::Name("VGA-1".to_string()),
+            (0, 0),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("DP-1, HDMI-1"));
+    }
+}
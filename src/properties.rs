@@ -0,0 +1,180 @@
+//! Read what the filters need to know about X11 windows.
+//!
+//! The requests for all windows are sent before waiting for any reply, which keeps looking at many
+//! windows fast on connections with a high latency such as X forwarded over SSH.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{Context, Result};
+use x11rb::connection::Connection;
+use x11rb::cookie::Cookie;
+use x11rb::protocol::xproto::{
+    Atom, AtomEnum, ConnectionExt, GetPropertyReply, GetWindowAttributesReply,
+};
+
+x11rb::atom_manager! {
+    /// The atoms wmfocus uses, interned together in a single round trip.
+    pub Atoms: AtomsCookie {
+        UTF8_STRING,
+        WM_PROTOCOLS,
+        WM_DELETE_WINDOW,
+        _NET_ACTIVE_WINDOW,
+        _NET_CLIENT_LIST,
+        _NET_CLIENT_LIST_STACKING,
+        _NET_CURRENT_DESKTOP,
+        _NET_FRAME_EXTENTS,
+        _NET_RESTACK_WINDOW,
+        _NET_SUPPORTED,
+        _NET_WM_DESKTOP,
+        _NET_WM_NAME,
+        _NET_WM_STATE,
+        _NET_WM_STATE_HIDDEN,
+        _NET_WM_WINDOW_OPACITY,
+        _NET_WM_WINDOW_TYPE,
+    }
+}
+
+impl Atoms {
+    /// Intern all atoms on `conn`.
+    pub fn intern(conn: &impl Connection) -> Result<Atoms> {
+        Atoms::new(conn)?.reply().context("Couldn't create atoms")
+    }
+}
+
+/// What the filters need to know about a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowProperties {
+    /// The `_NET_WM_WINDOW_TYPE`s without the `_NET_WM_WINDOW_TYPE_` prefix, eg `DOCK`.
+    pub types: Vec<String>,
+    /// Whether no window manager manages the window.
+    pub override_redirect: bool,
+    /// The `_NET_WM_WINDOW_OPACITY` between 0.0 and 1.0, `None` if it isn't set.
+    pub opacity: Option<f64>,
+    /// Whether the window is minimized, which EWMH marks by `_NET_WM_STATE_HIDDEN`.
+    pub minimized: bool,
+}
+
+/// The requests for the properties of a single window.
+struct PropertyCookies<'c, C: Connection> {
+    types: Cookie<'c, C, GetPropertyReply>,
+    attributes: Cookie<'c, C, GetWindowAttributesReply>,
+    opacity: Cookie<'c, C, GetPropertyReply>,
+    state: Cookie<'c, C, GetPropertyReply>,
+}
+
+/// The replies for a single window, window types are still atoms.
+struct PropertyReplies {
+    types: Vec<Atom>,
+    override_redirect: bool,
+    opacity: Option<u32>,
+    state: Vec<Atom>,
+}
+
+impl<'c, C: Connection> PropertyCookies<'c, C> {
+    fn send(conn: &'c C, atoms: &Atoms, window: u32) -> Result<Self> {
+        Ok(PropertyCookies {
+            types: conn.get_property(
+                false,
+                window,
+                atoms._NET_WM_WINDOW_TYPE,
+                AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?,
+            attributes: conn.get_window_attributes(window)?,
+            opacity: conn.get_property(
+                false,
+                window,
+                atoms._NET_WM_WINDOW_OPACITY,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )?,
+            state: conn.get_property(
+                false,
+                window,
+                atoms._NET_WM_STATE,
+                AtomEnum::ATOM,
+                0,
+                u32::MAX,
+            )?,
+        })
+    }
+
+    fn reply(self) -> Result<PropertyReplies> {
+        let atoms = |reply: GetPropertyReply| -> Vec<Atom> {
+            reply.value32().into_iter().flatten().collect()
+        };
+        Ok(PropertyReplies {
+            types: atoms(
+                self.types
+                    .reply()
+                    .context("Couldn't get _NET_WM_WINDOW_TYPE")?,
+            ),
+            override_redirect: self
+                .attributes
+                .reply()
+                .context("Couldn't get window attributes")?
+                .override_redirect,
+            opacity: self
+                .opacity
+                .reply()
+                .context("Couldn't get _NET_WM_WINDOW_OPACITY")?
+                .value32()
+                .and_then(|mut values| values.next()),
+            state: atoms(self.state.reply().context("Couldn't get _NET_WM_STATE")?),
+        })
+    }
+}
+
+/// Get the properties of all `windows`, an error for those that couldn't be read.
+///
+/// Window types are looked up by name once for all windows as most windows share them.
+pub fn get_window_properties<C: Connection>(
+    conn: &C,
+    atoms: &Atoms,
+    windows: &[u32],
+) -> Result<Vec<Result<WindowProperties>>> {
+    let mut cookies = vec![];
+    for &window in windows {
+        cookies.push(PropertyCookies::send(conn, atoms, window)?);
+    }
+    let replies: Vec<Result<PropertyReplies>> =
+        cookies.into_iter().map(PropertyCookies::reply).collect();
+
+    let types: HashSet<Atom> = replies
+        .iter()
+        .flatten()
+        .flat_map(|r| r.types.iter().copied())
+        .collect();
+    let mut name_cookies = vec![];
+    for atom in types {
+        name_cookies.push((atom, conn.get_atom_name(atom)?));
+    }
+    let mut names = HashMap::new();
+    for (atom, cookie) in name_cookies {
+        let name = cookie.reply().context("Couldn't get atom name")?.name;
+        let name = String::from_utf8_lossy(&name);
+        let name = name.strip_prefix("_NET_WM_WINDOW_TYPE_").unwrap_or(&name);
+        names.insert(atom, name.to_string());
+    }
+
+    Ok(replies
+        .into_iter()
+        .map(|replies| {
+            let replies = replies?;
+            Ok(WindowProperties {
+                types: replies
+                    .types
+                    .iter()
+                    .map(|atom| names[atom].clone())
+                    .collect(),
+                override_redirect: replies.override_redirect,
+                opacity: replies
+                    .opacity
+                    .map(|opacity| f64::from(opacity) / f64::from(u32::MAX)),
+                minimized: replies.state.contains(&atoms._NET_WM_STATE_HIDDEN),
+            })
+        })
+        .collect())
+}
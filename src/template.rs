@@ -0,0 +1,244 @@
+//! Templates for printing the selected window, eg `{id}\t{class}\t{title}`.
+
+use std::fmt::{self, Write};
+
+use anyhow::{bail, Result};
+
+use crate::DesktopWindow;
+
+/// A value of the selected window that can be put into a template.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Placeholder {
+    Id,
+    Target,
+    Class,
+    Instance,
+    Title,
+    X,
+    Y,
+    Width,
+    Height,
+    Hint,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 10] = [
+        ("id", Placeholder::Id),
+        ("target", Placeholder::Target),
+        ("class", Placeholder::Class),
+        ("instance", Placeholder::Instance),
+        ("title", Placeholder::Title),
+        ("x", Placeholder::X),
+        ("y", Placeholder::Y),
+        ("width", Placeholder::Width),
+        ("height", Placeholder::Height),
+        ("hint", Placeholder::Hint),
+    ];
+
+    fn from_name(name: &str) -> Option<Placeholder> {
+        Placeholder::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, p)| *p)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(String),
+    Placeholder(Placeholder),
+}
+
+/// A parsed output template.
+///
+/// Placeholders are written as `{name}`, literal braces as `{{` and `}}`. The escapes `\t`, `\n`
+/// and `\\` are supported so that templates can be passed from a shell without quoting tricks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template> {
+        let mut segments = vec![];
+        let mut literal = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => bail!("Unterminated placeholder '{{{}' in template", name),
+                        }
+                    }
+                    let Some(placeholder) = Placeholder::from_name(&name) else {
+                        let known: Vec<String> = Placeholder::ALL
+                            .iter()
+                            .map(|(n, _)| format!("{{{n}}}"))
+                            .collect();
+                        bail!(
+                            "Unknown placeholder '{{{}}}', known placeholders are {}",
+                            name,
+                            known.join(", ")
+                        );
+                    };
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(Segment::Placeholder(placeholder));
+                }
+                '}' => bail!("Unmatched '}}' in template, use '}}}}' for a literal brace"),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+        Ok(Template { segments })
+    }
+
+    /// Fill in the template for `window` which got `hint`.
+    pub fn render(&self, window: &DesktopWindow, hint: &str) -> String {
+        let mut output = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Placeholder(placeholder) => {
+                    // Writing to a String can't fail.
+                    let _ = match placeholder {
+                        Placeholder::Id => {
+                            write!(output, "0x{:x}", window.x_window_id.unwrap_or(0))
+                        }
+                        Placeholder::Target => {
+                            write!(output, "{}", window.target.as_deref().unwrap_or_default())
+                        }
+                        Placeholder::Class => {
+                            write!(output, "{}", window.class.as_deref().unwrap_or_default())
+                        }
+                        Placeholder::Instance => {
+                            write!(output, "{}", window.instance.as_deref().unwrap_or_default())
+                        }
+                        Placeholder::Title => {
+                            write!(output, "{}", window.title.as_deref().unwrap_or_default())
+                        }
+                        Placeholder::X => write!(output, "{}", window.pos.0),
+                        Placeholder::Y => write!(output, "{}", window.pos.1),
+                        Placeholder::Width => write!(output, "{}", window.size.0),
+                        Placeholder::Height => write!(output, "{}", window.size.1),
+                        Placeholder::Hint => write!(output, "{}", hint),
+                    };
+                }
+            }
+        }
+        output
+    }
+}
+
+impl fmt::Display for Template {
+    /// Write the template back in the form `parse` reads.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(literal) => {
+                    for c in literal.chars() {
+                        match c {
+                            '{' => f.write_str("{{")?,
+                            '}' => f.write_str("}}")?,
+                            '\t' => f.write_str("\\t")?,
+                            '\n' => f.write_str("\\n")?,
+                            '\\' => f.write_str("\\\\")?,
+                            c => f.write_char(c)?,
+                        }
+                    }
+                }
+                Segment::Placeholder(placeholder) => {
+                    let (name, _) = Placeholder::ALL
+                        .iter()
+                        .find(|(_, p)| p == placeholder)
+                        .expect("All placeholders have a name");
+                    write!(f, "{{{name}}}")?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window() -> DesktopWindow {
+        DesktopWindow {
+            id: 1,
+            x_window_id: Some(0x1200003),
+            class: Some("Alacritty".to_string()),
+            instance: None,
+            title: Some("vim".to_string()),
+            target: None,
+            pos: (10, 20),
+            size: (800, 600),
+            is_focused: false,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let template = Template::parse(r"{id}\t{class}\t{instance}\t{title}").unwrap();
+        assert_eq!(
+            template.render(&window(), "sa"),
+            "0x1200003\tAlacritty\t\tvim"
+        );
+        let template = Template::parse("{hint}: {width}x{height}+{x}+{y}").unwrap();
+        assert_eq!(template.render(&window(), "sa"), "sa: 800x600+10+20");
+        let template = Template::parse("{id} {target}").unwrap();
+        let pane = DesktopWindow {
+            target: Some("left".to_string()),
+            ..window()
+        };
+        assert_eq!(template.render(&window(), "sa"), "0x1200003 ");
+        assert_eq!(template.render(&pane, "sa"), "0x1200003 left");
+    }
+
+    #[test]
+    fn test_escaped_braces() {
+        let template = Template::parse("{{{class}}}").unwrap();
+        assert_eq!(template.render(&window(), "sa"), "{Alacritty}");
+    }
+
+    #[test]
+    fn test_display() {
+        for source in [r"{id}\t{class}\n", "{{{title}}} \\\\ {hint}", ""] {
+            assert_eq!(Template::parse(source).unwrap().to_string(), source);
+        }
+    }
+
+    #[test]
+    fn test_invalid_templates() {
+        let err = Template::parse("{id} {name}").unwrap_err();
+        assert!(err.to_string().contains("Unknown placeholder '{name}'"));
+        assert!(Template::parse("{id").is_err());
+        assert!(Template::parse("id}").is_err());
+    }
+}
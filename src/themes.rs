@@ -0,0 +1,132 @@
+//! Bundled color palettes selected with `--theme`.
+
+use anyhow::{bail, Result};
+use clap::ArgMatches;
+
+use crate::args::{self, AppConfig};
+
+type Color = (f64, f64, f64, f64);
+
+const fn rgba(r: u8, g: u8, b: u8, a: f64) -> Color {
+    (r as f64 / 255.0, g as f64 / 255.0, b as f64 / 255.0, a)
+}
+
+const fn rgb(r: u8, g: u8, b: u8) -> Color {
+    rgba(r, g, b, 1.0)
+}
+
+/// Colors of the hints, the fields mirror the color options of `AppConfig`.
+#[derive(Debug, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub text_color: Color,
+    pub text_color_alt: Color,
+    pub bg_color: Color,
+    pub text_color_current: Color,
+    pub text_color_current_alt: Color,
+    pub bg_color_current: Color,
+    pub border_color: Color,
+}
+
+pub const THEMES: [Theme; 3] = [
+    Theme {
+        name: "nord",
+        text_color: rgb(0xec, 0xef, 0xf4),
+        text_color_alt: rgb(0x61, 0x6e, 0x88),
+        bg_color: rgba(0x2e, 0x34, 0x40, 0.9),
+        text_color_current: rgb(0x2e, 0x34, 0x40),
+        text_color_current_alt: rgb(0x4c, 0x56, 0x6a),
+        bg_color_current: rgba(0x88, 0xc0, 0xd0, 0.9),
+        border_color: rgb(0x3b, 0x42, 0x52),
+    },
+    Theme {
+        name: "solarized-dark",
+        text_color: rgb(0x93, 0xa1, 0xa1),
+        text_color_alt: rgb(0x58, 0x6e, 0x75),
+        bg_color: rgba(0x00, 0x2b, 0x36, 0.9),
+        text_color_current: rgb(0xfd, 0xf6, 0xe3),
+        text_color_current_alt: rgb(0x93, 0xa1, 0xa1),
+        bg_color_current: rgba(0x26, 0x8b, 0xd2, 0.9),
+        border_color: rgb(0x07, 0x36, 0x42),
+    },
+    Theme {
+        name: "gruvbox",
+        text_color: rgb(0xeb, 0xdb, 0xb2),
+        text_color_alt: rgb(0x92, 0x83, 0x74),
+        bg_color: rgba(0x28, 0x28, 0x28, 0.9),
+        text_color_current: rgb(0x28, 0x28, 0x28),
+        text_color_current_alt: rgb(0x7c, 0x6f, 0x64),
+        bg_color_current: rgba(0xfa, 0xbd, 0x2f, 0.9),
+        border_color: rgb(0x3c, 0x38, 0x36),
+    },
+];
+
+impl Theme {
+    /// Find the bundled theme called `name`, ignoring case.
+    pub fn find(name: &str) -> Result<&'static Theme> {
+        match THEMES.iter().find(|t| t.name.eq_ignore_ascii_case(name)) {
+            Some(theme) => Ok(theme),
+            None => {
+                let names: Vec<&str> = THEMES.iter().map(|t| t.name).collect();
+                bail!(
+                    "Unknown theme '{}', available themes are {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        }
+    }
+
+    /// Apply the colors of this theme onto `config` for all colors that weren't explicitly set on
+    /// the command line or in the environment.
+    ///
+    /// This has to happen before the config file is applied, whose colors win over the theme.
+    pub fn apply(&self, config: &mut AppConfig, matches: &ArgMatches) {
+        macro_rules! apply {
+            ($field:ident) => {
+                if !args::is_explicit(matches, stringify!($field)) {
+                    config.$field = self.$field;
+                }
+            };
+        }
+
+        apply!(text_color);
+        apply!(text_color_alt);
+        apply!(bg_color);
+        apply!(text_color_current);
+        apply!(text_color_current_alt);
+        apply!(bg_color_current);
+        apply!(border_color);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_theme() {
+        assert_eq!(Theme::find("Nord").unwrap().name, "nord");
+        assert_eq!(
+            args::format_color(Theme::find("gruvbox").unwrap().bg_color),
+            "rgba(40, 40, 40, 0.9)"
+        );
+        let err = Theme::find("dracula").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown theme 'dracula', available themes are nord, solarized-dark, gruvbox"
+        );
+    }
+
+    #[test]
+    fn test_apply_theme() {
+        let args = ["wmfocus", "--bgcolor", "red"];
+        let mut config = args::test_config(&args);
+        let matches = args::command_without_fonts().get_matches_from(args);
+        let theme = Theme::find("nord").unwrap();
+        theme.apply(&mut config, &matches);
+        assert_eq!(config.bg_color, (1.0, 0.0, 0.0, 1.0));
+        assert_eq!(config.text_color, theme.text_color);
+        assert_eq!(config.border_color, theme.border_color);
+    }
+}
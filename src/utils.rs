@@ -1,49 +1,140 @@
-use std::iter;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 use anyhow::{bail, Context, Result};
-use itertools::Itertools;
-use log::debug;
-use regex::Regex;
+use log::{debug, warn};
 use x11rb::connection::Connection;
+use x11rb::properties::WmClass;
+use x11rb::protocol::shape::{self, ConnectionExt as _};
+use x11rb::protocol::xinput::{self, ConnectionExt as _};
 use x11rb::protocol::xproto::{
-    grab_keyboard, grab_pointer, ConnectionExt, EventMask, GrabMode, GrabStatus, Screen, Visualtype,
+    grab_keyboard, grab_pointer, Atom, AtomEnum, ClientMessageEvent, ClipOrdering, ColormapAlloc,
+    ConfigureWindowAux, ConnectionExt, EventMask, GetInputFocusReply, GrabMode, GrabStatus,
+    InputFocus, MapState, PropMode, Rectangle, Screen, StackMode, VisualClass, Visualtype,
 };
 use x11rb::protocol::Event;
+use x11rb::wrapper::ConnectionExt as _;
+
+use crate::args::{Antialias, AppConfig, InputMethod, Offset, TextDirection};
+use crate::error;
+use crate::geometry::Rect;
+use crate::properties::Atoms;
+use crate::{DesktopWindow, HintBox, RenderWindow};
+
+/// The first `count` hints that all have the same length, made of `hint_chars`.
+///
+/// Hints are generated by counting upwards in a base of the number of characters in `hint_chars`
+/// where each digit is one of them. They are as short as possible while still fitting `count`
+/// hints and unique by construction.
+pub fn get_uniform_hints(hint_chars: &str, count: usize) -> Result<Vec<String>> {
+    let chars: Vec<char> = hint_chars.chars().collect();
+    if chars.len() < 2 {
+        bail!("Need at least two hint_chars");
+    }
+    let base = chars.len();
 
-use crate::args::AppConfig;
-use crate::{DesktopWindow, RenderWindow};
-
-/// Given a list of `current_hints` and a bunch of `hint_chars`, this finds a unique combination
-/// of characters that doesn't yet exist in `current_hints`. `max_count` is the maximum possible
-/// number of hints we need.
-pub fn get_next_hint(
-    current_hints: Vec<&String>,
-    hint_chars: &str,
-    max_count: usize,
-) -> Result<String> {
     // Figure out which size we need.
     let mut size_required = 1;
-    while hint_chars.len().pow(size_required) < max_count {
+    while base.pow(size_required) < count {
         size_required += 1;
     }
-    let mut ret = hint_chars
+
+    let mut digits = vec![0; size_required as usize];
+    let mut hints = Vec::with_capacity(count);
+    for _ in 0..count {
+        hints.push(digits.iter().map(|&d| chars[d]).collect());
+
+        // Increment the counter, starting from the least significant (rightmost) digit.
+        for digit in digits.iter_mut().rev() {
+            *digit += 1;
+            if *digit < base {
+                break;
+            }
+            *digit = 0;
+        }
+    }
+    debug!("Generated hints: {:?}", hints);
+    Ok(hints)
+}
+
+/// Number of windows that can get a hint of at most `max_length` characters of `hint_chars`.
+pub fn max_hint_count(hint_chars: &str, max_length: u32) -> usize {
+    hint_chars
         .chars()
-        .next()
-        .context("No hint_chars found")?
-        .to_string();
-    let it = iter::repeat(hint_chars.chars().rev())
-        .take(size_required as usize)
-        .multi_cartesian_product();
-    for c in it {
-        let folded = c.into_iter().collect();
-        if !current_hints.contains(&&folded) {
-            ret = folded;
+        .count()
+        .checked_pow(max_length)
+        .unwrap_or(usize::MAX)
+}
+
+/// Generate `count` numeric hints counting from 1.
+///
+/// They are padded with zeros to the same width so that none is a prefix of another.
+pub fn get_numeric_hints(count: usize) -> Vec<String> {
+    let width = count.to_string().len();
+    (1..=count).map(|i| format!("{i:0width$}")).collect()
+}
+
+/// Generate `count` prefix-free hints that are as short as possible.
+///
+/// We start out with every character being a hint of its own. As long as we don't have enough
+/// hints, the last of the shortest hints is turned into a prefix for one hint per character. This
+/// leaves the first characters of `hint_chars` as short as possible. The hints are returned
+/// shortest first.
+pub fn get_shortest_hints(hint_chars: &str, count: usize) -> Result<Vec<String>> {
+    let chars: Vec<char> = hint_chars.chars().collect();
+    if chars.len() < 2 {
+        bail!("Need at least two hint_chars");
+    }
+    let mut hints: Vec<String> = chars.iter().map(|c| c.to_string()).collect();
+    while hints.len() < count {
+        let shortest = hints.iter().map(|h| h.chars().count()).min().unwrap_or(0);
+        let index = hints
+            .iter()
+            .rposition(|h| h.chars().count() == shortest)
+            .unwrap_or(0);
+        let prefix = hints.remove(index);
+        hints.extend(chars.iter().map(|c| format!("{prefix}{c}")));
+    }
+    hints.truncate(count);
+    hints.sort_by_key(|h| h.chars().count());
+    debug!("Generated hints: {:?}", hints);
+    Ok(hints)
+}
+
+/// Remove all characters from `hint_chars` that only differ in case from an earlier one.
+///
+/// This is required for case-insensitive matching as otherwise `A` and `a` could be handed out as
+/// distinct hints.
+pub fn fold_hint_chars(hint_chars: &str) -> String {
+    let mut folded = String::new();
+    for c in hint_chars.chars() {
+        if !folded
+            .chars()
+            .any(|f| f.to_lowercase().eq(c.to_lowercase()))
+        {
+            folded.push(c);
         }
     }
-    debug!("Returning next hint: {}", ret);
-    Ok(ret)
+    folded
+}
+
+/// Return the character of `hint_chars` that `kstr` case-insensitively matches or `kstr` itself
+/// if there is none.
+pub fn match_hint_char_case(kstr: &str, hint_chars: &str) -> String {
+    hint_chars
+        .chars()
+        .map(|c| c.to_string())
+        .find(|c| c.to_lowercase() == kstr.to_lowercase())
+        .unwrap_or_else(|| kstr.to_string())
+}
+
+/// Spell `hint` with the characters of `hint_chars` that its characters case-insensitively match.
+pub fn fold_hint(hint: &str, hint_chars: &str) -> String {
+    hint.chars()
+        .map(|c| match_hint_char_case(&c.to_string(), hint_chars))
+        .collect()
 }
 
 /// A rust version of XCB's `xcb_visualtype_t` struct. This is used in a FFI-way.
@@ -75,30 +166,240 @@ impl From<Visualtype> for xcb_visualtype_t {
     }
 }
 
-/// Find a `xcb_visualtype_t` based on its ID number
-pub fn find_xcb_visualtype(conn: &impl Connection, visual_id: u32) -> Option<xcb_visualtype_t> {
-    for root in &conn.setup().roots {
-        for depth in &root.allowed_depths {
-            for visual in &depth.visuals {
-                if visual.visual_id == visual_id {
-                    return Some((*visual).into());
-                }
+/// Find a 32-bit TrueColor visual of `screen`.
+///
+/// Windows of such a visual have an alpha channel so the parts of hints that aren't drawn over
+/// stay transparent.
+pub fn find_argb_visual(screen: &Screen) -> Option<Visualtype> {
+    screen
+        .allowed_depths
+        .iter()
+        .filter(|depth| depth.depth == 32)
+        .flat_map(|depth| &depth.visuals)
+        .find(|visual| visual.class == VisualClass::TRUE_COLOR)
+        .copied()
+}
+
+/// The visual that hint windows are created with along with its depth and colormap.
+#[derive(Debug, Clone, Copy)]
+pub struct HintVisual {
+    pub visual: Visualtype,
+    pub depth: u8,
+    /// Only visuals other than the root visual need a colormap of their own.
+    pub colormap: Option<u32>,
+}
+
+impl HintVisual {
+    /// Use a 32-bit visual if `screen` has one and fall back to the root visual otherwise.
+    pub fn new(conn: &impl Connection, screen: &Screen) -> Result<HintVisual> {
+        if let Some(visual) = find_argb_visual(screen) {
+            let colormap = conn.generate_id()?;
+            conn.create_colormap(ColormapAlloc::NONE, colormap, screen.root, visual.visual_id)?;
+            debug!("Using 32-bit visual 0x{:x}", visual.visual_id);
+            return Ok(HintVisual {
+                visual,
+                depth: 32,
+                colormap: Some(colormap),
+            });
+        }
+        warn!(
+            "The screen has no 32-bit visual, hints might get black instead of transparent parts"
+        );
+        let visual = screen
+            .allowed_depths
+            .iter()
+            .flat_map(|depth| &depth.visuals)
+            .find(|visual| visual.visual_id == screen.root_visual)
+            .copied()
+            .context("Couldn't find root visual")?;
+        Ok(HintVisual {
+            visual,
+            depth: screen.root_depth,
+            colormap: None,
+        })
+    }
+
+    /// Free the colormap once no window uses it anymore.
+    pub fn free(&self, conn: &impl Connection) -> Result<()> {
+        if let Some(colormap) = self.colormap {
+            conn.free_colormap(colormap)?;
+        }
+        Ok(())
+    }
+}
+
+/// Clamp `radius` to half of the smaller side of a box of `width` and `height`.
+pub fn clamp_corner_radius(radius: u32, width: i32, height: i32) -> f64 {
+    f64::from(radius).min(f64::from(width.min(height)) / 2.0)
+}
+
+/// Add a path of a rectangle with rounded corners of `radius` from (0, 0) to (`width`, `height`).
+fn rounded_rectangle(cr: &cairo::Context, width: f64, height: f64, radius: f64) {
+    use std::f64::consts::PI;
+    cr.new_path();
+    cr.arc(width - radius, radius, radius, -PI / 2.0, 0.0);
+    cr.arc(width - radius, height - radius, radius, 0.0, PI / 2.0);
+    cr.arc(radius, height - radius, radius, PI / 2.0, PI);
+    cr.arc(radius, radius, radius, PI, 3.0 * PI / 2.0);
+    cr.close_path();
+}
+
+/// Approximate a rectangle of `width` and `height` with rounded corners of `radius` by a list of
+/// horizontal rectangles.
+///
+/// This is used to shape the window so that the corners are actually see-through even without a
+/// compositor.
+pub fn rounded_rect_region(width: u16, height: u16, radius: f64) -> Vec<Rectangle> {
+    let mut rects: Vec<Rectangle> = vec![];
+    for y in 0..height {
+        let row = f64::from(y) + 0.5;
+        let dy = if row < radius {
+            radius - row
+        } else if row > f64::from(height) - radius {
+            row - (f64::from(height) - radius)
+        } else {
+            0.0
+        };
+        let inset = if dy > 0.0 {
+            (radius - (radius * radius - dy * dy).sqrt()).round() as u16
+        } else {
+            0
+        };
+        let inset = inset.min(width / 2);
+
+        // Merge with the previous row if it has the same inset.
+        if let Some(last) = rects.last_mut() {
+            if last.x as u16 == inset {
+                last.height += 1;
+                continue;
             }
         }
+        rects.push(Rectangle {
+            x: inset as i16,
+            y: y as i16,
+            width: width - 2 * inset,
+            height: 1,
+        });
+    }
+    rects
+}
+
+/// Stroke a border of `app_config.border_width` along the inside of the box.
+fn draw_border(rw: &RenderWindow, app_config: &AppConfig, radius: f64) -> Result<()> {
+    let (width, height) = (f64::from(rw.box_size.0), f64::from(rw.box_size.1));
+    // Cairo strokes centered on the path so inset it by half the line width to keep the whole
+    // border inside of the box.
+    let line_width = f64::from(app_config.border_width).min(width.min(height) / 2.0);
+    let inset = line_width / 2.0;
+    rw.cairo_context.save().context("Error trying to draw")?;
+    rw.cairo_context.translate(inset, inset);
+    if radius > 0.0 {
+        rounded_rectangle(
+            &rw.cairo_context,
+            width - line_width,
+            height - line_width,
+            (radius - inset).max(0.0),
+        );
+    } else {
+        rw.cairo_context.new_path();
+        rw.cairo_context
+            .rectangle(0.0, 0.0, width - line_width, height - line_width);
+    }
+    rw.cairo_context.restore().context("Error trying to draw")?;
+    rw.cairo_context.set_source_rgba(
+        app_config.border_color.0,
+        app_config.border_color.1,
+        app_config.border_color.2,
+        app_config.border_color.3,
+    );
+    rw.cairo_context.set_line_width(line_width);
+    rw.cairo_context.stroke().context("Error trying to draw")?;
+    Ok(())
+}
+
+/// Font size for the hint of a window of `size`, `factor` times the square root of its area but
+/// at least `min` and at most `max`.
+pub fn font_size_for_window(factor: f64, size: (i32, i32), min: f64, max: f64) -> f64 {
+    let area = f64::from(size.0.max(0)) * f64::from(size.1.max(0));
+    (factor * area.sqrt()).clamp(min, max)
+}
+
+/// Antialias everything drawn with `context` using `antialias`, including text.
+pub fn set_antialias(context: &cairo::Context, antialias: Antialias) -> Result<()> {
+    let mut font_options = cairo::FontOptions::new().context("Couldn't create font options")?;
+    font_options.set_antialias(antialias.into());
+    context.set_font_options(&font_options);
+    context.set_antialias(antialias.into());
+    Ok(())
+}
+
+/// Weight of the font to draw the hint of a window in.
+pub fn font_weight(app_config: &AppConfig, is_focused: bool) -> cairo::FontWeight {
+    match app_config.font_weight_current {
+        Some(weight) if is_focused => weight.into(),
+        _ => app_config.font_weight.into(),
     }
-    None
 }
 
-pub fn extents_for_text(text: &str, family: &str, size: f64) -> Result<cairo::TextExtents> {
-    // Create a buffer image that should be large enough.
-    // TODO: Figure out the maximum size from the largest window on the desktop.
-    // For now we'll use made-up maximum values.
-    let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1024, 1024)
-        .context("Couldn't create ImageSurface")?;
-    let cr = cairo::Context::new(&surface).context("Couldn't create Cairo Surface")?;
-    cr.select_font_face(family, cairo::FontSlant::Normal, cairo::FontWeight::Normal);
-    cr.set_font_size(size);
-    cr.text_extents(text).context("Couldn't create TextExtents")
+/// Returns true if boxes should get a shadow.
+pub fn has_shadow(app_config: &AppConfig) -> bool {
+    app_config.shadow_blur > 0 || app_config.shadow_offset.x != 0 || app_config.shadow_offset.y != 0
+}
+
+/// Space needed around a box for a shadow moved by `offset` and blurred by `blur` pixels, in the
+/// format (left, top, right, bottom).
+pub fn shadow_padding(offset: &Offset, blur: u32) -> (i32, i32, i32, i32) {
+    let blur = blur as i32;
+    (
+        (blur - offset.x).max(0),
+        (blur - offset.y).max(0),
+        (blur + offset.x).max(0),
+        (blur + offset.y).max(0),
+    )
+}
+
+/// Paint the shadow of the box of `rw`.
+///
+/// The blur is approximated by stacking rectangles that shrink towards the shadow. With a 32-bit
+/// ARGB visual the blur fades out into real transparency. On the root visual fallback the windows
+/// don't have an alpha channel, so it fades out towards black instead.
+fn draw_shadow(rw: &RenderWindow, app_config: &AppConfig) -> Result<()> {
+    let (width, height) = (f64::from(rw.box_size.0), f64::from(rw.box_size.1));
+    let x = rw.box_pos.0 + f64::from(app_config.shadow_offset.x);
+    let y = rw.box_pos.1 + f64::from(app_config.shadow_offset.y);
+    let steps = app_config.shadow_blur + 1;
+    rw.cairo_context.set_source_rgba(
+        app_config.shadow_color.0,
+        app_config.shadow_color.1,
+        app_config.shadow_color.2,
+        app_config.shadow_color.3 / f64::from(steps),
+    );
+    for step in 0..steps {
+        let grow = f64::from(app_config.shadow_blur - step);
+        rw.cairo_context
+            .rectangle(x - grow, y - grow, width + 2.0 * grow, height + 2.0 * grow);
+        rw.cairo_context.fill().context("Error trying to draw")?;
+    }
+    Ok(())
+}
+
+/// Width of the lines drawn by `draw_connectors` in pixels.
+pub const CONNECTOR_WIDTH: i32 = 2;
+
+/// Draw the connectors of all `render_windows` in `color` onto the overlay `context`.
+pub fn draw_connectors(
+    context: &cairo::Context,
+    render_windows: &HashMap<String, RenderWindow>,
+    color: (f64, f64, f64, f64),
+) -> Result<()> {
+    context.set_source_rgba(color.0, color.1, color.2, color.3);
+    context.set_line_width(f64::from(CONNECTOR_WIDTH));
+    for (from, to) in render_windows.values().filter_map(|rw| rw.connector) {
+        context.move_to(from.0.into(), from.1.into());
+        context.line_to(to.0.into(), to.1.into());
+    }
+    context.stroke().context("Error trying to draw")?;
+    Ok(())
 }
 
 /// Draw a `text` onto `rw`. In case any `current_hints` are already typed, it will draw those in a
@@ -109,124 +410,593 @@ pub fn draw_hint_text(
     text: &str,
     current_hints: &str,
 ) -> Result<()> {
+    if has_shadow(app_config) {
+        rw.cairo_context.set_operator(cairo::Operator::Source);
+        rw.cairo_context.set_source_rgba(0.0, 0.0, 0.0, 0.0);
+        rw.cairo_context.paint().context("Error trying to draw")?;
+        rw.cairo_context.set_operator(cairo::Operator::Over);
+        draw_shadow(rw, app_config)?;
+    }
+
+    // Everything else is relative to the box.
+    rw.cairo_context.save().context("Error trying to draw")?;
+    rw.cairo_context.translate(rw.box_pos.0, rw.box_pos.1);
+
     // Paint background.
     rw.cairo_context.set_operator(cairo::Operator::Source);
 
-    if rw.desktop_window.is_focused {
-        rw.cairo_context.set_source_rgb(
-            app_config.bg_color_current.0,
-            app_config.bg_color_current.1,
-            app_config.bg_color_current.2,
-        );
+    let bg_color = if rw.desktop_window.is_focused {
+        app_config.bg_color_current
     } else {
-        rw.cairo_context.set_source_rgb(
-            app_config.bg_color.0,
-            app_config.bg_color.1,
-            app_config.bg_color.2,
-        );
+        app_config
+            .class_palette
+            .color_for(rw.desktop_window)
+            .unwrap_or(app_config.bg_color)
+    };
+    rw.cairo_context
+        .set_source_rgb(bg_color.0, bg_color.1, bg_color.2);
+    let radius = clamp_corner_radius(app_config.corner_radius, rw.box_size.0, rw.box_size.1);
+    let (box_width, box_height) = (f64::from(rw.box_size.0), f64::from(rw.box_size.1));
+    if radius > 0.0 {
+        rounded_rectangle(&rw.cairo_context, box_width, box_height, radius);
+    } else {
+        rw.cairo_context.new_path();
+        rw.cairo_context.rectangle(0.0, 0.0, box_width, box_height);
     }
-    rw.cairo_context.paint().context("Error trying to draw")?;
+    rw.cairo_context.fill().context("Error trying to draw")?;
     rw.cairo_context.set_operator(cairo::Operator::Over);
 
+    if app_config.border_width > 0 {
+        draw_border(rw, app_config, radius)?;
+    }
+
     rw.cairo_context.select_font_face(
         &app_config.font.font_family,
-        cairo::FontSlant::Normal,
-        cairo::FontWeight::Normal,
+        app_config.font_slant.into(),
+        font_weight(app_config, rw.desktop_window.is_focused),
     );
-    rw.cairo_context.set_font_size(app_config.font.font_size);
+    rw.cairo_context.set_font_size(rw.font_size);
     rw.cairo_context.move_to(rw.draw_pos.0, rw.draw_pos.1);
-    if text.starts_with(current_hints) {
-        // Paint already selected chars.
-        if rw.desktop_window.is_focused {
-            rw.cairo_context.set_source_rgba(
-                app_config.text_color_current_alt.0,
-                app_config.text_color_current_alt.1,
-                app_config.text_color_current_alt.2,
-                app_config.text_color_current_alt.3,
-            );
+
+    // In vertical mode every character gets its own row and is centered in the column.
+    let font_extents = rw
+        .cairo_context
+        .font_extents()
+        .context("Couldn't create FontExtents")?;
+    let row_height = font_extents.ascent() + font_extents.descent();
+    let column_width = f64::from(rw.box_size.0) - 2.0 * rw.draw_pos.0;
+    // Hints are only uppercased for display, matching is still done against `text`.
+    let show_char = |c: char, row: usize| -> Result<()> {
+        let c = if app_config.uppercase_labels {
+            c.to_uppercase().to_string()
         } else {
-            rw.cairo_context.set_source_rgba(
-                app_config.text_color_alt.0,
-                app_config.text_color_alt.1,
-                app_config.text_color_alt.2,
-                app_config.text_color_alt.3,
+            c.to_string()
+        };
+        if app_config.text_direction == TextDirection::Vertical {
+            let extents = rw
+                .cairo_context
+                .text_extents(&c)
+                .context("Couldn't create TextExtents")?;
+            rw.cairo_context.move_to(
+                rw.draw_pos.0 + (column_width - extents.x_advance()) / 2.0,
+                rw.draw_pos.1 + row_height * row as f64,
             );
         }
+        rw.cairo_context
+            .show_text(&c)
+            .context("Couldn't show text")?;
+        Ok(())
+    };
+    let (text_color, text_color_alt) = if rw.desktop_window.is_focused {
+        (
+            app_config.text_color_current,
+            app_config.text_color_current_alt,
+        )
+    } else {
+        (app_config.text_color, app_config.text_color_alt)
+    };
+    let set_color = |color: (f64, f64, f64, f64)| {
+        rw.cairo_context
+            .set_source_rgba(color.0, color.1, color.2, color.3);
+    };
+
+    // Next to a title, the hint gets a chip of its own so that it's clear which part is matched
+    // against the typed keys. The chip has the text color and the hint the background color.
+    let hint_color = if rw.title.is_some() && app_config.text_direction == TextDirection::Ltr {
+        let shown_hint = if app_config.uppercase_labels {
+            text.to_uppercase()
+        } else {
+            text.to_string()
+        };
+        let hint_extents = rw
+            .cairo_context
+            .text_extents(&shown_hint)
+            .context("Couldn't create TextExtents")?;
+        // The chip spans the height of the box and reaches halfway into the space before the
+        // title.
+        let padding = rw
+            .cairo_context
+            .text_extents(" ")
+            .context("Couldn't create TextExtents")?
+            .x_advance()
+            / 2.0;
+        let x = (rw.draw_pos.0 - padding).max(0.0);
+        let width = rw.draw_pos.0 + hint_extents.x_advance() + padding - x;
+        set_color(text_color);
+        rw.cairo_context.save().context("Error trying to draw")?;
+        rw.cairo_context.translate(x, 0.0);
+        rounded_rectangle(
+            &rw.cairo_context,
+            width,
+            box_height,
+            (box_height / 2.0).min(width / 2.0),
+        );
+        rw.cairo_context.restore().context("Error trying to draw")?;
+        rw.cairo_context.fill().context("Error trying to draw")?;
+        rw.cairo_context.move_to(rw.draw_pos.0, rw.draw_pos.1);
+        (bg_color.0, bg_color.1, bg_color.2, 1.0)
+    } else {
+        text_color
+    };
+
+    let mut row = 0;
+
+    if text.starts_with(current_hints) {
+        // Paint already selected chars.
+        set_color(app_config.match_color.unwrap_or(text_color_alt));
         for c in current_hints.chars() {
-            rw.cairo_context
-                .show_text(&c.to_string())
-                .context("Couldn't display text")?;
+            show_char(c, row)?;
+            row += 1;
         }
     }
 
     // Paint unselected chars.
-    if rw.desktop_window.is_focused {
-        rw.cairo_context.set_source_rgba(
-            app_config.text_color_current.0,
-            app_config.text_color_current.1,
-            app_config.text_color_current.2,
-            app_config.text_color_current.3,
-        );
-    } else {
-        rw.cairo_context.set_source_rgba(
-            app_config.text_color.0,
-            app_config.text_color.1,
-            app_config.text_color.2,
-            app_config.text_color.3,
-        );
+    set_color(hint_color);
+    for c in text.strip_prefix(current_hints).unwrap_or(text).chars() {
+        show_char(c, row)?;
+        row += 1;
     }
-    let re = Regex::new(&format!("^{current_hints}")).unwrap();
-    for c in re.replace(text, "").chars() {
-        rw.cairo_context
-            .show_text(&c.to_string())
-            .context("Couldn't show text")?;
+
+    // Paint the window title after the hint.
+    if let Some(title) = &rw.title {
+        set_color(text_color);
+        match &rw.title_font {
+            Some(title_font) => {
+                rw.cairo_context
+                    .show_text(" ")
+                    .context("Couldn't show title")?;
+                rw.cairo_context.select_font_face(
+                    title_font,
+                    app_config.font_slant.into(),
+                    font_weight(app_config, rw.desktop_window.is_focused),
+                );
+                rw.cairo_context
+                    .show_text(title)
+                    .context("Couldn't show title")?;
+            }
+            None => rw
+                .cairo_context
+                .show_text(&format!(" {title}"))
+                .context("Couldn't show title")?,
+        }
     }
+    rw.cairo_context.restore().context("Error trying to draw")?;
     rw.cairo_context.target().flush();
 
     Ok(())
 }
 
-/// Try to grab the keyboard until `timeout` is reached.
+/// Returns true if `window` is minimized, which EWMH marks by `_NET_WM_STATE_HIDDEN`.
+pub fn is_minimized(conn: &impl Connection, atoms: &Atoms, window: u32) -> Result<bool> {
+    let reply = conn
+        .get_property(
+            false,
+            window,
+            atoms._NET_WM_STATE,
+            AtomEnum::ATOM,
+            0,
+            u32::MAX,
+        )?
+        .reply()
+        .context("Couldn't get _NET_WM_STATE")?;
+    Ok(reply
+        .value32()
+        .is_some_and(|mut states| states.any(|state| state == atoms._NET_WM_STATE_HIDDEN)))
+}
+
+/// Get the window that the window manager reports as active in `_NET_ACTIVE_WINDOW`, if any.
+pub fn get_active_window(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+) -> error::Result<Option<u32>> {
+    let reply = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_ACTIVE_WINDOW,
+            AtomEnum::WINDOW,
+            0,
+            1,
+        )?
+        .reply()
+        .map_err(error::Error::reply("_NET_ACTIVE_WINDOW"))?;
+    Ok(reply
+        .value32()
+        .and_then(|mut windows| windows.next())
+        .filter(|&window| window != x11rb::NONE))
+}
+
+/// Get the class part of the `WM_CLASS` of `window`.
+pub fn get_window_class(conn: &impl Connection, window: u32) -> Result<String> {
+    let wm_class = WmClass::get(conn, window)?
+        .reply()
+        .context("Couldn't get WM_CLASS")?;
+    Ok(String::from_utf8_lossy(wm_class.class()).into_owned())
+}
+
+/// Set the `_NET_WM_WINDOW_OPACITY` of `window` which compositors use to make it translucent.
+pub fn set_opacity(conn: &impl Connection, atoms: &Atoms, window: u32, opacity: f64) -> Result<()> {
+    let opacity = (0xFFFFFFFFu64 as f64 * opacity) as u64;
+    conn.change_property32(
+        PropMode::REPLACE,
+        window,
+        atoms._NET_WM_WINDOW_OPACITY,
+        AtomEnum::CARDINAL,
+        &[opacity as u32],
+    )?;
+    Ok(())
+}
+
+/// Get the override-redirect children of `root` that are currently shown.
 ///
-/// Generally with X, I found that you can't grab global keyboard input without it failing
-/// sometimes due to other clients grabbing it occasionally. Hence, we'll have to keep retrying
-/// until we eventually succeed.
-pub fn snatch_keyboard(conn: &impl Connection, screen: &Screen, timeout: Duration) -> Result<()> {
-    let now = Instant::now();
+/// Window managers don't know about these so they are missing from their window lists.
+pub fn get_override_redirect_windows(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+) -> Result<Vec<DesktopWindow>> {
+    let children = conn
+        .query_tree(root)?
+        .reply()
+        .context("Couldn't query window tree")?
+        .children;
+    let mut cookies = vec![];
+    for &child in &children {
+        cookies.push((child, conn.get_window_attributes(child)?));
+    }
+    let mut windows = vec![];
+    for (child, cookie) in cookies {
+        // Windows can go away while we look at them.
+        let Ok(attributes) = cookie.reply() else {
+            continue;
+        };
+        if attributes.override_redirect && attributes.map_state == MapState::VIEWABLE {
+            windows.push(child);
+        }
+    }
+    Ok(crate::ewmh::describe_windows(conn, atoms, root, &windows)?)
+}
+
+/// Get the geometry of the frame that a reparenting window manager put around `window`.
+///
+/// The frame is taken from the `_NET_FRAME_EXTENTS` of `window` if the window manager sets them
+/// and is the ancestor of `window` that is a child of `root` otherwise.
+pub fn get_frame_rect(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+    window: u32,
+) -> Result<Rect> {
+    let extents = conn
+        .get_property(
+            false,
+            window,
+            atoms._NET_FRAME_EXTENTS,
+            AtomEnum::CARDINAL,
+            0,
+            4,
+        )?
+        .reply()
+        .context("Couldn't get _NET_FRAME_EXTENTS")?;
+    if let Some(&[left, right, top, bottom]) = extents
+        .value32()
+        .map(|values| values.map(|v| v as i32).collect::<Vec<_>>())
+        .as_deref()
+    {
+        let geometry = conn
+            .get_geometry(window)?
+            .reply()
+            .context("Couldn't get geometry")?;
+        let pos = conn
+            .translate_coordinates(window, root, 0, 0)?
+            .reply()
+            .context("Couldn't get position")?;
+        let client = Rect::new(
+            pos.dst_x.into(),
+            pos.dst_y.into(),
+            geometry.width.into(),
+            geometry.height.into(),
+        );
+        return Ok(client.expand(left, right, top, bottom));
+    }
+
+    let mut frame = window;
     loop {
-        if now.elapsed() > timeout {
-            bail!("Couldn't grab keyboard input within {:?}", now.elapsed());
+        let parent = conn
+            .query_tree(frame)?
+            .reply()
+            .context("Couldn't query window tree")?
+            .parent;
+        if parent == root || parent == x11rb::NONE {
+            break;
+        }
+        frame = parent;
+    }
+    let geometry = conn
+        .get_geometry(frame)?
+        .reply()
+        .context("Couldn't get frame geometry")?;
+    let border = i32::from(geometry.border_width);
+    Ok(Rect::new(
+        geometry.x.into(),
+        geometry.y.into(),
+        i32::from(geometry.width) + 2 * border,
+        i32::from(geometry.height) + 2 * border,
+    ))
+}
+
+/// Returns true if the window manager lists `atom` in `_NET_SUPPORTED`.
+fn is_supported(conn: &impl Connection, atoms: &Atoms, root: u32, atom: Atom) -> Result<bool> {
+    let supported = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_SUPPORTED,
+            AtomEnum::ATOM,
+            0,
+            u32::MAX,
+        )?
+        .reply()
+        .context("Couldn't get _NET_SUPPORTED")?;
+    Ok(supported
+        .value32()
+        .is_some_and(|mut atoms| atoms.any(|a| a == atom)))
+}
+
+/// Returns true if the window manager lists `_NET_ACTIVE_WINDOW` in `_NET_SUPPORTED`.
+pub fn supports_active_window(conn: &impl Connection, atoms: &Atoms, root: u32) -> Result<bool> {
+    is_supported(conn, atoms, root, atoms._NET_ACTIVE_WINDOW)
+}
+
+/// Ask the window manager to activate `window` by sending a `_NET_ACTIVE_WINDOW` client message
+/// to the root window.
+pub fn activate_window(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+    window: u32,
+) -> Result<()> {
+    let current = get_active_window(conn, atoms, root)?.unwrap_or(x11rb::NONE);
+    // A source indication of 2 tells the window manager that the request comes from a pager
+    // acting on behalf of the user, so it shouldn't be refused as focus stealing.
+    let event = ClientMessageEvent::new(
+        32,
+        window,
+        atoms._NET_ACTIVE_WINDOW,
+        [2, x11rb::CURRENT_TIME, current, 0, 0],
+    );
+    debug!("Sending _NET_ACTIVE_WINDOW for window 0x{:x}", window);
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Bring `window` to the top of the stack.
+///
+/// Window managers supporting `_NET_RESTACK_WINDOW` are asked to do it, which also raises the
+/// frame around the window. Otherwise the window is raised directly.
+pub fn raise_window(conn: &impl Connection, atoms: &Atoms, root: u32, window: u32) -> Result<()> {
+    if !is_supported(conn, atoms, root, atoms._NET_RESTACK_WINDOW)? {
+        debug!("Raising window 0x{:x}", window);
+        conn.configure_window(
+            window,
+            &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+        )?;
+        conn.flush()?;
+        return Ok(());
+    }
+    // Like for _NET_ACTIVE_WINDOW, we act as a pager on behalf of the user. Without a sibling the
+    // window goes above all others.
+    let event = ClientMessageEvent::new(
+        32,
+        window,
+        atoms._NET_RESTACK_WINDOW,
+        [2, x11rb::NONE, u32::from(StackMode::ABOVE), 0, 0],
+    );
+    debug!("Sending _NET_RESTACK_WINDOW for window 0x{:x}", window);
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        event,
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Raise `window` and give it the X input focus.
+pub fn set_input_focus(conn: &impl Connection, window: u32) -> Result<()> {
+    debug!("Setting input focus to window 0x{:x}", window);
+    conn.configure_window(
+        window,
+        &ConfigureWindowAux::new().stack_mode(StackMode::ABOVE),
+    )?;
+    conn.set_input_focus(InputFocus::PARENT, window, x11rb::CURRENT_TIME)?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Politely ask `window` to close by sending it a `WM_DELETE_WINDOW` client message.
+pub fn close_window(conn: &impl Connection, atoms: &Atoms, window: u32) -> Result<()> {
+    let protocols = conn
+        .get_property(
+            false,
+            window,
+            atoms.WM_PROTOCOLS,
+            AtomEnum::ATOM,
+            0,
+            u32::MAX,
+        )?
+        .reply()
+        .context("Couldn't get WM_PROTOCOLS")?;
+    if !protocols
+        .value32()
+        .is_some_and(|mut protocols| protocols.any(|a| a == atoms.WM_DELETE_WINDOW))
+    {
+        bail!(
+            "Window 0x{:x} doesn't support WM_DELETE_WINDOW, try --action kill",
+            window
+        );
+    }
+    let event = ClientMessageEvent::new(
+        32,
+        window,
+        atoms.WM_PROTOCOLS,
+        [atoms.WM_DELETE_WINDOW, x11rb::CURRENT_TIME, 0, 0, 0],
+    );
+    debug!("Sending WM_DELETE_WINDOW to window 0x{:x}", window);
+    conn.send_event(false, window, EventMask::NO_EVENT, event)?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Show only those hints that start with `pressed_keys` and hide all others.
+///
+/// All hints on a single `overlay` window are hidden by cutting them out of its shape.
+pub fn update_hint_visibility(
+    conn: &impl Connection,
+    render_windows: &HashMap<String, RenderWindow>,
+    pressed_keys: &str,
+    overlay: Option<u32>,
+) -> Result<()> {
+    if let Some(overlay) = overlay {
+        let shape: Vec<Rectangle> = render_windows
+            .iter()
+            .filter(|(hint, _)| hint.starts_with(pressed_keys))
+            .flat_map(|(_, rw)| {
+                rw.shape.iter().map(|r| Rectangle {
+                    x: r.x + rw.rect.x as i16,
+                    y: r.y + rw.rect.y as i16,
+                    ..*r
+                })
+            })
+            .collect();
+        conn.shape_rectangles(
+            shape::SO::SET,
+            shape::SK::BOUNDING,
+            ClipOrdering::UNSORTED,
+            overlay,
+            0,
+            0,
+            &shape,
+        )?;
+        conn.flush()?;
+        return Ok(());
+    }
+    for (hint, rw) in render_windows {
+        if hint.starts_with(pressed_keys) {
+            conn.map_window(rw.xcb_window_id)?;
+        } else {
+            conn.unmap_window(rw.xcb_window_id)?;
+        }
+    }
+    conn.flush()?;
+    Ok(())
+}
+
+/// How often we try to grab input before giving up.
+const GRAB_ATTEMPTS: u32 = 5;
+
+/// Time to wait after the first failed grab, this grows linearly with every further attempt.
+const GRAB_BACKOFF: Duration = Duration::from_millis(20);
+
+/// Call `grab` until it reports `GrabStatus::SUCCESS` or we run out of attempts.
+///
+/// Generally with X, I found that you can't grab global input without it failing sometimes due
+/// to other clients grabbing it occasionally. Hence, we'll have to retry a couple of times.
+fn retry_grab(what: &str, mut grab: impl FnMut() -> Result<GrabStatus>) -> Result<()> {
+    let mut status = GrabStatus::ALREADY_GRABBED;
+    for attempt in 1..=GRAB_ATTEMPTS {
+        status = grab()?;
+        if status == GrabStatus::SUCCESS {
+            return Ok(());
+        }
+        debug!("Couldn't grab {} ({:?}), attempt {}", what, status, attempt);
+        if attempt < GRAB_ATTEMPTS {
+            sleep(GRAB_BACKOFF * attempt);
         }
-        let grab_keyboard_cookie = grab_keyboard(
+    }
+    bail!(
+        "Couldn't grab {} after {} attempts ({:?}), another client seems to be holding it",
+        what,
+        GRAB_ATTEMPTS,
+        status
+    );
+}
+
+/// Grab the keyboard.
+pub fn snatch_keyboard(conn: &impl Connection, screen: &Screen) -> Result<()> {
+    retry_grab("keyboard", || {
+        let reply = grab_keyboard(
             conn,
             true,
             screen.root,
             x11rb::CURRENT_TIME,
             GrabMode::ASYNC,
             GrabMode::ASYNC,
+        )?
+        .reply()
+        .context("Couldn't communicate with X")?;
+        Ok(reply.status)
+    })
+}
+
+/// Receive the raw key events of all keyboards on the root window using XInput2.
+///
+/// Unlike a grab, this keeps working while another client holds a grab on the keyboard.
+pub fn select_raw_keys(conn: &impl Connection, screen: &Screen) -> Result<()> {
+    let version = conn
+        .xinput_xi_query_version(2, 0)?
+        .reply()
+        .context("XInput2 isn't available")?;
+    if version.major_version < 2 {
+        bail!(
+            "Need XInput 2.0 but the X server only supports {}.{}",
+            version.major_version,
+            version.minor_version
         );
-        let grab_keyboard_reply = grab_keyboard_cookie?
-            .reply()
-            .context("Couldn't communicate with X")?;
-        if grab_keyboard_reply.status == GrabStatus::SUCCESS {
-            return Ok(());
-        }
-        sleep(Duration::from_millis(1));
     }
+    conn.xinput_xi_select_events(
+        screen.root,
+        &[xinput::EventMask {
+            deviceid: xinput::Device::ALL_MASTER.into(),
+            mask: vec![xinput::XIEventMask::RAW_KEY_PRESS | xinput::XIEventMask::RAW_KEY_RELEASE],
+        }],
+    )?;
+    conn.flush()?;
+    Ok(())
 }
 
-/// Try to grab the mouse until `timeout` is reached.
-///
-/// Generally with X, I found that you can't grab global mouse input without it failing sometimes
-/// due to other clients grabbing it occasionally. Hence, we'll have to keep retrying until we
-/// eventually succeed.
-pub fn snatch_mouse(conn: &impl Connection, screen: &Screen, timeout: Duration) -> Result<()> {
-    let now = Instant::now();
-    loop {
-        if now.elapsed() > timeout {
-            bail!("Couldn't grab keyboard input within {:?}", now.elapsed());
-        }
-        let grab_pointer_cookie = grab_pointer(
+/// Grab the mouse.
+pub fn snatch_mouse(conn: &impl Connection, screen: &Screen) -> Result<()> {
+    retry_grab("mouse", || {
+        let reply = grab_pointer(
             conn,
             true,
             screen.root,
@@ -236,14 +1006,88 @@ pub fn snatch_mouse(conn: &impl Connection, screen: &Screen, timeout: Duration)
             x11rb::NONE,
             x11rb::NONE,
             x11rb::CURRENT_TIME,
-        );
-        let grab_pointer_reply = grab_pointer_cookie?
+        )?
+        .reply()
+        .context("Couldn't communicate with X")?;
+        Ok(reply.status)
+    })
+}
+
+/// Grab both keyboard and mouse.
+///
+/// Either both grabs are held afterwards or neither is.
+pub fn snatch_input(conn: &impl Connection, screen: &Screen) -> Result<()> {
+    snatch_keyboard(conn, screen)?;
+    if let Err(e) = snatch_mouse(conn, screen) {
+        conn.ungrab_keyboard(x11rb::CURRENT_TIME)?;
+        conn.flush()?;
+        return Err(e);
+    }
+    Ok(())
+}
+
+/// Release the keyboard and mouse grabs.
+pub fn release_input(conn: &impl Connection) -> Result<()> {
+    conn.ungrab_keyboard(x11rb::CURRENT_TIME)?;
+    conn.ungrab_pointer(x11rb::CURRENT_TIME)?;
+    conn.flush()?;
+    Ok(())
+}
+
+/// Holds the input grabbed until it's dropped.
+///
+/// Before letting go, the input focus from before the grab is given back so that the keyboard
+/// isn't left stuck on one of our windows. Being a guard, this also happens on early returns,
+/// errors and panics.
+pub struct InputGrab<'c, C: Connection> {
+    conn: &'c C,
+    previous_focus: Option<GetInputFocusReply>,
+}
+
+impl<'c, C: Connection> InputGrab<'c, C> {
+    /// Remember the current input focus and grab the input using `input_method`.
+    pub fn new(conn: &'c C, screen: &Screen, input_method: InputMethod) -> Result<Self> {
+        let previous_focus = conn
+            .get_input_focus()?
             .reply()
-            .context("Couldn't communicate with X")?;
-        if grab_pointer_reply.status == GrabStatus::SUCCESS {
-            return Ok(());
+            .context("Couldn't get input focus")?;
+        debug!(
+            "Input focus before the grab is 0x{:x}",
+            previous_focus.focus
+        );
+        match input_method {
+            InputMethod::Core => snatch_input(conn, screen)?,
+            InputMethod::Xi2 => {
+                select_raw_keys(conn, screen)?;
+                snatch_mouse(conn, screen)?;
+            }
+        }
+        Ok(InputGrab {
+            conn,
+            previous_focus: Some(previous_focus),
+        })
+    }
+
+    /// Leave the input focus as it is when letting go, eg because we focused a window since.
+    pub fn keep_focus(&mut self) {
+        self.previous_focus = None;
+    }
+}
+
+impl<C: Connection> Drop for InputGrab<'_, C> {
+    fn drop(&mut self) {
+        if let Some(focus) = self.previous_focus.take() {
+            debug!("Restoring input focus to 0x{:x}", focus.focus);
+            if let Err(e) =
+                self.conn
+                    .set_input_focus(focus.revert_to, focus.focus, x11rb::CURRENT_TIME)
+            {
+                warn!("Couldn't restore input focus: {}", e);
+            }
+        }
+        if let Err(e) = release_input(self.conn) {
+            warn!("Couldn't release input: {:#}", e);
         }
-        sleep(Duration::from_millis(1));
     }
 }
 
@@ -256,57 +1100,279 @@ pub fn sort_by_pos(mut dws: Vec<DesktopWindow>) -> Vec<DesktopWindow> {
     dws
 }
 
-/// Returns true if `r1` and `r2` overlap.
-fn intersects(r1: (i32, i32, i32, i32), r2: (i32, i32, i32, i32)) -> bool {
-    let left_corner_inside = r1.0 < r2.0 + r2.2;
-    let right_corner_inside = r1.0 + r1.2 > r2.0;
-    let top_corner_inside = r1.1 < r2.1 + r2.3;
-    let bottom_corner_inside = r1.1 + r1.3 > r2.1;
-    left_corner_inside && right_corner_inside && top_corner_inside && bottom_corner_inside
+/// Sort list of `DesktopWindow`s by class.
+///
+/// Windows of the same class are sorted by position.
+pub fn sort_by_class(dws: Vec<DesktopWindow>) -> Vec<DesktopWindow> {
+    let mut dws = sort_by_pos(dws);
+    dws.sort_by(|a, b| a.class.cmp(&b.class));
+    dws
 }
 
-/// Finds overlaps and returns a list of those rects in the format (x, y, w, h).
-pub fn find_overlaps(
-    rws: Vec<&RenderWindow>,
-    rect: (i32, i32, i32, i32),
-) -> Vec<(i32, i32, i32, i32)> {
+/// Keep only the window of every class with the highest `rank`, eg the topmost one.
+///
+/// Windows without a class are all kept and the order of the kept windows doesn't change.
+pub fn once_per_class(
+    dws: Vec<DesktopWindow>,
+    rank: impl Fn(&DesktopWindow) -> Option<usize>,
+) -> Vec<DesktopWindow> {
+    let mut best: HashMap<&str, (Option<usize>, usize)> = HashMap::new();
+    for (i, dw) in dws.iter().enumerate() {
+        let Some(class) = dw.class.as_deref() else {
+            continue;
+        };
+        let rank = rank(dw);
+        // Ties go to the first window.
+        best.entry(class)
+            .and_modify(|b| {
+                if rank > b.0 {
+                    *b = (rank, i);
+                }
+            })
+            .or_insert((rank, i));
+    }
+    let keep: HashSet<usize> = best.into_values().map(|(_, i)| i).collect();
+    dws.into_iter()
+        .enumerate()
+        .filter(|(i, dw)| dw.class.is_none() || keep.contains(i))
+        .map(|(_, dw)| dw)
+        .collect()
+}
+
+/// Get the `_NET_CLIENT_LIST_STACKING` of the window manager from bottom to top, `None` if it
+/// doesn't provide one.
+pub fn get_client_stacking(
+    conn: &impl Connection,
+    atoms: &Atoms,
+    root: u32,
+) -> Result<Option<Vec<u32>>> {
+    let reply = conn
+        .get_property(
+            false,
+            root,
+            atoms._NET_CLIENT_LIST_STACKING,
+            AtomEnum::WINDOW,
+            0,
+            u32::MAX,
+        )?
+        .reply()
+        .context("Couldn't get _NET_CLIENT_LIST_STACKING")?;
+    Ok(reply.value32().map(|windows| windows.collect()))
+}
+
+/// Get the top-level windows that are currently shown from bottom to top along with their rects.
+///
+/// The requests for all children are sent before waiting for any reply.
+pub fn get_stacking_order(conn: &impl Connection, root: u32) -> Result<Vec<(u32, Rect)>> {
+    let children = conn
+        .query_tree(root)?
+        .reply()
+        .context("Couldn't query window tree")?
+        .children;
+    let mut cookies = vec![];
+    for &child in &children {
+        cookies.push((
+            child,
+            conn.get_window_attributes(child)?,
+            conn.get_geometry(child)?,
+        ));
+    }
+    let mut stack = vec![];
+    for (child, attributes, geometry) in cookies {
+        // Windows might disappear while we're looking at them.
+        let Ok(attributes) = attributes.reply() else {
+            continue;
+        };
+        if attributes.map_state != MapState::VIEWABLE {
+            continue;
+        }
+        let Ok(geometry) = geometry.reply() else {
+            continue;
+        };
+        let border = 2 * i32::from(geometry.border_width);
+        stack.push((
+            child,
+            Rect::new(
+                geometry.x.into(),
+                geometry.y.into(),
+                i32::from(geometry.width) + border,
+                i32::from(geometry.height) + border,
+            ),
+        ));
+    }
+    Ok(stack)
+}
+
+/// Find the child of `root` that `window` is a descendant of.
+pub fn get_toplevel(conn: &impl Connection, root: u32, window: u32) -> Result<u32> {
+    let mut window = window;
+    loop {
+        let parent = conn
+            .query_tree(window)?
+            .reply()
+            .context("Couldn't query window tree")?
+            .parent;
+        if parent == root || parent == x11rb::NONE {
+            return Ok(window);
+        }
+        window = parent;
+    }
+}
+
+/// Finds overlaps and returns a list of those rects.
+pub fn find_overlaps(hint_boxes: &[HintBox], rect: Rect) -> Vec<Rect> {
     let mut overlaps = vec![];
-    for rw in rws {
-        if intersects(rw.rect, rect) {
-            overlaps.push(rw.rect);
+    for hint_box in hint_boxes {
+        if hint_box.rect.intersects(&rect) {
+            overlaps.push(hint_box.rect);
         }
     }
     overlaps
 }
 
+/// Look up the keysym called `name`, eg `Escape`, `XK_Escape` or `XF86AudioMute`.
+///
+/// Names are compared without `XK_` just like pressed keys are. xkeysym can only turn keysyms
+/// into names so the first lookup of anything but the default `Escape` searches the ranges that
+/// named keysyms live in and remembers all names it finds.
+pub fn keysym_from_name(name: &str) -> Option<u32> {
+    static KEYSYMS: OnceLock<HashMap<String, u32>> = OnceLock::new();
+
+    let name = name.replace("XK_", "");
+    if name == "Escape" {
+        return Some(xkeysym::KEY_Escape);
+    }
+    let keysyms = KEYSYMS.get_or_init(|| {
+        const RANGES: [u32; 9] = [
+            0x0, 0xff, 0x100, 0x1000, 0x1004, 0x1005, 0x1006, 0x1007, 0x1008,
+        ];
+        let mut keysyms = HashMap::new();
+        for keysym in RANGES
+            .into_iter()
+            .flat_map(|high| (0..=0xffff).map(move |low| high << 16 | low))
+        {
+            if let Some(keysym_name) = xkeysym::name(keysym) {
+                keysyms
+                    .entry(keysym_name.replacen("XK_", "", 1))
+                    .or_insert(keysym);
+            }
+        }
+        keysyms
+    });
+    keysyms.get(&name).copied()
+}
+
 /// Remove last pressed key from pressed keys
 pub fn remove_last_key(pressed_keys: &mut String, kstr: &str) {
-    if pressed_keys.contains(kstr) {
-        pressed_keys.replace_range(pressed_keys.len() - kstr.len().., "");
+    if let Some(rest) = pressed_keys.strip_suffix(kstr) {
+        pressed_keys.truncate(rest.len());
     }
 }
 
-pub fn get_pressed_symbol(conn: &impl Connection, event: Event) -> u32 {
-    let mapping = conn
-        .get_keyboard_mapping(
-            conn.setup().min_keycode,
-            conn.setup().max_keycode - conn.setup().min_keycode + 1,
-        )
-        .unwrap()
-        .reply()
-        .unwrap();
+/// A snapshot of the keyboard mapping of the X server.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    min_keycode: u8,
+    max_keycode: u8,
+    keysyms_per_keycode: u8,
+    keysyms: Vec<u32>,
+}
 
-    match event {
-        Event::KeyPress(event) | Event::KeyRelease(event) => xkeysym::keysym(
-            event.detail,
+impl Keymap {
+    /// Get the current keyboard mapping.
+    pub fn get(conn: &impl Connection) -> Result<Keymap> {
+        let (min_keycode, max_keycode) = (conn.setup().min_keycode, conn.setup().max_keycode);
+        let mapping = conn
+            .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+            .reply()
+            .context("Couldn't get keyboard mapping")?;
+        Ok(Keymap {
+            min_keycode,
+            max_keycode,
+            keysyms_per_keycode: mapping.keysyms_per_keycode,
+            keysyms: mapping.keysyms,
+        })
+    }
+
+    /// The keysym that `keycode` produces without any modifiers.
+    pub fn keysym(&self, keycode: u8) -> Option<u32> {
+        if !(self.min_keycode..=self.max_keycode).contains(&keycode) {
+            return None;
+        }
+        xkeysym::keysym(
+            keycode,
             0,
-            conn.setup().min_keycode,
-            mapping.keysyms_per_keycode,
-            mapping.keysyms.as_slice(),
+            self.min_keycode,
+            self.keysyms_per_keycode,
+            &self.keysyms,
         )
-        .unwrap(),
-        _ => unreachable!(),
     }
+
+    /// Characters of `chars` that no key produces without modifiers.
+    pub fn missing_chars(&self, chars: &str) -> Vec<char> {
+        chars
+            .chars()
+            .filter(|&c| {
+                !(self.min_keycode..=self.max_keycode).any(|keycode| {
+                    self.keysym(keycode)
+                        .and_then(xkeysym::name)
+                        .is_some_and(|name| name.replace("XK_", "") == c.to_string())
+                })
+            })
+            .collect()
+    }
+}
+
+/// The keycode of the key in `event`, `None` if it isn't a key event.
+pub fn event_keycode(event: &Event) -> Option<u8> {
+    match event {
+        Event::KeyPress(event) | Event::KeyRelease(event) => Some(event.detail),
+        Event::XinputRawKeyPress(event) | Event::XinputRawKeyRelease(event) => {
+            Some(event.detail as u8)
+        }
+        _ => None,
+    }
+}
+
+/// Keycodes of the keys set in the bit vector `keys` of a `QueryKeymap` reply.
+fn keycodes_in_keymap(keys: &[u8; 32]) -> HashSet<u8> {
+    (0..=u8::MAX)
+        .filter(|&keycode| keys[usize::from(keycode / 8)] & (1 << (keycode % 8)) != 0)
+        .collect()
+}
+
+/// Keycodes of all keys that are held down right now.
+pub fn get_held_keys(conn: &impl Connection) -> Result<HashSet<u8>> {
+    let reply = conn
+        .query_keymap()?
+        .reply()
+        .context("Couldn't query the keyboard state")?;
+    Ok(keycodes_in_keymap(&reply.keys))
+}
+
+/// Get the keysym of the key in `event`.
+///
+/// The key is looked up in `fixed_keymap` if given and in the current keyboard mapping
+/// otherwise.
+pub fn get_pressed_symbol(
+    conn: &impl Connection,
+    event: Event,
+    fixed_keymap: Option<&Keymap>,
+) -> Result<u32> {
+    let Some(keycode) = event_keycode(&event) else {
+        bail!("Not a key event: {:?}", event);
+    };
+    let current_keymap;
+    let keymap = match fixed_keymap {
+        Some(keymap) => keymap,
+        None => {
+            current_keymap = Keymap::get(conn)?;
+            &current_keymap
+        }
+    };
+    keymap
+        .keysym(keycode)
+        .with_context(|| format!("No keysym for keycode {}", keycode))
 }
 
 /// Struct helps to write sequence and check if it is found in list of exit sequences
@@ -355,18 +1421,346 @@ impl Sequence {
     }
 }
 
+impl std::fmt::Display for Sequence {
+    /// Write the keys like `--exit-keys` takes them, eg `Control_L+g`.
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.sequence.join("+"))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_get_uniform_hints_unique() {
+        for count in 1..=200 {
+            let hints = get_uniform_hints("sadfjklewcmpgh", count).unwrap();
+            assert_eq!(hints.len(), count);
+            let unique: HashSet<&String> = hints.iter().collect();
+            assert_eq!(unique.len(), count);
+        }
+    }
+
+    #[test]
+    fn test_get_hints_multibyte() {
+        // Four characters that take up 13 bytes.
+        let hint_chars = "éàü🦀";
+        for count in [3, 4, 5, 16, 17] {
+            let hints = get_uniform_hints(hint_chars, count).unwrap();
+            let unique: HashSet<&String> = hints.iter().collect();
+            assert_eq!(unique.len(), count);
+            let length = if count <= 4 {
+                1
+            } else if count <= 16 {
+                2
+            } else {
+                3
+            };
+            assert!(hints.iter().all(|h| h.chars().count() == length));
+            assert!(hints
+                .iter()
+                .all(|h| h.chars().all(|c| hint_chars.contains(c))));
+        }
+        let hints = get_shortest_hints(hint_chars, 7).unwrap();
+        assert_eq!(hints, vec!["é", "à", "ü", "🦀é", "🦀à", "🦀ü", "🦀🦀"]);
+    }
+
+    #[test]
+    fn test_keymap() {
+        // Two keysyms per keycode: the plain and the shifted one.
+        let keymap = Keymap {
+            min_keycode: 8,
+            max_keycode: 10,
+            keysyms_per_keycode: 2,
+            keysyms: vec![
+                xkeysym::KEY_a,
+                xkeysym::KEY_A,
+                xkeysym::KEY_s,
+                xkeysym::KEY_S,
+                xkeysym::KEY_Escape,
+                0,
+            ],
+        };
+        assert_eq!(keymap.keysym(9), Some(xkeysym::KEY_s));
+        assert_eq!(keymap.keysym(10), Some(xkeysym::KEY_Escape));
+        assert_eq!(keymap.keysym(7), None);
+        assert_eq!(keymap.keysym(11), None);
+        assert_eq!(keymap.missing_chars("asdA"), vec!['d', 'A']);
+    }
+
+    #[test]
+    fn test_keycodes_in_keymap() {
+        let mut keys = [0; 32];
+        assert!(keycodes_in_keymap(&keys).is_empty());
+        // Keycode 9 is usually Escape and 133 Super_L.
+        keys[1] = 0b10;
+        keys[16] = 0b100000;
+        keys[31] = 0b10000000;
+        assert_eq!(keycodes_in_keymap(&keys), HashSet::from([9, 133, 255]));
+    }
+
+    #[test]
+    fn test_remove_last_key() {
+        let mut pressed_keys = "aé".to_string();
+        remove_last_key(&mut pressed_keys, "a");
+        assert_eq!(pressed_keys, "aé");
+        remove_last_key(&mut pressed_keys, "é");
+        assert_eq!(pressed_keys, "a");
+    }
+
+    #[test]
+    fn test_get_uniform_hints_order() {
+        assert_eq!(
+            get_uniform_hints("ab", 4).unwrap(),
+            vec!["aa", "ab", "ba", "bb"]
+        );
+        assert_eq!(
+            get_uniform_hints("abc", 4).unwrap(),
+            vec!["aa", "ab", "ac", "ba"]
+        );
+        assert_eq!(get_uniform_hints("ab", 0).unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_get_shortest_hints_single_chars() {
+        for count in 1..=4 {
+            let hints = get_shortest_hints("asdf", count).unwrap();
+            assert_eq!(hints.len(), count);
+            assert!(hints.iter().all(|h| h.len() == 1));
+        }
+    }
+
+    #[test]
+    fn test_get_shortest_hints_prefix_free() {
+        assert_eq!(
+            get_shortest_hints("abc", 4).unwrap(),
+            vec!["a", "b", "ca", "cb"]
+        );
+        for count in 1..=200 {
+            let hints = get_shortest_hints("sadfjklewcmpgh", count).unwrap();
+            assert_eq!(hints.len(), count);
+            for (i, a) in hints.iter().enumerate() {
+                for (j, b) in hints.iter().enumerate() {
+                    assert!(
+                        i == j || !b.starts_with(a.as_str()),
+                        "{a} is a prefix of {b}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_get_uniform_hints_too_few_chars() {
+        assert!(get_uniform_hints("", 2).is_err());
+        assert!(get_uniform_hints("a", 2).is_err());
+    }
+
+    #[test]
+    fn test_fold_hint_chars() {
+        assert_eq!(fold_hint_chars("asdf"), "asdf");
+        assert_eq!(fold_hint_chars("AasSd"), "Asd");
+    }
+
+    #[test]
+    fn test_keysym_from_name() {
+        assert_eq!(keysym_from_name("Escape"), Some(xkeysym::KEY_Escape));
+        assert_eq!(
+            keysym_from_name("XK_Caps_Lock"),
+            Some(xkeysym::KEY_Caps_Lock)
+        );
+        assert_eq!(keysym_from_name("q"), Some(xkeysym::KEY_q));
+        assert_eq!(
+            keysym_from_name("XF86AudioMute"),
+            Some(xkeysym::KEY_XF86_AudioMute)
+        );
+        assert_eq!(keysym_from_name("NotAKey"), None);
+    }
+
+    #[test]
+    fn test_match_hint_char_case() {
+        assert_eq!(match_hint_char_case("a", "ABC"), "A");
+        assert_eq!(match_hint_char_case("b", "abc"), "b");
+        assert_eq!(match_hint_char_case("Escape", "abc"), "Escape");
+    }
+
+    #[test]
+    fn test_clamp_corner_radius() {
+        assert_eq!(clamp_corner_radius(0, 100, 50), 0.0);
+        assert_eq!(clamp_corner_radius(10, 100, 50), 10.0);
+        assert_eq!(clamp_corner_radius(40, 100, 50), 25.0);
+    }
+
+    #[test]
+    fn test_rounded_rect_region() {
+        let rects = rounded_rect_region(100, 50, 10.0);
+        assert_eq!(rects.iter().map(|r| r.height).sum::<u16>(), 50);
+        // The middle is a full rectangle.
+        assert!(rects
+            .iter()
+            .any(|r| r.x == 0 && r.width == 100 && r.height >= 30));
+        // The corners are cut off.
+        assert!(rects[0].x > 0);
+        assert!(rects.last().unwrap().x > 0);
+    }
+
+    fn window(class: &str, pos: (i32, i32)) -> DesktopWindow {
+        DesktopWindow {
+            id: 0,
+            x_window_id: None,
+            class: Some(class.to_string()),
+            instance: None,
+            title: None,
+            target: None,
+            pos,
+            size: (100, 100),
+            is_focused: false,
+        }
+    }
+
+    #[test]
+    fn test_font_size_for_window() {
+        assert_eq!(font_size_for_window(0.05, (800, 800), 8.0, 72.0), 40.0);
+        assert_eq!(font_size_for_window(0.05, (100, 100), 8.0, 72.0), 8.0);
+        assert_eq!(font_size_for_window(0.05, (3840, 2160), 8.0, 72.0), 72.0);
+        assert_eq!(font_size_for_window(0.05, (0, 600), 8.0, 72.0), 8.0);
+    }
 
     #[test]
-    fn test_intersects() {
-        assert!(intersects((1905, 705, 31, 82), (1905, 723, 38, 64)));
+    fn test_find_argb_visual() {
+        let visual = |visual_id, class| Visualtype {
+            visual_id,
+            class,
+            ..Default::default()
+        };
+        let depth = |depth, visuals| x11rb::protocol::xproto::Depth { depth, visuals };
+        let mut screen = Screen {
+            allowed_depths: vec![
+                depth(24, vec![visual(0x21, VisualClass::TRUE_COLOR)]),
+                depth(32, vec![visual(0x40, VisualClass::DIRECT_COLOR)]),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(find_argb_visual(&screen), None);
+        screen.allowed_depths[1]
+            .visuals
+            .push(visual(0x41, VisualClass::TRUE_COLOR));
+        assert_eq!(find_argb_visual(&screen).map(|v| v.visual_id), Some(0x41));
+    }
+
+    #[test]
+    fn test_set_antialias() {
+        let surface = cairo::ImageSurface::create(cairo::Format::ARgb32, 1, 1).unwrap();
+        let context = cairo::Context::new(&surface).unwrap();
+        set_antialias(&context, Antialias::None).unwrap();
+        assert_eq!(context.antialias(), cairo::Antialias::None);
+        assert_eq!(
+            context.font_options().unwrap().antialias(),
+            cairo::Antialias::None
+        );
+    }
+
+    #[test]
+    fn test_max_hint_count() {
+        assert_eq!(max_hint_count("ab", 1), 2);
+        assert_eq!(max_hint_count("asdf", 3), 64);
+        assert_eq!(max_hint_count("sadfjklewcmpgh", 100), usize::MAX);
+    }
+
+    #[test]
+    fn test_get_numeric_hints() {
+        assert_eq!(get_numeric_hints(3), vec!["1", "2", "3"]);
+        let hints = get_numeric_hints(10);
+        assert_eq!(hints.first().map(String::as_str), Some("01"));
+        assert_eq!(hints.last().map(String::as_str), Some("10"));
+        assert!(get_numeric_hints(0).is_empty());
+    }
+
+    #[test]
+    fn test_shadow_padding() {
+        assert_eq!(shadow_padding(&Offset { x: 0, y: 0 }, 0), (0, 0, 0, 0));
+        assert_eq!(shadow_padding(&Offset { x: 3, y: 4 }, 0), (0, 0, 3, 4));
+        assert_eq!(shadow_padding(&Offset { x: 3, y: -2 }, 2), (0, 4, 5, 0));
+        assert_eq!(shadow_padding(&Offset { x: 0, y: 0 }, 5), (5, 5, 5, 5));
+    }
+
+    #[test]
+    fn test_retry_grab() {
+        let mut attempts = 0;
+        retry_grab("test", || {
+            attempts += 1;
+            Ok(if attempts < 3 {
+                GrabStatus::ALREADY_GRABBED
+            } else {
+                GrabStatus::SUCCESS
+            })
+        })
+        .unwrap();
+        assert_eq!(attempts, 3);
+
+        let mut attempts = 0;
+        let err = retry_grab("test", || {
+            attempts += 1;
+            Ok(GrabStatus::FROZEN)
+        })
+        .unwrap_err();
+        assert_eq!(attempts, GRAB_ATTEMPTS);
+        assert!(err.to_string().contains("Couldn't grab test"));
+    }
+
+    #[test]
+    fn test_sort_by_pos() {
+        let dws = vec![
+            window("a", (100, 100)),
+            window("b", (0, 100)),
+            window("c", (100, 0)),
+            window("d", (0, 0)),
+        ];
+        let sorted: Vec<_> = sort_by_pos(dws).into_iter().map(|w| w.pos).collect();
+        assert_eq!(sorted, vec![(0, 0), (100, 0), (0, 100), (100, 100)]);
+    }
+
+    #[test]
+    fn test_sort_by_class() {
+        let dws = vec![
+            window("firefox", (100, 0)),
+            window("alacritty", (100, 0)),
+            window("firefox", (0, 0)),
+            window("alacritty", (0, 0)),
+        ];
+        let sorted: Vec<_> = sort_by_class(dws)
+            .into_iter()
+            .map(|w| (w.class.unwrap(), w.pos))
+            .collect();
+        assert_eq!(
+            sorted,
+            vec![
+                ("alacritty".to_string(), (0, 0)),
+                ("alacritty".to_string(), (100, 0)),
+                ("firefox".to_string(), (0, 0)),
+                ("firefox".to_string(), (100, 0)),
+            ]
+        );
     }
 
     #[test]
-    fn test_no_intersect() {
-        assert!(!intersects((1905, 705, 31, 82), (2000, 723, 38, 64)));
+    fn test_once_per_class() {
+        let mut dws = vec![
+            window("Alacritty", (0, 0)),
+            window("firefox", (100, 0)),
+            window("Alacritty", (200, 0)),
+            window("Alacritty", (300, 0)),
+        ];
+        dws[3].class = None;
+        let kept = once_per_class(dws, |dw| match dw.pos.0 {
+            0 => Some(1),
+            100 => None,
+            _ => Some(2),
+        });
+        let positions: Vec<i32> = kept.iter().map(|dw| dw.pos.0).collect();
+        assert_eq!(positions, vec![100, 200, 300]);
     }
 
     #[test]
@@ -0,0 +1,873 @@
+//! Showing hints on Wayland compositors through the wlr layer-shell and
+//! foreign-toplevel-management protocols.
+//!
+//! These protocols don't tell us where windows are, so all hints are shown in a grid in the middle
+//! of the screen along with the titles of their windows.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::ffi::c_char;
+use std::fs::File;
+use std::os::fd::{AsFd, OwnedFd};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use log::{debug, info, warn};
+use rustix::event::{PollFd, PollFlags, Timespec};
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{
+    wl_buffer, wl_compositor, wl_keyboard, wl_pointer, wl_registry, wl_seat, wl_shm, wl_shm_pool,
+    wl_surface,
+};
+use wayland_client::{
+    delegate_noop, event_created_child, Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum,
+};
+use wayland_protocols_wlr::foreign_toplevel::v1::client::{
+    zwlr_foreign_toplevel_handle_v1 as toplevel_handle,
+    zwlr_foreign_toplevel_manager_v1 as toplevel_manager,
+};
+use wayland_protocols_wlr::layer_shell::v1::client::{
+    zwlr_layer_shell_v1 as layer_shell, zwlr_layer_surface_v1 as layer_surface,
+};
+use xkbcommon_dl as xkb;
+
+use crate::args::{self, AppConfig};
+use crate::backend::Backend;
+use crate::{
+    measure, monitor, utils, DesktopWindow, HintKeys, KeyPress, Rect, RenderWindow, Selection,
+    FADE_STEPS,
+};
+
+/// DPI that font sizes in points are converted at, compositors scale our surface from there.
+const WAYLAND_DPI: f64 = 96.0;
+
+/// A window as the compositor told us about it.
+#[derive(Debug)]
+struct Toplevel {
+    handle: toplevel_handle::ZwlrForeignToplevelHandleV1,
+    title: Option<String>,
+    app_id: Option<String>,
+    activated: bool,
+    minimized: bool,
+    /// Whether all properties have been sent at least once.
+    done: bool,
+}
+
+/// Input that arrived for our surface.
+#[derive(Debug)]
+enum Input {
+    Key { keysym: u32, pressed: bool },
+    Button,
+    FocusLost,
+}
+
+/// A keymap compiled by libxkbcommon along with the state of the modifiers.
+struct XkbKeymap {
+    xkb: &'static xkb::XkbCommon,
+    context: *mut xkb::xkb_context,
+    keymap: *mut xkb::xkb_keymap,
+    state: *mut xkb::xkb_state,
+}
+
+impl XkbKeymap {
+    /// Compile the keymap of `size` bytes the compositor sent in `fd`.
+    fn new(fd: OwnedFd, size: u32) -> Result<XkbKeymap> {
+        let xkb = xkb::xkbcommon_option().context("Couldn't load libxkbcommon")?;
+        let file = File::from(fd);
+        // SAFETY: The compositor doesn't change the keymap after sending it and we only read it.
+        let map = unsafe {
+            memmap2::MmapOptions::new()
+                .len(size as usize)
+                .map_copy_read_only(&file)
+        }
+        .context("Couldn't map keymap")?;
+        // The keymap is terminated by a NUL which libxkbcommon doesn't want to see.
+        let len = map.iter().position(|&b| b == 0).unwrap_or(map.len());
+
+        // SAFETY: All pointers are checked before they're used and freed exactly once on drop.
+        unsafe {
+            let context = (xkb.xkb_context_new)(xkb::xkb_context_flags::XKB_CONTEXT_NO_FLAGS);
+            if context.is_null() {
+                bail!("Couldn't create xkb context");
+            }
+            let keymap = (xkb.xkb_keymap_new_from_buffer)(
+                context,
+                map.as_ptr() as *const c_char,
+                len,
+                xkb::xkb_keymap_format::XKB_KEYMAP_FORMAT_TEXT_V1,
+                xkb::xkb_keymap_compile_flags::XKB_KEYMAP_COMPILE_NO_FLAGS,
+            );
+            if keymap.is_null() {
+                (xkb.xkb_context_unref)(context);
+                bail!("Couldn't compile keymap");
+            }
+            let state = (xkb.xkb_state_new)(keymap);
+            if state.is_null() {
+                (xkb.xkb_keymap_unref)(keymap);
+                (xkb.xkb_context_unref)(context);
+                bail!("Couldn't create xkb state");
+            }
+            Ok(XkbKeymap {
+                xkb,
+                context,
+                keymap,
+                state,
+            })
+        }
+    }
+
+    /// The keysym that the evdev `key` produces with the current modifiers.
+    fn keysym(&self, key: u32) -> u32 {
+        // SAFETY: `state` is valid for as long as we are. Wayland key codes are evdev codes which
+        // are 8 less than xkb ones.
+        unsafe { (self.xkb.xkb_state_key_get_one_sym)(self.state, key + 8) }
+    }
+
+    fn update_modifiers(&self, depressed: u32, latched: u32, locked: u32, group: u32) {
+        // SAFETY: `state` is valid for as long as we are.
+        unsafe {
+            (self.xkb.xkb_state_update_mask)(self.state, depressed, latched, locked, 0, 0, group);
+        }
+    }
+}
+
+impl Drop for XkbKeymap {
+    fn drop(&mut self) {
+        // SAFETY: These were created in `new` and are only freed here.
+        unsafe {
+            (self.xkb.xkb_state_unref)(self.state);
+            (self.xkb.xkb_keymap_unref)(self.keymap);
+            (self.xkb.xkb_context_unref)(self.context);
+        }
+    }
+}
+
+/// Everything the compositor sent that we care about.
+#[derive(Default)]
+struct State {
+    toplevels: BTreeMap<u32, Toplevel>,
+    keyboard: Option<wl_keyboard::WlKeyboard>,
+    pointer: Option<wl_pointer::WlPointer>,
+    keymap: Option<XkbKeymap>,
+    input: VecDeque<Input>,
+    /// Size of the overlay once the compositor configured it.
+    configured: Option<(u32, u32)>,
+    /// The compositor took the overlay away, eg because its output is gone.
+    closed: bool,
+    /// Which of the overlay's buffers the compositor doesn't read from anymore.
+    free_buffers: [bool; 2],
+}
+
+/// A surface covering the whole output that the hints are drawn onto.
+struct Overlay {
+    conn: Connection,
+    surface: wl_surface::WlSurface,
+    layer: layer_surface::ZwlrLayerSurfaceV1,
+    pool: wl_shm_pool::WlShmPool,
+    buffers: [wl_buffer::WlBuffer; 2],
+    memory: memmap2::MmapMut,
+    size: (i32, i32),
+}
+
+impl Overlay {
+    /// Show `canvas` in the areas of the `render_windows` that match `pressed_keys`.
+    ///
+    /// Returns `false` if the compositor still reads from both buffers, it has to be tried again
+    /// once one of them is released.
+    fn present(
+        &mut self,
+        app_config: &AppConfig,
+        canvas: &cairo::ImageSurface,
+        render_windows: &std::collections::HashMap<String, RenderWindow>,
+        pressed_keys: &str,
+        opacity: f64,
+        free_buffers: &mut [bool; 2],
+    ) -> Result<bool> {
+        let Some(index) = free_buffers.iter().position(|&free| free) else {
+            return Ok(false);
+        };
+        let (width, height) = self.size;
+        let mut frame = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+            .context("Couldn't create Cairo Surface")?;
+        {
+            let context = cairo::Context::new(&frame).context("Couldn't create Cairo Context")?;
+            if app_config.dim > 0.0 {
+                context.set_source_rgba(0.0, 0.0, 0.0, app_config.dim);
+                context.paint()?;
+            }
+            for rw in render_windows
+                .iter()
+                .filter(|(hint, _)| hint.starts_with(pressed_keys))
+                .map(|(_, rw)| rw)
+            {
+                for r in &rw.shape {
+                    context.rectangle(
+                        f64::from(rw.rect.x + i32::from(r.x)),
+                        f64::from(rw.rect.y + i32::from(r.y)),
+                        r.width.into(),
+                        r.height.into(),
+                    );
+                }
+            }
+            context.clip();
+            context.set_source_surface(canvas, 0.0, 0.0)?;
+            context.paint_with_alpha(opacity)?;
+        }
+        frame.flush();
+
+        let frame_size = (width * height * 4) as usize;
+        let data = frame.data().context("Couldn't read Cairo Surface")?;
+        self.memory[index * frame_size..][..frame_size].copy_from_slice(&data);
+        self.surface.attach(Some(&self.buffers[index]), 0, 0);
+        self.surface.damage_buffer(0, 0, width, height);
+        self.surface.commit();
+        free_buffers[index] = false;
+        Ok(true)
+    }
+}
+
+impl Drop for Overlay {
+    fn drop(&mut self) {
+        for buffer in &self.buffers {
+            buffer.destroy();
+        }
+        self.pool.destroy();
+        self.layer.destroy();
+        self.surface.destroy();
+        if let Err(e) = self.conn.flush() {
+            warn!("Couldn't remove overlay: {}", e);
+        }
+    }
+}
+
+/// Show hints on a Wayland compositor.
+pub(crate) struct WaylandBackend {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+    compositor: wl_compositor::WlCompositor,
+    shm: wl_shm::WlShm,
+    seat: wl_seat::WlSeat,
+    layer_shell: layer_shell::ZwlrLayerShellV1,
+}
+
+impl WaylandBackend {
+    /// Connect to the compositor `WAYLAND_DISPLAY` names.
+    ///
+    /// Font sizes given in points are converted to pixels at 96 DPI.
+    pub(crate) fn connect(app_config: &mut AppConfig) -> Result<WaylandBackend> {
+        let conn = Connection::connect_to_env().context("No Wayland connection")?;
+        let (globals, mut queue) =
+            registry_queue_init::<State>(&conn).context("Couldn't get Wayland globals")?;
+        let qh = queue.handle();
+        let compositor = globals
+            .bind(&qh, 4..=6, ())
+            .context("Compositor doesn't support wl_compositor")?;
+        let shm = globals
+            .bind(&qh, 1..=1, ())
+            .context("Compositor doesn't support wl_shm")?;
+        let seat = globals
+            .bind(&qh, 1..=7, ())
+            .context("Compositor doesn't support wl_seat")?;
+        let layer_shell = globals
+            .bind(&qh, 1..=4, ())
+            .context("Compositor doesn't support the wlr layer-shell protocol")?;
+        let _: toplevel_manager::ZwlrForeignToplevelManagerV1 = globals
+            .bind(&qh, 1..=3, ())
+            .context("Compositor doesn't support the wlr foreign-toplevel-management protocol")?;
+
+        let mut state = State::default();
+        // The first round trip announces the windows and input devices, the second their details.
+        queue.roundtrip(&mut state)?;
+        queue.roundtrip(&mut state)?;
+
+        if app_config.font_unit == args::FontUnit::Pt {
+            app_config.font.font_size =
+                monitor::points_to_pixels(app_config.font.font_size, WAYLAND_DPI);
+            info!(
+                "Using a font size of {}px at {:.0} DPI",
+                app_config.font.font_size, WAYLAND_DPI
+            );
+        }
+
+        Ok(WaylandBackend {
+            conn,
+            queue,
+            state,
+            compositor,
+            shm,
+            seat,
+            layer_shell,
+        })
+    }
+
+    /// Put a surface covering the whole output above everything else and take the keyboard.
+    fn create_overlay(&mut self) -> Result<Overlay> {
+        let qh = self.queue.handle();
+        let surface = self.compositor.create_surface(&qh, ());
+        let layer = self.layer_shell.get_layer_surface(
+            &surface,
+            None,
+            layer_shell::Layer::Overlay,
+            "wmfocus".to_string(),
+            &qh,
+            (),
+        );
+        layer.set_anchor(
+            layer_surface::Anchor::Top
+                | layer_surface::Anchor::Bottom
+                | layer_surface::Anchor::Left
+                | layer_surface::Anchor::Right,
+        );
+        // Panels don't push us away.
+        layer.set_exclusive_zone(-1);
+        layer.set_keyboard_interactivity(layer_surface::KeyboardInteractivity::Exclusive);
+        surface.commit();
+
+        self.state.configured = None;
+        self.state.closed = false;
+        while self.state.configured.is_none() {
+            if self.state.closed {
+                bail!("Compositor closed the overlay right away");
+            }
+            self.queue.blocking_dispatch(&mut self.state)?;
+        }
+        let (width, height) = self.state.configured.unwrap_or_default();
+        let (width, height) = (width as i32, height as i32);
+        debug!("Overlay is {}x{}", width, height);
+
+        // Two buffers let us draw the next frame while the compositor shows the last one.
+        let frame_size = width * height * 4;
+        let fd = rustix::fs::memfd_create("wmfocus", rustix::fs::MemfdFlags::CLOEXEC)
+            .context("Couldn't create shared memory")?;
+        let file = File::from(fd);
+        file.set_len(2 * frame_size as u64)
+            .context("Couldn't size shared memory")?;
+        // SAFETY: Only the compositor reads from the file, and only from buffers we committed.
+        let memory =
+            unsafe { memmap2::MmapMut::map_mut(&file) }.context("Couldn't map shared memory")?;
+        let pool = self.shm.create_pool(file.as_fd(), 2 * frame_size, &qh, ());
+        let buffers = [0, 1].map(|i| {
+            pool.create_buffer(
+                i * frame_size,
+                width,
+                height,
+                width * 4,
+                wl_shm::Format::Argb8888,
+                &qh,
+                i as usize,
+            )
+        });
+        self.state.free_buffers = [true; 2];
+
+        Ok(Overlay {
+            conn: self.conn.clone(),
+            surface,
+            layer,
+            pool,
+            buffers,
+            memory,
+            size: (width, height),
+        })
+    }
+
+    /// Wait for the compositor to send something for at most `timeout`.
+    fn wait(&mut self, timeout: Option<Duration>) -> Result<()> {
+        self.queue.flush()?;
+        let Some(guard) = self.queue.prepare_read() else {
+            return Ok(());
+        };
+        let timeout = timeout.map(|t| Timespec {
+            tv_sec: t.as_secs() as _,
+            tv_nsec: t.subsec_nanos() as _,
+        });
+        let fd = guard.connection_fd();
+        let ready =
+            match rustix::event::poll(&mut [PollFd::new(&fd, PollFlags::IN)], timeout.as_ref()) {
+                Ok(ready) => ready,
+                Err(rustix::io::Errno::INTR) => 0,
+                Err(e) => return Err(e).context("Couldn't wait for Wayland events"),
+            };
+        if ready > 0 {
+            guard.read()?;
+        }
+        self.queue.dispatch_pending(&mut self.state)?;
+        Ok(())
+    }
+}
+
+impl Backend for WaylandBackend {
+    fn monitors(&self) -> &[monitor::Monitor] {
+        &[]
+    }
+
+    fn list_windows(&mut self, app_config: &AppConfig) -> Result<Vec<DesktopWindow>> {
+        for (option, set) in [
+            (
+                "--monitor",
+                app_config.monitor != args::MonitorSelection::All,
+            ),
+            ("--region", app_config.region.is_some()),
+            ("--group-by-monitor", app_config.group_by_monitor),
+            ("--windows-from-stdin", app_config.windows_from_stdin),
+        ] {
+            if set {
+                warn!("The Wayland backend doesn't know where windows are, ignoring {option}");
+            }
+        }
+
+        self.queue.roundtrip(&mut self.state)?;
+        let windows = self
+            .state
+            .toplevels
+            .iter()
+            .filter(|(_, t)| t.done)
+            .filter(|(_, t)| !(app_config.skip_minimized && t.minimized))
+            .map(|(&id, t)| DesktopWindow {
+                id: id.into(),
+                x_window_id: None,
+                class: t.app_id.clone(),
+                instance: None,
+                title: t.title.clone(),
+                target: None,
+                pos: (0, 0),
+                size: (0, 0),
+                is_focused: t.activated,
+            })
+            .filter(|w| app_config.filter.matches(w));
+
+        // Only other instances of the active window's application get a hint.
+        let windows: Vec<DesktopWindow> = if app_config.same_class {
+            let active = self
+                .state
+                .toplevels
+                .values()
+                .find(|t| t.activated)
+                .context("No active window to take the class from")?;
+            let class = active.app_id.clone();
+            info!("Only hinting other windows of class '{:?}'", class);
+            windows
+                .filter(|w| w.class == class && !w.is_focused)
+                .collect()
+        } else {
+            windows.collect()
+        };
+
+        // Without a stacking order, the first window of every application is as good as any.
+        let windows = if app_config.once_per_class {
+            utils::once_per_class(windows, |_| None)
+        } else {
+            windows
+        };
+        let mut windows = match app_config.order {
+            args::HintOrder::Class => utils::sort_by_class(windows),
+            _ => windows,
+        };
+
+        if let Some(max_windows) = app_config.max_windows {
+            if windows.len() > max_windows {
+                warn!(
+                    "Found {} windows, only hinting the first {}",
+                    windows.len(),
+                    max_windows
+                );
+                windows.truncate(max_windows);
+            }
+        }
+        Ok(windows)
+    }
+
+    fn show_hints(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_windows: Vec<DesktopWindow>,
+    ) -> Result<Option<Selection>> {
+        // Without positions, the windows can only be told apart by their titles.
+        let mut app_config = app_config.clone();
+        app_config.show_title = true;
+        app_config.hint_position_relative_to = args::HintAnchor::Pointer;
+        if app_config.legend != args::LegendPosition::Off {
+            warn!("The Wayland backend doesn't show a legend");
+        }
+        let app_config = &app_config;
+
+        let hinted_windows = crate::hint_windows(app_config, desktop_windows, &[])?;
+        if let [(hint, desktop_window)] = hinted_windows.as_slice() {
+            if app_config.same_class_auto {
+                info!("Only one other window of the same class, selecting it right away");
+                return Ok(Some(Selection::Window {
+                    window: desktop_window.clone(),
+                    hint: hint.clone(),
+                }));
+            }
+        }
+
+        self.state.input.clear();
+        let mut overlay = self.create_overlay()?;
+        let (width, height) = overlay.size;
+
+        let mut text_measurer = measure::TextMeasurer::new()?;
+        text_measurer.set_fast_metrics(app_config.fast_metrics);
+        let screen_rect = Rect::new(0, 0, width, height);
+        let hint_boxes = crate::layout_hints(
+            app_config,
+            &hinted_windows,
+            &[],
+            &mut text_measurer,
+            Some(((width / 2, height / 2), screen_rect)),
+        )?;
+        let canvas = cairo::ImageSurface::create(cairo::Format::ARgb32, width, height)
+            .context("Couldn't create Cairo Surface")?;
+        let render_windows = crate::draw_on_surface(app_config, &canvas, hint_boxes)?;
+
+        let mut keys = HintKeys::new(app_config);
+        let timeout = (app_config.timeout > 0).then(|| Duration::from_secs(app_config.timeout));
+        let mut last_activity = Instant::now();
+
+        let fade_in = Duration::from_millis(app_config.fade_in);
+        let fade_start = Instant::now();
+        let mut fade_step = (!fade_in.is_zero()).then_some(0);
+        let mut opacity = if fade_step.is_some() {
+            0.0
+        } else {
+            app_config.bg_color.3
+        };
+        let mut dirty = true;
+
+        loop {
+            if let Some(step) = fade_step {
+                let elapsed = fade_start.elapsed().as_secs_f64() / fade_in.as_secs_f64();
+                let current_step = ((elapsed * f64::from(FADE_STEPS)) as u32).min(FADE_STEPS);
+                if current_step > step {
+                    let progress = f64::from(current_step) / f64::from(FADE_STEPS);
+                    opacity = app_config.bg_color.3 * progress;
+                    dirty = true;
+                }
+                fade_step = (current_step < FADE_STEPS).then_some(current_step);
+            }
+            if dirty {
+                dirty = !overlay.present(
+                    app_config,
+                    &canvas,
+                    &render_windows,
+                    &keys.pressed_keys,
+                    opacity,
+                    &mut self.state.free_buffers,
+                )?;
+            }
+
+            while let Some(input) = self.state.input.pop_front() {
+                match input {
+                    Input::Key {
+                        keysym,
+                        pressed: false,
+                    } => keys.release(keysym)?,
+                    Input::Key {
+                        keysym,
+                        pressed: true,
+                    } => {
+                        last_activity = Instant::now();
+
+                        // Don't make the user wait for the fade to finish.
+                        if fade_step.take().is_some() {
+                            opacity = app_config.bg_color.3;
+                            dirty = true;
+                        }
+                        match keys.press(keysym, render_windows.keys())? {
+                            KeyPress::Cancel => return Ok(Some(Selection::Cancelled)),
+                            KeyPress::Select(hint) => {
+                                return Ok(Some(Selection::Window {
+                                    window: render_windows[&hint].desktop_window.clone(),
+                                    hint,
+                                }));
+                            }
+                            KeyPress::Update => {
+                                for (hint, rw) in &render_windows {
+                                    utils::draw_hint_text(rw, app_config, hint, &keys.pressed_keys)
+                                        .context("Couldn't draw hint text")?;
+                                }
+                                canvas.flush();
+                                dirty = true;
+                            }
+                            // There is no bell to ring on Wayland.
+                            KeyPress::Reject { close: true } => {
+                                return Ok(Some(Selection::Cancelled))
+                            }
+                            KeyPress::Reject { close: false } | KeyPress::Ignore => {}
+                        }
+                    }
+                    Input::Button => return Ok(Some(Selection::Cancelled)),
+                    Input::FocusLost if app_config.exit_on_focus_loss => {
+                        warn!("Lost keyboard focus, exiting");
+                        return Ok(Some(Selection::Cancelled));
+                    }
+                    Input::FocusLost => {}
+                }
+            }
+
+            // The output changed, the hints have to be laid out again.
+            if self.state.closed || self.state.configured != Some((width as u32, height as u32)) {
+                info!("Screen changed, showing hints again");
+                return Ok(None);
+            }
+
+            let mut wait = timeout.map(|timeout| timeout.saturating_sub(last_activity.elapsed()));
+            if wait.is_some_and(|wait| wait.is_zero()) {
+                info!(
+                    "No key pressed within {:?}, exiting",
+                    timeout.unwrap_or_default()
+                );
+                return Ok(Some(Selection::TimedOut));
+            }
+            // Fading in needs to wake up in between events to update the opacity.
+            if fade_step.is_some() {
+                wait = Some(wait.map_or(Duration::from_millis(10), |w| {
+                    w.min(Duration::from_millis(10))
+                }));
+            }
+            self.wait(wait)?;
+        }
+    }
+
+    fn update_monitors(&mut self, _app_config: &AppConfig) -> Result<()> {
+        Ok(())
+    }
+
+    fn perform_action(
+        &mut self,
+        app_config: &AppConfig,
+        desktop_window: &DesktopWindow,
+    ) -> Result<()> {
+        let toplevel = u32::try_from(desktop_window.id)
+            .ok()
+            .and_then(|id| self.state.toplevels.get(&id))
+            .context("Window is gone")?;
+        match app_config.action {
+            args::Action::Focus => toplevel.handle.activate(&self.seat),
+            args::Action::Close => toplevel.handle.close(),
+            args::Action::Kill => {
+                bail!("The Wayland backend can't kill clients, please use --action close instead")
+            }
+        }
+        self.queue.roundtrip(&mut self.state)?;
+        Ok(())
+    }
+
+    fn settle(&mut self) -> Result<()> {
+        self.queue.roundtrip(&mut self.state)?;
+        Ok(())
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        _: &mut Self,
+        _: &wl_registry::WlRegistry,
+        _: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<wl_seat::WlSeat, ()> for State {
+    fn event(
+        state: &mut Self,
+        seat: &wl_seat::WlSeat,
+        event: wl_seat::Event,
+        _: &(),
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        if let wl_seat::Event::Capabilities {
+            capabilities: WEnum::Value(capabilities),
+        } = event
+        {
+            if capabilities.contains(wl_seat::Capability::Keyboard) && state.keyboard.is_none() {
+                state.keyboard = Some(seat.get_keyboard(qh, ()));
+            }
+            if capabilities.contains(wl_seat::Capability::Pointer) && state.pointer.is_none() {
+                state.pointer = Some(seat.get_pointer(qh, ()));
+            }
+        }
+    }
+}
+
+impl Dispatch<wl_keyboard::WlKeyboard, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_keyboard::WlKeyboard,
+        event: wl_keyboard::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_keyboard::Event::Keymap {
+                format: WEnum::Value(wl_keyboard::KeymapFormat::XkbV1),
+                fd,
+                size,
+            } => match XkbKeymap::new(fd, size) {
+                Ok(keymap) => state.keymap = Some(keymap),
+                Err(e) => warn!("Couldn't load keymap: {:#}", e),
+            },
+            wl_keyboard::Event::Key {
+                key,
+                state: key_state,
+                ..
+            } => {
+                let Some(keymap) = &state.keymap else {
+                    warn!("Ignoring key {} without a keymap", key);
+                    return;
+                };
+                state.input.push_back(Input::Key {
+                    keysym: keymap.keysym(key),
+                    pressed: key_state == WEnum::Value(wl_keyboard::KeyState::Pressed),
+                });
+            }
+            wl_keyboard::Event::Modifiers {
+                mods_depressed,
+                mods_latched,
+                mods_locked,
+                group,
+                ..
+            } => {
+                if let Some(keymap) = &state.keymap {
+                    keymap.update_modifiers(mods_depressed, mods_latched, mods_locked, group);
+                }
+            }
+            wl_keyboard::Event::Leave { .. } => state.input.push_back(Input::FocusLost),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_pointer::WlPointer, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_pointer::WlPointer,
+        event: wl_pointer::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_pointer::Event::Button {
+            state: WEnum::Value(wl_pointer::ButtonState::Pressed),
+            ..
+        } = event
+        {
+            state.input.push_back(Input::Button);
+        }
+    }
+}
+
+impl Dispatch<layer_surface::ZwlrLayerSurfaceV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        layer: &layer_surface::ZwlrLayerSurfaceV1,
+        event: layer_surface::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        match event {
+            layer_surface::Event::Configure {
+                serial,
+                width,
+                height,
+            } => {
+                layer.ack_configure(serial);
+                state.configured = Some((width, height));
+            }
+            layer_surface::Event::Closed => state.closed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_buffer::WlBuffer, usize> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_buffer::WlBuffer,
+        event: wl_buffer::Event,
+        index: &usize,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let wl_buffer::Event::Release = event {
+            state.free_buffers[*index] = true;
+        }
+    }
+}
+
+impl Dispatch<toplevel_manager::ZwlrForeignToplevelManagerV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        _: &toplevel_manager::ZwlrForeignToplevelManagerV1,
+        event: toplevel_manager::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        if let toplevel_manager::Event::Toplevel { toplevel } = event {
+            state.toplevels.insert(
+                toplevel.id().protocol_id(),
+                Toplevel {
+                    handle: toplevel,
+                    title: None,
+                    app_id: None,
+                    activated: false,
+                    minimized: false,
+                    done: false,
+                },
+            );
+        }
+    }
+
+    event_created_child!(State, toplevel_manager::ZwlrForeignToplevelManagerV1, [
+        toplevel_manager::EVT_TOPLEVEL_OPCODE => (toplevel_handle::ZwlrForeignToplevelHandleV1, ()),
+    ]);
+}
+
+impl Dispatch<toplevel_handle::ZwlrForeignToplevelHandleV1, ()> for State {
+    fn event(
+        state: &mut Self,
+        handle: &toplevel_handle::ZwlrForeignToplevelHandleV1,
+        event: toplevel_handle::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let id = handle.id().protocol_id();
+        if let toplevel_handle::Event::Closed = event {
+            if let Some(toplevel) = state.toplevels.remove(&id) {
+                toplevel.handle.destroy();
+            }
+            return;
+        }
+        let Some(toplevel) = state.toplevels.get_mut(&id) else {
+            return;
+        };
+        match event {
+            toplevel_handle::Event::Title { title } => toplevel.title = Some(title),
+            toplevel_handle::Event::AppId { app_id } => toplevel.app_id = Some(app_id),
+            toplevel_handle::Event::State { state } => {
+                // The states are an array of native endian u32s.
+                let states: Vec<u32> = state
+                    .chunks_exact(4)
+                    .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+                    .collect();
+                toplevel.activated = states.contains(&(toplevel_handle::State::Activated as u32));
+                toplevel.minimized = states.contains(&(toplevel_handle::State::Minimized as u32));
+            }
+            toplevel_handle::Event::Done => toplevel.done = true,
+            _ => {}
+        }
+    }
+}
+
+delegate_noop!(State: wl_compositor::WlCompositor);
+delegate_noop!(State: ignore wl_surface::WlSurface);
+delegate_noop!(State: ignore wl_shm::WlShm);
+delegate_noop!(State: wl_shm_pool::WlShmPool);
+delegate_noop!(State: layer_shell::ZwlrLayerShellV1);
@@ -0,0 +1,131 @@
+//! Read the candidate windows from a plain text list instead of asking the window manager.
+//!
+//! Every non-empty line describes one window as `id x y width height [class] [title]`. The class
+//! is a single word while the title is the rest of the line. Lines starting with `#` are ignored.
+//!
+//! An id of the form `window/target` describes an area inside a window, eg a pane of a terminal
+//! that tiles its own splits. A window can be listed once per target and every target gets its
+//! own hint.
+
+use std::io::{self, Read};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::DesktopWindow;
+
+fn parse_field<T: std::str::FromStr>(value: Option<&str>, name: &str) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow!("Missing {}", name))?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("Invalid {} '{}'", name, value))
+}
+
+/// Parse an id which is either decimal or hexadecimal with a `0x` prefix.
+fn parse_id(value: Option<&str>) -> Result<i64> {
+    match value.and_then(|v| v.strip_prefix("0x")) {
+        Some(hex) => i64::from_str_radix(hex, 16).map_err(|_| anyhow!("Invalid id '0x{}'", hex)),
+        None => parse_field(value, "id"),
+    }
+}
+
+fn parse_line(line: &str) -> Result<DesktopWindow> {
+    let mut fields = line
+        .splitn(7, char::is_whitespace)
+        .filter(|f| !f.is_empty());
+    let id_field = fields.next();
+    let (id, target) = match id_field.and_then(|f| f.split_once('/')) {
+        Some((_, "")) => bail!("Missing target after '/'"),
+        Some((id, target)) => (parse_id(Some(id))?, Some(target.to_string())),
+        None => (parse_id(id_field)?, None),
+    };
+    let pos = (
+        parse_field(fields.next(), "x")?,
+        parse_field(fields.next(), "y")?,
+    );
+    let size = (
+        parse_field(fields.next(), "width")?,
+        parse_field(fields.next(), "height")?,
+    );
+    Ok(DesktopWindow {
+        id,
+        x_window_id: i32::try_from(id).ok(),
+        class: fields.next().map(String::from),
+        instance: None,
+        title: fields.next().map(|t| t.trim().to_string()),
+        target,
+        pos,
+        size,
+        is_focused: false,
+    })
+}
+
+/// Parse a list of windows.
+pub fn parse_windows(contents: &str) -> Result<Vec<DesktopWindow>> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| parse_line(line.trim()).with_context(|| format!("Line {}", i + 1)))
+        .collect()
+}
+
+/// Read a list of windows from stdin.
+pub fn get_windows() -> Result<Vec<DesktopWindow>> {
+    let mut contents = String::new();
+    io::stdin()
+        .read_to_string(&mut contents)
+        .context("Couldn't read windows from stdin")?;
+    parse_windows(&contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_windows() {
+        let windows = parse_windows(
+            "# id x y width height class title
+            0x1200003 0 0 800 600 Alacritty ~/src wmfocus
+            42 800 0 400 600
+
+            43 800 600 400 300 firefox",
+        )
+        .unwrap();
+        assert_eq!(windows.len(), 3);
+        assert_eq!(windows[0].id, 0x1200003);
+        assert_eq!(windows[0].x_window_id, Some(0x1200003));
+        assert_eq!(windows[0].class.as_deref(), Some("Alacritty"));
+        assert_eq!(windows[0].title.as_deref(), Some("~/src wmfocus"));
+        assert_eq!(windows[1].pos, (800, 0));
+        assert_eq!(windows[1].size, (400, 600));
+        assert_eq!(windows[1].class, None);
+        assert_eq!(windows[2].class.as_deref(), Some("firefox"));
+        assert_eq!(windows[2].title, None);
+        assert_eq!(windows[2].target, None);
+    }
+
+    #[test]
+    fn test_parse_window_targets() {
+        let windows = parse_windows(
+            "0x1200003/left 0 0 400 600 kitty vim
+            0x1200003/right 400 0 400 600 kitty htop",
+        )
+        .unwrap();
+        assert_eq!(windows[0].x_window_id, Some(0x1200003));
+        assert_eq!(windows[0].target.as_deref(), Some("left"));
+        assert_eq!(windows[1].x_window_id, Some(0x1200003));
+        assert_eq!(windows[1].target.as_deref(), Some("right"));
+        assert_eq!(windows[1].pos, (400, 0));
+        let err = parse_windows("0x1200003/ 0 0 400 600").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 1: Missing target"));
+    }
+
+    #[test]
+    fn test_parse_windows_errors() {
+        let err = parse_windows("1 0 0 800").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 1: Missing height"));
+        let err = parse_windows("1 0 0 800 600\nx 0 0 1 1").unwrap_err();
+        assert!(format!("{:#}", err).contains("Line 2: Invalid id 'x'"));
+    }
+}
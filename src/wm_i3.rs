@@ -1,8 +1,9 @@
-use anyhow::{Context, Result};
-use i3ipc::reply::{Node, NodeLayout, NodeType, Workspace};
+use i3ipc::reply::{Node, NodeLayout, NodeType, WindowProperty, Workspace};
 use i3ipc::I3Connection;
 use log::{debug, info};
 
+use crate::args::WorkspaceSelection;
+use crate::error::{Error, Result};
 use crate::DesktopWindow;
 
 /// Find first `Node` that fulfills a given criterion.
@@ -45,7 +46,7 @@ fn crawl_windows(root_node: &Node, workspace: &Workspace) -> Result<Vec<DesktopW
     let workspace_node = find_first_node_with_attr(root_node, |x| {
         x.name == Some(workspace.name.clone()) && x.nodetype == NodeType::Workspace
     })
-    .context("Couldn't find the Workspace node")?;
+    .ok_or_else(|| Error::MissingWorkspace(workspace.name.clone()))?;
 
     let mut nodes_to_explore: Vec<&Node> = workspace_node.nodes.iter().collect();
     nodes_to_explore.extend(workspace_node.floating_nodes.iter());
@@ -78,9 +79,19 @@ fn crawl_windows(root_node: &Node, workspace: &Workspace) -> Result<Vec<DesktopW
                     node.rect.1 + node.deco_rect.3
                 };
 
+                let window_property = |property| {
+                    node.window_properties
+                        .as_ref()
+                        .and_then(|properties| properties.get(&property).cloned())
+                };
+
                 let window = DesktopWindow {
                     id: node.id,
                     x_window_id: node.window,
+                    class: window_property(WindowProperty::Class),
+                    instance: window_property(WindowProperty::Instance),
+                    title: window_property(WindowProperty::Title).or_else(|| node.name.clone()),
+                    target: None,
                     pos: (pos_x, pos_y),
                     size: (size_x, (node.rect.3 + node.deco_rect.3)),
                     is_focused: node.focused,
@@ -94,18 +105,19 @@ fn crawl_windows(root_node: &Node, workspace: &Workspace) -> Result<Vec<DesktopW
     Ok(windows)
 }
 
-/// Return a list of all windows.
-pub fn get_windows() -> Result<Vec<DesktopWindow>> {
+/// Return a list of all windows on the visible workspaces or on all of them, depending on
+/// `workspace_selection`.
+pub fn get_windows(workspace_selection: WorkspaceSelection) -> Result<Vec<DesktopWindow>> {
     // Establish a connection to i3 over a unix socket
-    let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
-    let workspaces = connection
-        .get_workspaces()
-        .context("Problem communicating with i3")?
-        .workspaces;
-    let visible_workspaces = workspaces.iter().filter(|w| w.visible);
+    let mut connection = I3Connection::connect()?;
+    let workspaces = connection.get_workspaces()?.workspaces;
+    let selected_workspaces = workspaces.iter().filter(|w| match workspace_selection {
+        WorkspaceSelection::Current => w.visible,
+        WorkspaceSelection::All => true,
+    });
     let root_node = connection.get_tree()?;
     let mut windows = vec![];
-    for workspace in visible_workspaces {
+    for workspace in selected_workspaces {
         windows.extend(crawl_windows(&root_node, workspace)?);
     }
     Ok(windows)
@@ -113,11 +125,9 @@ pub fn get_windows() -> Result<Vec<DesktopWindow>> {
 
 /// Focus a specific `window`.
 pub fn focus_window(window: &DesktopWindow) -> Result<()> {
-    let mut connection = I3Connection::connect().context("Couldn't acquire i3 connection")?;
+    let mut connection = I3Connection::connect()?;
     let command_str = format!("[con_id=\"{}\"] focus", window.id);
-    let command = connection
-        .run_command(&command_str)
-        .context("Couldn't communicate with i3")?;
+    let command = connection.run_command(&command_str)?;
     info!("Sending to i3: {:?}", command);
     Ok(())
 }